@@ -10,19 +10,144 @@ mod paged_vector;
 #[path = "../src/bit_array.rs"] 
 mod bit_array;
 
+#[path = "../src/bitmap.rs"]
+mod bitmap;
+
 #[path = "../src/database.rs"] 
 mod database;
 
+#[path = "../src/hash_index.rs"]
+mod hash_index;
+
+#[path = "../src/dictionary.rs"]
+mod dictionary;
+
 
 fn bench_bit_array(c: &mut Criterion) {
     let mut pp = bit_array::BitArray::new(40, 11);
-    c.bench_function("put BA", move |b| b.iter(|| pp.put(black_box(20u32), 7)));
+    c.bench_function("put BA", move |b| b.iter(|| pp.put(black_box(20usize), 7)));
 
     let pp = bit_array::BitArray::new(40, 11);
-    c.bench_function("get BA", move |b| b.iter(|| pp.get(black_box(20u32))));
+    c.bench_function("get BA", move |b| b.iter(|| pp.get(black_box(20usize))));
+}
+
+fn bench_bit_array_bulk(c: &mut Criterion) {
+    let entries = 65536;
+    let values : Vec<u32> = (0..entries as u32).map(|i| i & 0x7ff).collect();
+
+    let mut pp = bit_array::BitArray::new(entries, 11);
+    pp.pack_from(0, &values);
+    c.bench_function("get loop BA", move |b| b.iter(|| {
+        let mut sum = 0u64;
+        for i in 0..entries {
+            sum += pp.get(black_box(i));
+        }
+        sum
+    }));
+
+    let mut pp = bit_array::BitArray::new(entries, 11);
+    pp.pack_from(0, &values);
+    let mut out = vec![0u32; entries];
+    c.bench_function("unpack BA", move |b| b.iter(|| {
+        pp.unpack_into(black_box(0), &mut out);
+        out[entries - 1]
+    }));
+
+    let mut pp = bit_array::BitArray::new(entries, 11);
+    let put_values = values.clone();
+    c.bench_function("put loop BA", move |b| b.iter(|| {
+        for (i, &v) in put_values.iter().enumerate() {
+            pp.put(black_box(i), v as u64);
+        }
+    }));
+
+    let mut pp = bit_array::BitArray::new(entries, 11);
+    c.bench_function("pack BA", move |b| b.iter(|| pp.pack_from(black_box(0), &values)));
 }
 
+fn bench_bit_array_select(c: &mut Criterion) {
+    let entries = 65536;
+    let values : Vec<u32> = (0..entries as u32).map(|i| i.wrapping_mul(2654435761) >> 22).collect();
+
+    let mut pp = bit_array::BitArray::new(entries, 10);
+    pp.pack_from(0, &values);
+    c.bench_function("filter loop BA", move |b| b.iter(|| {
+        let mut selected = bitmap::Bitmap::new(entries);
+        for i in 0..entries {
+            let v = pp.get(i);
+            if v >= black_box(100) && v <= black_box(200) {
+                selected.set(i);
+            }
+        }
+        selected
+    }));
+
+    let mut pp = bit_array::BitArray::new(entries, 10);
+    pp.pack_from(0, &values);
+    c.bench_function("select_between BA", move |b| b.iter(|| pp.select_between(black_box(100), black_box(200))));
+}
+
+fn bench_paged_vector_load(c: &mut Criterion) {
+    use database::MemoryPageProvider;
+    use paged_vector::{PagedVector, PagedVectorFns};
+
+    let values : Vec<u32> = (0..1000000).collect();
+    let chunks = values.clone();
+
+    c.bench_function("append PV", move |b| b.iter(|| {
+        let mut pp = MemoryPageProvider::new();
+        let mut p = PagedVector::<u32>::bulk_load(&mut pp, &[]);
+        for chunk in chunks.chunks(1000) {
+            p.append(black_box(chunk));
+        }
+        p.len()
+    }));
+
+    c.bench_function("bulk_load PV", move |b| b.iter(|| {
+        let mut pp = MemoryPageProvider::new();
+        let p = PagedVector::<u32>::bulk_load(&mut pp, black_box(&values));
+        p.len()
+    }));
+}
+
+fn bench_dictionary_filters(c: &mut Criterion) {
+    use database::MemoryPageProvider;
+    use dictionary::{ArrayDictionary, Dictionary, DictionaryOptions};
+
+    let values : Vec<Vec<u8>> = (0..5000).map(|i| format!("value {}", i * 31).into_bytes()).collect();
+    let misses : Vec<Vec<u8>> = (0..100).map(|i| format!("missing {}", i).into_bytes()).collect();
+
+    for &filter_ids in [0u32, 8, 16, 64, 340].iter() {
+        let mut pp = MemoryPageProvider::new();
+        let header = {
+            let mut d = ArrayDictionary::<u8>::with_options(&mut pp, DictionaryOptions { index: false, filter_ids });
+            for v in values.iter() {
+                d.add(v);
+            }
+            d.header_page()
+        };
+
+        let misses = misses.clone();
+        c.bench_function(&format!("absent lookup filter {}", filter_ids), move |b| b.iter(|| {
+            let d = ArrayDictionary::<u8>::open(&mut pp, header);
+            misses.iter().filter(|v| d.lookup(black_box(v)).is_some()).count()
+        }));
+    }
+
+    let mut pp = MemoryPageProvider::new();
+    let header = {
+        let mut d = ArrayDictionary::<u8>::new(&mut pp);
+        for v in values.iter() {
+            d.add(v);
+        }
+        d.header_page()
+    };
+    c.bench_function("absent lookup index", move |b| b.iter(|| {
+        let d = ArrayDictionary::<u8>::open(&mut pp, header);
+        misses.iter().filter(|v| d.lookup(black_box(v)).is_some()).count()
+    }));
+}
 
-criterion_group!(benches, bench_bit_array);
+criterion_group!(benches, bench_bit_array, bench_bit_array_bulk, bench_bit_array_select, bench_paged_vector_load, bench_dictionary_filters);
 
 criterion_main!(benches);
\ No newline at end of file
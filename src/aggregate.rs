@@ -0,0 +1,279 @@
+// Aggregates over a table scan, grouped by zero or more columns
+// Grouping on a single Ids or String column indexes groups by dictionary ID in a dense array,
+// so rows never hash a key. Any other grouping hashes the row's key, Int values and IDs
+// NULL is a group of its own, and is skipped by every aggregate but Count
+// Groups come back in the order their first row was scanned, whichever way they were found
+
+#![allow(dead_code)]
+
+use crate::catalog::{Catalog, CatalogError, ColumnType, NULL_ID, NULL_INT};
+use crate::scan::{self, BatchColumn, Predicate, Value};
+use fnv::{FnvHashMap};
+use std::convert::TryFrom;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Aggregate {
+  Count,           // Rows
+  CountOf(String), // Rows where the column isn't NULL
+  Sum(String),
+  Min(String),
+  Max(String),
+  Avg(String),
+}
+
+impl Aggregate {
+  fn column(&self) -> Option<&str> {
+    match self {
+      Aggregate::Count => None,
+      Aggregate::CountOf(c) | Aggregate::Sum(c) | Aggregate::Min(c) | Aggregate::Max(c) | Aggregate::Avg(c) => Some(c),
+    }
+  }
+}
+
+// key holds the group's value of each group by column, values each aggregate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+  pub key: Vec<Value>,
+  pub values: Vec<Value>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Acc {
+  count: u64,
+  sum: i128,
+  min: i64,
+  max: i64,
+}
+
+impl Acc {
+  const EMPTY: Acc = Acc { count: 0, sum: 0, min: i64::MAX, max: i64::MIN };
+
+  fn add(&mut self, v: i64) {
+    self.count += 1;
+    self.sum += v as i128;
+    self.min = std::cmp::min(self.min, v);
+    self.max = std::cmp::max(self.max, v);
+  }
+
+  fn value(&self, a: &Aggregate) -> Value {
+    match a {
+      Aggregate::Count | Aggregate::CountOf(_) => Value::Int(self.count as i64),
+      _ if self.count == 0 => Value::Null,
+      Aggregate::Sum(_) => match i64::try_from(self.sum) {
+        Ok(sum) => Value::Int(sum),
+        Err(_) => Value::Float(self.sum as f64),
+      },
+      Aggregate::Min(_) => Value::Int(self.min),
+      Aggregate::Max(_) => Value::Int(self.max),
+      Aggregate::Avg(_) => Value::Float(self.sum as f64 / self.count as f64),
+    }
+  }
+}
+
+// Where each group's accumulators are, groups holds them in order of first appearance
+enum Index {
+  Single,
+  Dense(Vec<u32>), // By ID + 1 so NULL_ID is 0, u32::MAX for no group yet
+  Hash(FnvHashMap<Vec<i64>, u32>),
+}
+
+const NO_GROUP: u32 = u32::MAX;
+
+fn group_key(columns: &[BatchColumn], i: usize) -> Vec<i64> {
+  columns.iter().map(|c| match c {
+    BatchColumn::Int(vs) => vs[i],
+    BatchColumn::Ids(ids) => ids[i] as i64,
+  }).collect()
+}
+
+pub fn aggregate(catalog: &mut Catalog, table: &str, group_by: &[&str], aggregates: &[Aggregate], predicate: &Predicate) -> Result<Vec<Group>, CatalogError> {
+  aggregate_with(catalog, table, group_by, aggregates, predicate, true)
+}
+
+fn aggregate_with(catalog: &mut Catalog, table: &str, group_by: &[&str], aggregates: &[Aggregate], predicate: &Predicate, dense: bool) -> Result<Vec<Group>, CatalogError> {
+  let column = |catalog: &Catalog, name: &str| {
+    let id = catalog.find_column(table, name).ok_or_else(|| CatalogError::NotFound(format!("{}.{}", table, name)))?;
+    catalog.column(id)
+  };
+  let keys = group_by.iter().map(|c| column(catalog, c)).collect::<Result<Vec<_>, _>>()?;
+  for a in aggregates.iter() {
+    match (a, a.column()) {
+      (Aggregate::Count, _) | (Aggregate::CountOf(_), _) => {},
+      (_, Some(name)) if column(catalog, name)?.column_type != ColumnType::Int => {
+        return Err(CatalogError::WrongType(format!("{}.{}", table, name)));
+      }
+      _ => {},
+    }
+  }
+
+  let mut index = match &keys[..] {
+    [] => Index::Single,
+    [k] if dense && k.column_type != ColumnType::Int => {
+      let size = catalog.dictionary(k.dictionary.unwrap())?.len();
+      Index::Dense(vec![NO_GROUP; size + 1])
+    }
+    _ => Index::Hash(FnvHashMap::default()),
+  };
+  // Aggregates of group g are accs[g * aggregates.len()..]
+  let mut firsts : Vec<Vec<i64>> = Vec::new();
+  let mut accs : Vec<Acc> = Vec::new();
+  if let Index::Single = index {
+    firsts.push(Vec::new());
+    accs.extend(aggregates.iter().map(|_| Acc::EMPTY));
+  }
+
+  let mut columns : Vec<&str> = group_by.to_vec();
+  columns.extend(aggregates.iter().filter_map(|a| a.column()));
+  for batch in scan::scan(catalog, table, &columns, predicate)? {
+    let (key_columns, value_columns) = batch.columns.split_at(group_by.len());
+    for i in 0..batch.rows.len() {
+      let group = match &mut index {
+        Index::Single => 0,
+        Index::Dense(slots) => {
+          let id = match &key_columns[0] {
+            BatchColumn::Ids(ids) => ids[i],
+            BatchColumn::Int(_) => unreachable!(),
+          };
+          let slot = &mut slots[id.wrapping_add(1) as usize];
+          if *slot == NO_GROUP {
+            *slot = firsts.len() as u32;
+            firsts.push(vec![id as i64]);
+            accs.extend(aggregates.iter().map(|_| Acc::EMPTY));
+          }
+          *slot
+        }
+        Index::Hash(map) => {
+          let key = group_key(key_columns, i);
+          *map.entry(key.clone()).or_insert_with(|| {
+            firsts.push(key);
+            accs.extend(aggregates.iter().map(|_| Acc::EMPTY));
+            firsts.len() as u32 - 1
+          })
+        }
+      } as usize;
+
+      let mut values = value_columns.iter();
+      for (a, acc) in aggregates.iter().zip(accs[group * aggregates.len()..].iter_mut()) {
+        match (a, a.column().map(|_| values.next().unwrap())) {
+          (_, None) => acc.count += 1,
+          (_, Some(BatchColumn::Ids(ids))) => if ids[i] != NULL_ID { acc.count += 1 },
+          (_, Some(BatchColumn::Int(vs))) => if vs[i] != NULL_INT { acc.add(vs[i]) },
+        }
+      }
+    }
+  }
+
+  // Keys are decoded once per group rather than per row
+  let mut groups = Vec::with_capacity(firsts.len());
+  for (g, first) in firsts.iter().enumerate() {
+    let mut key = Vec::with_capacity(keys.len());
+    for (k, &v) in keys.iter().zip(first.iter()) {
+      key.push(match k.column_type {
+        ColumnType::Int if v == NULL_INT => Value::Null,
+        ColumnType::Int => Value::Int(v),
+        _ if v as u32 == NULL_ID => Value::Null,
+        _ => {
+          let d = catalog.dictionary(k.dictionary.unwrap())?;
+          Value::Str(String::from_utf8_lossy(&d.get(v as u32)).into_owned())
+        }
+      });
+    }
+    let values = aggregates.iter().zip(accs[g * aggregates.len()..].iter()).map(|(a, acc)| acc.value(a)).collect();
+    groups.push(Group { key, values });
+  }
+  Ok(groups)
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use crate::scan::tests::{events, setup, user_name, Event};
+  #[allow(unused_imports)]
+  use std::collections::HashMap;
+
+  fn all() -> Vec<Aggregate> {
+    let ts = || "ts".to_string();
+    vec![Aggregate::Count, Aggregate::CountOf(ts()), Aggregate::Sum(ts()), Aggregate::Min(ts()), Aggregate::Max(ts()), Aggregate::Avg(ts())]
+  }
+
+  // Count, count, sum, min, max and avg of non NULL timestamps
+  fn expected(events: &[&Event]) -> Vec<Value> {
+    let ts : Vec<i64> = events.iter().map(|e| e.ts).filter(|&t| t != NULL_INT).collect();
+    let sum : i64 = ts.iter().sum();
+    vec![
+      Value::Int(events.len() as i64),
+      Value::Int(ts.len() as i64),
+      Value::Int(sum),
+      Value::Int(*ts.iter().min().unwrap()),
+      Value::Int(*ts.iter().max().unwrap()),
+      Value::Float(sum as f64 / ts.len() as f64),
+    ]
+  }
+
+  #[test]
+  pub fn totals() {
+    let mut pp = MemoryPageProvider::new();
+    let events = events();
+    let header = setup(&mut pp, &events);
+    let mut c = Catalog::open(&mut pp, header);
+
+    let groups = aggregate(&mut c, "events", &[], &all(), &Predicate::all()).unwrap();
+    assert!(groups.len() == 1 && groups[0].key.is_empty());
+    assert!(groups[0].values == expected(&events.iter().collect::<Vec<_>>()));
+
+    // No rows is still one group, with NULLs for anything but counts
+    let none = Predicate::Lt("ts".into(), Value::Int(0));
+    let groups = aggregate(&mut c, "events", &[], &all(), &none).unwrap();
+    assert!(groups[0].values == vec![Value::Int(0), Value::Int(0), Value::Null, Value::Null, Value::Null, Value::Null]);
+    assert!(aggregate(&mut c, "events", &["kind"], &all(), &none).unwrap().is_empty());
+
+    assert!(matches!(aggregate(&mut c, "events", &[], &[Aggregate::Sum("kind".into())], &Predicate::all()), Err(CatalogError::WrongType(_))));
+    assert!(matches!(aggregate(&mut c, "events", &["nope"], &[], &Predicate::all()), Err(CatalogError::NotFound(_))));
+  }
+
+  #[test]
+  pub fn group_by() {
+    let mut pp = MemoryPageProvider::new();
+    let events = events();
+    let header = setup(&mut pp, &events);
+    let mut c = Catalog::open(&mut pp, header);
+    let late = Predicate::Ge("ts".into(), Value::Int(1_300_000));
+
+    // Dense by dictionary ID, NULL kinds are a group
+    let groups = aggregate(&mut c, "events", &["kind"], &all(), &late).unwrap();
+    let mut by_kind : HashMap<Option<String>, Vec<&Event>> = HashMap::new();
+    events.iter().filter(|e| e.ts >= 1_300_000).for_each(|e| by_kind.entry(e.kind.clone()).or_default().push(e));
+    assert!(groups.len() == by_kind.len() && groups.len() == 41);
+    for g in groups.iter() {
+      let kind = match &g.key[0] {
+        Value::Str(s) => Some(s.clone()),
+        _ => None,
+      };
+      assert!(g.values == expected(&by_kind[&kind]), "{:?}", kind);
+    }
+    // The same groups in the same order when hashed
+    assert!(aggregate_with(&mut c, "events", &["kind"], &all(), &late, false).unwrap() == groups);
+
+    // Hashed on an Ids and an Int column, NULL timestamps are a group per user
+    let early = Predicate::Or(vec![Predicate::IsNull("ts".into()), Predicate::Lt("ts".into(), Value::Int(1_001_200))]);
+    let groups = aggregate(&mut c, "events", &["user", "ts"], &[Aggregate::Count, Aggregate::CountOf("ts".into())], &early).unwrap();
+    let mut by_key : HashMap<(String, Option<i64>), Vec<&Event>> = HashMap::new();
+    events.iter().filter(|e| e.ts == NULL_INT || e.ts < 1_001_200)
+      .for_each(|e| by_key.entry((user_name(e.user), if e.ts == NULL_INT { None } else { Some(e.ts) })).or_default().push(e));
+    assert!(groups.len() == by_key.len() && groups.len() == 19 + 12);
+    assert!(groups[0].key == vec![Value::Str("user0".into()), Value::Int(events[0].ts)]);
+    for g in groups.iter() {
+      let key = match (&g.key[0], &g.key[1]) {
+        (Value::Str(user), Value::Int(ts)) => (user.clone(), Some(*ts)),
+        (Value::Str(user), Value::Null) => (user.clone(), None),
+        _ => panic!("Unexpected key {:?}", g.key),
+      };
+      let rows = by_key[&key].len() as i64;
+      assert!(g.values == vec![Value::Int(rows), Value::Int(if key.1.is_some() { rows } else { 0 })]);
+    }
+  }
+}
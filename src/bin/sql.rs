@@ -0,0 +1,68 @@
+// Interactive SQL over an in memory catalog, see sql.rs for the dialect
+// Statements end with ; and can span lines, .quit or end of input leaves
+
+use journal::catalog::Catalog;
+use journal::database::MemoryPageProvider;
+use journal::scan::Value;
+use journal::sql::{self, Output};
+use std::io::{self, BufRead, Write};
+
+fn text(v: &Value) -> String {
+  match v {
+    Value::Null => "NULL".to_string(),
+    Value::Int(v) => v.to_string(),
+    Value::Float(v) => v.to_string(),
+    Value::Str(s) => s.clone(),
+  }
+}
+
+fn print(output: &Output) {
+  match output {
+    Output::Done => println!("OK"),
+    Output::Changed(n) => println!("{} row{}", n, if *n == 1 { "" } else { "s" }),
+    Output::Rows { names, batches } => {
+      let rows : Vec<Vec<String>> = batches.iter()
+        .flat_map(|b| (0..b.rows()).map(move |i| b.columns.iter().map(|c| text(&c.value(i))).collect()))
+        .collect();
+      let widths : Vec<usize> = names.iter().enumerate()
+        .map(|(i, n)| rows.iter().map(|r| r[i].chars().count()).chain(std::iter::once(n.chars().count())).max().unwrap())
+        .collect();
+      let line = |cells: &[String]| {
+        let cells : Vec<String> = cells.iter().zip(widths.iter()).map(|(c, &w)| format!("{:w$}", c, w = w)).collect();
+        println!("{}", cells.join(" | ").trim_end());
+      };
+      line(names);
+      println!("{}", widths.iter().map(|&w| "-".repeat(w)).collect::<Vec<_>>().join("-+-"));
+      rows.iter().for_each(|r| line(r));
+      println!("({} row{})", rows.len(), if rows.len() == 1 { "" } else { "s" });
+    }
+  }
+}
+
+fn main() {
+  let mut pp = MemoryPageProvider::new();
+  let mut catalog = Catalog::new(&mut pp);
+  let stdin = io::stdin();
+  let mut statement = String::new();
+  loop {
+    print!("{}", if statement.is_empty() { "sql> " } else { "...> " });
+    io::stdout().flush().unwrap();
+    let mut line = String::new();
+    if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 || (statement.is_empty() && line.trim() == ".quit") {
+      println!();
+      break;
+    }
+    statement.push_str(&line);
+    if !statement.trim_end().ends_with(';') {
+      if statement.trim().is_empty() {
+        statement.clear();
+      }
+      continue;
+    }
+    match sql::execute(&mut catalog, &statement) {
+      Ok(output) => print(&output),
+      Err(e) => println!("Error: {:?}", e),
+    }
+    statement.clear();
+  }
+}
@@ -1,8 +1,9 @@
-
 #![allow(dead_code)]
 
 use std::alloc::{GlobalAlloc, Layout, System};
 
+use crate::bitmap::{Bitmap};
+
 struct CacheAlignedMem {
   ptr : *mut u8,
   size : usize,
@@ -11,56 +12,490 @@ struct CacheAlignedMem {
 impl CacheAlignedMem {
   fn new(size: usize) -> CacheAlignedMem {
     CacheAlignedMem {
-      size: size,
+      size,
       ptr : unsafe { System.alloc_zeroed(Layout::from_size_align(size, 64).unwrap()) },
     }
   }
+
+  // Zero filled copy, truncated or extended to size
+  fn resized(&self, size: usize) -> CacheAlignedMem {
+    let mem = CacheAlignedMem::new(size);
+    unsafe { std::ptr::copy_nonoverlapping(self.ptr, mem.ptr, std::cmp::min(self.size, size)); }
+    mem
+  }
+}
+
+impl Clone for CacheAlignedMem {
+  fn clone(&self) -> CacheAlignedMem {
+    self.resized(self.size)
+  }
 }
 
 impl Drop for CacheAlignedMem {
-  fn drop(&mut self) -> () {
-    println!("Dropped");
+  fn drop(&mut self) {
     unsafe { System.dealloc(self.ptr, Layout::from_size_align(self.size, 64).unwrap()); }
   }
 }
 
 
+#[derive(Debug, PartialEq)]
+pub enum BitArrayError {
+  Overflow { value: u64, word_size: u32 },
+  OutOfBounds { index: usize, len: usize },
+}
+
+// Widest word that always fits in a single unaligned 64 bit read, wider words read 128 bits
+const MAX_U64_READ: u32 = 57;
+
+fn mask(word_size: u32) -> u64 {
+  if word_size == 64 { !0 } else { (1u64 << word_size) - 1 }
+}
+
+// 15 additional bytes to cope with the 128 bit read in get/put
+fn num_bytes(size: usize, word_size: u32) -> usize {
+  ((size * word_size as usize + 7) >> 3) + 15
+}
+
 // Align it to a cache line
+#[derive(Clone)]
 pub struct BitArray {
   bytes: CacheAlignedMem,
   mask: u64,
   word_size: u32,
+  size: usize,
+  capacity: usize,
+  growable: bool,
 }
 
 impl BitArray {
-  pub fn new(size: u32, word_size: u32) -> BitArray {
-    // 7 additional bytes to cope with the 64 bit read in get/put
-    let num_bytes = (((size * word_size + 7) >> 3) + 7) as usize;
+  pub fn new(size: usize, word_size: u32) -> BitArray {
+    assert!((1..=64).contains(&word_size), "BitArray word size must be 1 to 64 bits, not {}", word_size);
     BitArray {
-      bytes: CacheAlignedMem::new(num_bytes),
-      word_size: word_size,
-      mask: (1u64 << word_size) - 1,
+      bytes: CacheAlignedMem::new(num_bytes(size, word_size)),
+      word_size,
+      mask: mask(word_size),
+      size,
+      capacity: size,
+      growable: false,
     }
   }
 
-  pub fn get(&self, index: u32) -> u32 {
-    let bit_offset = index * self.word_size;
+  // put re-encodes the array at a larger width rather than masking values that don't fit
+  pub fn growable(size: usize, word_size: u32) -> BitArray {
+    let mut ba = BitArray::new(size, word_size);
+    ba.growable = true;
+    ba
+  }
+
+  pub fn word_size(&self) -> u32 {
+    self.word_size
+  }
+
+  pub fn len(&self) -> usize {
+    self.size
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.size == 0
+  }
+
+  pub fn fits(&self, value: u64) -> bool {
+    value & !self.mask == 0
+  }
+
+  pub fn get(&self, index: usize) -> u64 {
+    assert!(index < self.size, "Index {} out of bounds for BitArray of length {}", index, self.size);
+    unsafe { self.get_unchecked(index) }
+  }
+
+  pub fn try_get(&self, index: usize) -> Option<u64> {
+    if index < self.size {
+      Some(unsafe { self.get_unchecked(index) })
+    } else {
+      None
+    }
+  }
+
+  /// # Safety
+  /// index must be less than len()
+  pub unsafe fn get_unchecked(&self, index: usize) -> u64 {
+    let bit_offset = index * self.word_size as usize;
     let byte_offset = bit_offset >> 3;
     let shift = bit_offset & 7;
     // unaligned read from byte offset
-    let ptr = unsafe { self.bytes.ptr.offset(byte_offset as isize) as *const u64 };
-    let dword: u64 = unsafe { *ptr };
-    ((dword >> shift) & self.mask) as u32
+    let ptr = self.bytes.ptr.add(byte_offset);
+    if self.word_size <= MAX_U64_READ {
+      let dword: u64 = (ptr as *const u64).read_unaligned();
+      (dword >> shift) & self.mask
+    } else {
+      let qword: u128 = (ptr as *const u128).read_unaligned();
+      ((qword >> shift) as u64) & self.mask
+    }
+  }
+
+  // Values wider than word_size are masked unless the array is growable
+  pub fn put(&mut self, index: usize, value: u64) {
+    assert!(index < self.size, "Index {} out of bounds for BitArray of length {}", index, self.size);
+    if self.growable && !self.fits(value) {
+      self.widen(64 - value.leading_zeros());
+    }
+    unsafe { self.put_unchecked(index, value) }
+  }
+
+  // Never widens, fails rather than storing a truncated value
+  pub fn try_put(&mut self, index: usize, value: u64) -> Result<(), BitArrayError> {
+    if index >= self.size {
+      return Err(BitArrayError::OutOfBounds { index, len: self.size });
+    }
+    if !self.fits(value) {
+      return Err(BitArrayError::Overflow { value, word_size: self.word_size });
+    }
+    unsafe { self.put_unchecked(index, value) };
+    Ok(())
   }
 
-  pub fn put(&mut self, index: u32, value: u32) -> () {
-    let bit_offset = index * self.word_size;
+  /// # Safety
+  /// index must be less than len(), value is masked to word_size
+  pub unsafe fn put_unchecked(&mut self, index: usize, value: u64) {
+    let bit_offset = index * self.word_size as usize;
     let byte_offset = bit_offset >> 3;
     let shift = bit_offset & 7;
-    let ptr = unsafe { self.bytes.ptr.offset(byte_offset as isize) as *mut u64 };
-    let dword: u64 = unsafe { *ptr };
-    let updated = dword & !(self.mask << shift) | ((value as u64) << shift);
-    unsafe { *ptr = updated }
+    let ptr = self.bytes.ptr.add(byte_offset);
+    if self.word_size <= MAX_U64_READ {
+      let ptr = ptr as *mut u64;
+      let dword: u64 = ptr.read_unaligned();
+      let updated = dword & !(self.mask << shift) | ((value & self.mask) << shift);
+      ptr.write_unaligned(updated)
+    } else {
+      let ptr = ptr as *mut u128;
+      let qword: u128 = ptr.read_unaligned();
+      let mask = self.mask as u128;
+      let updated = qword & !(mask << shift) | (((value as u128) & mask) << shift);
+      ptr.write_unaligned(updated)
+    }
+  }
+
+  pub fn push(&mut self, value: u64) {
+    if self.size == self.capacity {
+      self.reserve(std::cmp::max(self.capacity, 16));
+    }
+    self.size += 1;
+    self.put(self.size - 1, value)
+  }
+
+  // New entries are 0
+  pub fn resize(&mut self, size: usize) {
+    if size > self.capacity {
+      self.reserve(size - self.size);
+    } else if size < self.size {
+      // Clear the truncated tail so a later grow reads zeros
+      for i in size..self.size {
+        unsafe { self.put_unchecked(i, 0) }
+      }
+    }
+    self.size = size;
+  }
+
+  fn reserve(&mut self, additional: usize) {
+    let capacity = self.size + additional;
+    if capacity > self.capacity {
+      self.bytes = self.bytes.resized(num_bytes(capacity, self.word_size));
+      self.capacity = capacity;
+    }
+  }
+
+  // Re-encode every entry at the new width, no-op if not wider than the current one
+  pub fn widen(&mut self, word_size: u32) {
+    if word_size <= self.word_size {
+      return;
+    }
+    let mut wider = BitArray::new(self.capacity, word_size);
+    for i in 0..self.size {
+      unsafe { wider.put_unchecked(i, self.get_unchecked(i)) }
+    }
+    self.bytes = wider.bytes;
+    self.mask = wider.mask;
+    self.word_size = word_size;
+  }
+
+  // Decode out.len() entries from start, word_size must be at most 32
+  // Runs of 8 entries start on a byte boundary so they are decoded a group at a time
+  pub fn unpack_into(&self, start: usize, out: &mut [u32]) {
+    assert!(self.word_size <= 32, "Can't unpack {} bit words into u32s", self.word_size);
+    assert!(start + out.len() <= self.size, "Range {}..{} out of bounds for BitArray of length {}", start, start + out.len(), self.size);
+
+    let head = std::cmp::min(out.len(), (GROUP - start % GROUP) % GROUP);
+    let body = (out.len() - head) / GROUP * GROUP;
+    let (head_out, rest) = out.split_at_mut(head);
+    let (body_out, tail_out) = rest.split_at_mut(body);
+
+    for (i, v) in head_out.iter_mut().enumerate() {
+      *v = unsafe { self.get_unchecked(start + i) } as u32;
+    }
+    unsafe {
+      let base = self.bytes.ptr.add(((start + head) * self.word_size as usize) >> 3);
+      unpack_groups(base, self.word_size, body_out);
+    }
+    let tail_start = start + head + body;
+    for (i, v) in tail_out.iter_mut().enumerate() {
+      *v = unsafe { self.get_unchecked(tail_start + i) } as u32;
+    }
+  }
+
+  // Encode values from start, growable arrays widen to fit the largest value, otherwise values are masked
+  pub fn pack_from(&mut self, start: usize, values: &[u32]) {
+    assert!(start + values.len() <= self.size, "Range {}..{} out of bounds for BitArray of length {}", start, start + values.len(), self.size);
+    if self.growable {
+      let max = values.iter().fold(0, |acc, &v| acc | v) as u64;
+      if !self.fits(max) {
+        self.widen(64 - max.leading_zeros());
+      }
+    }
+    if self.word_size > 32 {
+      // Group packing accumulates in 64 bits, rare enough to not need its own path
+      for (i, &v) in values.iter().enumerate() {
+        unsafe { self.put_unchecked(start + i, v as u64) }
+      }
+      return;
+    }
+
+    let head = std::cmp::min(values.len(), (GROUP - start % GROUP) % GROUP);
+    let body = (values.len() - head) / GROUP * GROUP;
+    let (head_values, rest) = values.split_at(head);
+    let (body_values, tail_values) = rest.split_at(body);
+
+    for (i, &v) in head_values.iter().enumerate() {
+      unsafe { self.put_unchecked(start + i, v as u64) }
+    }
+    unsafe {
+      let base = self.bytes.ptr.add(((start + head) * self.word_size as usize) >> 3);
+      pack_groups(base, self.word_size, self.mask, body_values);
+    }
+    let tail_start = start + head + body;
+    for (i, &v) in tail_values.iter().enumerate() {
+      unsafe { self.put_unchecked(tail_start + i, v as u64) }
+    }
+  }
+
+  // Rows equal to value
+  pub fn select_eq(&self, value: u64) -> Bitmap {
+    if !self.fits(value) {
+      return Bitmap::new(self.size);
+    }
+    let lanes = Lanes::new(self.word_size);
+    let pattern = lanes.replicate(value);
+    self.select(&lanes, |v| v == value, |x| lanes.eq(x, pattern))
+  }
+
+  // Rows in lo..=hi
+  pub fn select_between(&self, lo: u64, hi: u64) -> Bitmap {
+    let hi = std::cmp::min(hi, self.mask);
+    if lo > hi {
+      return Bitmap::new(self.size);
+    }
+    let lanes = Lanes::new(self.word_size);
+    let (lo_pattern, hi_pattern) = (lanes.replicate(lo), lanes.replicate(hi));
+    self.select(
+      &lanes,
+      |v| v >= lo && v <= hi,
+      |x| lanes.ge(x, lo_pattern) & lanes.ge(hi_pattern, x),
+    )
+  }
+
+  // Evaluates packed lanes a 64 bit word at a time, lane_test returns the high bit of each matching lane
+  fn select<F, G>(&self, lanes: &Lanes, test: F, lane_test: G) -> Bitmap
+  where
+    F: Fn(u64) -> bool,
+    G: Fn(u64) -> u64,
+  {
+    let mut selected = Bitmap::new(self.size);
+    let mut i = 0;
+    if lanes.count > 0 {
+      let w = self.word_size as usize;
+      let pext = lanes.has_pext();
+      while i + lanes.count <= self.size {
+        let bit_offset = i * w;
+        let dword = unsafe { (self.bytes.ptr.add(bit_offset >> 3) as *const u64).read_unaligned() };
+        let matches = lane_test(dword >> (bit_offset & 7)) & lanes.high;
+        if matches != 0 {
+          selected.or_bits(i, lanes.compress(matches, pext), lanes.count);
+        }
+        i += lanes.count;
+      }
+    }
+    for j in i..self.size {
+      if test(unsafe { self.get_unchecked(j) }) {
+        selected.set(j);
+      }
+    }
+    selected
+  }
+}
+
+// SWAR helpers over count lanes of word_size bits packed from bit 0 of a u64
+// There's no spare bit between lanes so comparisons work on the low bits and high bit of each lane separately
+struct Lanes {
+  word_size: u32,
+  count: usize,
+  high: u64, // top bit of each lane
+  low: u64,  // every other bit of each lane
+}
+
+impl Lanes {
+  // No lanes for words too wide for a single 64 bit read
+  fn new(word_size: u32) -> Lanes {
+    let count = (MAX_U64_READ / word_size) as usize;
+    let mut lanes = Lanes { word_size, count, high: 0, low: 0 };
+    lanes.high = lanes.replicate(1 << (word_size - 1));
+    lanes.low = lanes.replicate(mask(word_size - 1));
+    lanes
+  }
+
+  fn replicate(&self, value: u64) -> u64 {
+    (0..self.count).fold(0, |acc, j| acc | (value << (j as u32 * self.word_size)))
+  }
+
+  fn has_pext(&self) -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+      if is_x86_feature_detected!("bmi2") {
+        return true;
+      }
+    }
+    false
+  }
+
+  // Lane high bits down to one bit per lane
+  fn compress(&self, matches: u64, pext: bool) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+      if pext {
+        return unsafe { pext_u64(matches, self.high) };
+      }
+    }
+    let top = self.word_size - 1;
+    (0..self.count as u32).fold(0, |acc, j| acc | (((matches >> (j * self.word_size + top)) & 1) << j))
+  }
+
+  // High bit set in each lane where x == y
+  fn eq(&self, x: u64, y: u64) -> u64 {
+    let t = x ^ y;
+    // Adding low can't carry out of a lane, and sets the high bit if any low bit is set
+    let nonzero = ((t & self.low).wrapping_add(self.low) | t) & self.high;
+    !nonzero & self.high
+  }
+
+  // High bit set in each lane where x >= y
+  fn ge(&self, x: u64, y: u64) -> u64 {
+    // Per lane 2^(w-1) + x_low - y_low, always positive so never borrows from the next lane
+    let low_ge = (x | self.high).wrapping_sub(y & self.low);
+    // Decided by the high bits unless they're equal
+    ((x & !y) | (!(x ^ y) & low_ge)) & self.high
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+unsafe fn pext_u64(v: u64, mask: u64) -> u64 {
+  std::arch::x86_64::_pext_u64(v, mask)
+}
+
+// Entries per byte aligned group, 8 * word_size bits is always a whole number of bytes
+const GROUP: usize = 8;
+
+// out.len() is a multiple of GROUP, base is the first byte of the first group
+unsafe fn unpack_groups(base: *const u8, word_size: u32, out: &mut [u32]) {
+  #[cfg(target_arch = "x86_64")]
+  {
+    if is_x86_feature_detected!("avx2") {
+      return unpack_groups_avx2(base, word_size, out);
+    }
+  }
+  unpack_groups_scalar(base, word_size, out)
+}
+
+macro_rules! for_word_size {
+  ($word_size:expr, $f:ident, $($args:expr),*) => {
+    match $word_size {
+      1 => $f::<1>($($args),*), 2 => $f::<2>($($args),*), 3 => $f::<3>($($args),*), 4 => $f::<4>($($args),*),
+      5 => $f::<5>($($args),*), 6 => $f::<6>($($args),*), 7 => $f::<7>($($args),*), 8 => $f::<8>($($args),*),
+      9 => $f::<9>($($args),*), 10 => $f::<10>($($args),*), 11 => $f::<11>($($args),*), 12 => $f::<12>($($args),*),
+      13 => $f::<13>($($args),*), 14 => $f::<14>($($args),*), 15 => $f::<15>($($args),*), 16 => $f::<16>($($args),*),
+      17 => $f::<17>($($args),*), 18 => $f::<18>($($args),*), 19 => $f::<19>($($args),*), 20 => $f::<20>($($args),*),
+      21 => $f::<21>($($args),*), 22 => $f::<22>($($args),*), 23 => $f::<23>($($args),*), 24 => $f::<24>($($args),*),
+      25 => $f::<25>($($args),*), 26 => $f::<26>($($args),*), 27 => $f::<27>($($args),*), 28 => $f::<28>($($args),*),
+      29 => $f::<29>($($args),*), 30 => $f::<30>($($args),*), 31 => $f::<31>($($args),*), 32 => $f::<32>($($args),*),
+      w => panic!("Unsupported word size {}", w),
+    }
+  };
+}
+
+unsafe fn unpack_groups_scalar(base: *const u8, word_size: u32, out: &mut [u32]) {
+  for_word_size!(word_size, unpack_groups_w, base, out)
+}
+
+// Width known at compile time so the offsets and shifts within a group are constants
+unsafe fn unpack_groups_w<const W: usize>(base: *const u8, out: &mut [u32]) {
+  let mask = mask(W as u32);
+  for (g, group) in out.chunks_exact_mut(GROUP).enumerate() {
+    let ptr = base.add(g * W);
+    for (j, v) in group.iter_mut().enumerate() {
+      let bit = j * W;
+      let dword = (ptr.add(bit >> 3) as *const u64).read_unaligned();
+      *v = ((dword >> (bit & 7)) & mask) as u32;
+    }
+  }
+}
+
+// Gathers 64 bits at each entry's byte offset, shifts each lane by its bit offset and narrows to 32 bits
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn unpack_groups_avx2(base: *const u8, word_size: u32, out: &mut [u32]) {
+  use std::arch::x86_64::*;
+
+  let w = word_size as i32;
+  let offsets_lo = _mm_setr_epi32(0, w >> 3, (2 * w) >> 3, (3 * w) >> 3);
+  let offsets_hi = _mm_setr_epi32((4 * w) >> 3, (5 * w) >> 3, (6 * w) >> 3, (7 * w) >> 3);
+  let shifts_lo = _mm256_setr_epi64x(0, (w & 7) as i64, ((2 * w) & 7) as i64, ((3 * w) & 7) as i64);
+  let shifts_hi = _mm256_setr_epi64x(((4 * w) & 7) as i64, ((5 * w) & 7) as i64, ((6 * w) & 7) as i64, ((7 * w) & 7) as i64);
+  let mask = _mm256_set1_epi64x(mask(word_size) as i64);
+  let narrow = _mm256_setr_epi32(0, 2, 4, 6, 0, 2, 4, 6);
+
+  for (g, group) in out.chunks_exact_mut(GROUP).enumerate() {
+    let ptr = base.add(g * word_size as usize) as *const i64;
+    let lo = _mm256_i32gather_epi64::<1>(ptr, offsets_lo);
+    let hi = _mm256_i32gather_epi64::<1>(ptr, offsets_hi);
+    let lo = _mm256_and_si256(_mm256_srlv_epi64(lo, shifts_lo), mask);
+    let hi = _mm256_and_si256(_mm256_srlv_epi64(hi, shifts_hi), mask);
+    // Low 32 bits of each lane into the bottom 128 bits, then join the two halves
+    let lo = _mm256_permutevar8x32_epi32(lo, narrow);
+    let hi = _mm256_permutevar8x32_epi32(hi, narrow);
+    let v = _mm256_permute2x128_si256::<0x20>(lo, hi);
+    _mm256_storeu_si256(group.as_mut_ptr() as *mut __m256i, v);
+  }
+}
+
+// values.len() is a multiple of GROUP, base is the first byte of the first group
+// Whole groups fill whole bytes so the output is streamed 32 bits at a time without reading it back
+unsafe fn pack_groups(base: *mut u8, word_size: u32, mask: u64, values: &[u32]) {
+  let mut ptr = base;
+  let mut acc: u64 = 0;
+  let mut bits = 0;
+  for &v in values {
+    acc |= ((v as u64) & mask) << bits;
+    bits += word_size;
+    if bits >= 32 {
+      (ptr as *mut u32).write_unaligned(acc as u32);
+      ptr = ptr.add(4);
+      acc >>= 32;
+      bits -= 32;
+    }
+  }
+  // Always a whole number of bytes left over
+  for _ in 0..(bits >> 3) {
+    *ptr = acc as u8;
+    ptr = ptr.add(1);
+    acc >>= 8;
   }
 }
 
@@ -74,18 +509,239 @@ pub mod tests {
     let size = 11;
     let entries = 40;
     let mut pp = BitArray::new(entries, size);
-    let max_value = 1 << (size + 1) - 1;
+    let max_value = (1usize << size) - 1;
     let multiplier = max_value / entries;
 
     println!("Here");
 
     for i in 0..entries {
       println!("{}/{} = {}", i, entries - 1, i * multiplier);
-      pp.put(i as u32, (i * multiplier) as u32);
+      pp.put(i, (i * multiplier) as u64);
+    }
+    for i in 0..entries {
+      println!("{} : {}", i, pp.get(i));
+      assert!(pp.get(i) == (i * multiplier) as u64);
     }
+  }
+
+  #[test]
+  pub fn widen() {
+    let entries = 1000;
+    let mut pp = BitArray::growable(entries, 1);
+    // Every put needs at most one more bit than the last
     for i in 0..entries {
-      println!("{} : {}", i, pp.get(i as u32));
-      assert!(pp.get(i as u32) == (i * multiplier) as u32);
+      pp.put(i, (i * 17) as u64);
     }
+    assert!(pp.word_size() == 64 - (999u64 * 17).leading_zeros());
+    for i in 0..entries {
+      assert!(pp.get(i) == (i * 17) as u64);
+    }
+
+    pp.put(3, u32::MAX as u64);
+    assert!(pp.word_size() == 32);
+    assert!(pp.get(3) == u32::MAX as u64);
+    assert!(pp.get(2) == 34);
+    assert!(pp.get(4) == 68);
+  }
+
+  #[test]
+  pub fn try_put() {
+    let mut pp = BitArray::new(40, 4);
+    assert!(pp.try_put(1, 15) == Ok(()));
+    assert!(pp.try_put(2, 16) == Err(BitArrayError::Overflow { value: 16, word_size: 4 }));
+    assert!(pp.get(1) == 15);
+    assert!(pp.get(2) == 0);
+
+    // Fixed width arrays still mask, without clobbering neighbours
+    pp.put(2, 0x1f);
+    assert!(pp.get(1) == 15);
+    assert!(pp.get(2) == 0xf);
+    assert!(pp.get(3) == 0);
+    assert!(pp.word_size() == 4);
+
+    // Growable arrays don't widen on try_put
+    let mut pp = BitArray::growable(40, 4);
+    assert!(pp.try_put(2, 16).is_err());
+    assert!(pp.word_size() == 4);
+  }
+
+  #[test]
+  pub fn bounds() {
+    let mut pp = BitArray::new(10, 7);
+    assert!(pp.len() == 10);
+    assert!(pp.try_get(9) == Some(0));
+    assert!(pp.try_get(10).is_none());
+    assert!(pp.try_put(10, 1) == Err(BitArrayError::OutOfBounds { index: 10, len: 10 }));
+  }
+
+  #[test]
+  #[should_panic]
+  pub fn get_out_of_bounds() {
+    let pp = BitArray::new(10, 7);
+    pp.get(10);
+  }
+
+  #[test]
+  #[should_panic]
+  pub fn put_out_of_bounds() {
+    let mut pp = BitArray::new(10, 7);
+    pp.put(10, 1);
+  }
+
+  #[test]
+  pub fn push_resize() {
+    let mut pp = BitArray::new(0, 13);
+    assert!(pp.is_empty());
+    for i in 0..5000u64 {
+      pp.push(i);
+    }
+    assert!(pp.len() == 5000);
+    for i in 0..5000 {
+      assert!(pp.get(i) == i as u64);
+    }
+
+    pp.resize(10);
+    assert!(pp.len() == 10);
+    assert!(pp.try_get(10).is_none());
+    // Regrown entries read as zero rather than the old values
+    pp.resize(20);
+    assert!(pp.get(9) == 9);
+    assert!((10..20).all(|i| pp.get(i) == 0));
+    pp.resize(100000);
+    assert!(pp.get(99999) == 0);
+    pp.put(99999, 8191);
+    assert!(pp.get(99999) == 8191);
+
+    // Growable push widens
+    let mut pp = BitArray::growable(0, 1);
+    pp.push(1);
+    pp.push(1 << 40);
+    assert!(pp.word_size() == 41);
+    assert!(pp.get(0) == 1);
+    assert!(pp.get(1) == 1 << 40);
+  }
+
+  #[test]
+  pub fn clone() {
+    let mut pp = BitArray::new(100, 9);
+    pp.put(50, 300);
+    let mut copy = pp.clone();
+    copy.put(50, 7);
+    assert!(pp.get(50) == 300);
+    assert!(copy.get(50) == 7);
+  }
+
+  #[test]
+  pub fn wide_words() {
+    for word_size in 55..=64u32 {
+      let entries = 200;
+      let mut pp = BitArray::new(entries, word_size);
+      let mask = mask(word_size);
+      let value = |i: usize| (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask;
+      for i in 0..entries {
+        pp.put(i, value(i));
+      }
+      pp.put(entries - 1, mask);
+      for i in 0..entries - 1 {
+        assert!(pp.get(i) == value(i), "word size {} index {}", word_size, i);
+      }
+      assert!(pp.get(entries - 1) == mask);
+    }
+  }
+
+  #[test]
+  pub fn unpack() {
+    for word_size in 1..=32u32 {
+      let entries = 1000;
+      let mut pp = BitArray::new(entries, word_size);
+      let value = |i: usize| (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) & mask(word_size);
+      for i in 0..entries {
+        pp.put(i, value(i));
+      }
+
+      // Unaligned starts and lengths exercise the head, group and tail paths
+      for &(start, len) in [(0, 1000), (0, 7), (3, 5), (5, 100), (8, 64), (13, 987)].iter() {
+        let mut out = vec![0u32; len];
+        pp.unpack_into(start, &mut out);
+        for (i, &v) in out.iter().enumerate() {
+          assert!(v as u64 == value(start + i), "word size {} start {} index {}", word_size, start, i);
+        }
+      }
+
+      // The scalar fallback matches whatever was detected at runtime
+      let mut out = vec![0u32; 992];
+      unsafe { unpack_groups_scalar(pp.bytes.ptr, word_size, &mut out) };
+      assert!(out.iter().enumerate().all(|(i, &v)| v as u64 == value(i)));
+    }
+  }
+
+  #[test]
+  pub fn pack() {
+    for word_size in 1..=33u32 {
+      let entries = 1000;
+      let mut pp = BitArray::new(entries, word_size);
+      let values : Vec<u32> = (0..entries).map(|i| (i as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) as u32).collect();
+      let masked = |v: u32| (v as u64) & mask(word_size);
+
+      // Fill around the packed range to check it doesn't touch neighbours
+      for i in 0..entries {
+        pp.put(i, mask(word_size));
+      }
+      pp.pack_from(5, &values[5..990]);
+      for (i, &v) in values.iter().enumerate() {
+        let expected = if (5..990).contains(&i) { masked(v) } else { mask(word_size) };
+        assert!(pp.get(i) == expected, "word size {} index {}", word_size, i);
+      }
+    }
+
+    let mut pp = BitArray::growable(100, 2);
+    pp.pack_from(0, &[1, 2, 3, 1000]);
+    assert!(pp.word_size() == 10);
+    let mut out = [0u32; 4];
+    pp.unpack_into(0, &mut out);
+    assert!(out == [1, 2, 3, 1000]);
+  }
+
+  #[test]
+  pub fn select() {
+    for word_size in 1..=64u32 {
+      let entries = 777;
+      let mut pp = BitArray::new(entries, word_size);
+      let m = mask(word_size);
+      // Few distinct values so equality finds something, plus the extremes
+      let value = |i: usize| match i % 11 {
+        0 => 0,
+        1 => m,
+        k => ((k as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) >> (64 - word_size)) & m,
+      };
+      for i in 0..entries {
+        pp.put(i, value(i));
+      }
+
+      for probe in [0, 1, m, value(5), value(7)].iter() {
+        let expected : Vec<usize> = (0..entries).filter(|&i| value(i) == *probe).collect();
+        assert!(pp.select_eq(*probe).iter().eq(expected), "word size {} eq {}", word_size, probe);
+      }
+
+      let bounds = [(0, 0), (0, m), (1, m / 2), (m / 3, m / 3 * 2), (value(5), value(5)), (m, m), (m / 2, 1)];
+      for &(lo, hi) in bounds.iter() {
+        let expected : Vec<usize> = (0..entries).filter(|&i| value(i) >= lo && value(i) <= hi).collect();
+        assert!(pp.select_between(lo, hi).iter().eq(expected), "word size {} between {} {}", word_size, lo, hi);
+      }
+    }
+
+    // Scalar lane compression agrees with pext
+    for word_size in 1..=MAX_U64_READ {
+      let lanes = Lanes::new(word_size);
+      let matches = 0xA5A5_5A5A_F0F0_0F0F & lanes.high;
+      let expected = (0..lanes.count).filter(|j| matches & (1 << (j * word_size as usize + word_size as usize - 1)) != 0).fold(0, |acc, j| acc | (1 << j));
+      assert!(lanes.compress(matches, false) == expected);
+      assert!(lanes.compress(matches, lanes.has_pext()) == expected);
+    }
+
+    let pp = BitArray::new(100, 4);
+    assert!(pp.select_eq(16).count() == 0);
+    assert!(pp.select_eq(0).count() == 100);
+    assert!(pp.select_between(0, 1000).count() == 100);
   }
 }
@@ -0,0 +1,163 @@
+// Selection bitmap, bit i is set if row i is selected
+// Produced by predicate evaluation and combined with and/or before fetching rows
+
+#![allow(dead_code)]
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bitmap {
+  words: Vec<u64>,
+  len: usize,
+}
+
+impl Bitmap {
+  pub fn new(len: usize) -> Bitmap {
+    Bitmap {
+      words: vec![0; len.div_ceil(64)],
+      len,
+    }
+  }
+
+  pub fn full(len: usize) -> Bitmap {
+    let mut b = Bitmap {
+      words: vec![!0; len.div_ceil(64)],
+      len,
+    };
+    b.clear_tail();
+    b
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn get(&self, i: usize) -> bool {
+    assert!(i < self.len, "Index {} out of bounds for Bitmap of length {}", i, self.len);
+    self.words[i >> 6] & (1 << (i & 63)) != 0
+  }
+
+  pub fn set(&mut self, i: usize) {
+    assert!(i < self.len, "Index {} out of bounds for Bitmap of length {}", i, self.len);
+    self.words[i >> 6] |= 1 << (i & 63);
+  }
+
+  pub fn unset(&mut self, i: usize) {
+    assert!(i < self.len, "Index {} out of bounds for Bitmap of length {}", i, self.len);
+    self.words[i >> 6] &= !(1 << (i & 63));
+  }
+
+  // Or the low n bits of bits into positions i..i + n
+  pub fn or_bits(&mut self, i: usize, bits: u64, n: usize) {
+    assert!(n <= 64 && i + n <= self.len, "Range {}..{} out of bounds for Bitmap of length {}", i, i + n, self.len);
+    let shift = i & 63;
+    self.words[i >> 6] |= bits << shift;
+    if shift + n > 64 {
+      self.words[(i >> 6) + 1] |= bits >> (64 - shift);
+    }
+  }
+
+  // Number of selected rows
+  pub fn count(&self) -> usize {
+    self.words.iter().map(|w| w.count_ones() as usize).sum()
+  }
+
+  pub fn and(&mut self, other: &Bitmap) {
+    assert!(self.len == other.len, "Bitmap lengths differ {} != {}", self.len, other.len);
+    self.words.iter_mut().zip(other.words.iter()).for_each(|(a, b)| *a &= b);
+  }
+
+  pub fn or(&mut self, other: &Bitmap) {
+    assert!(self.len == other.len, "Bitmap lengths differ {} != {}", self.len, other.len);
+    self.words.iter_mut().zip(other.words.iter()).for_each(|(a, b)| *a |= b);
+  }
+
+  pub fn not(&mut self) {
+    self.words.iter_mut().for_each(|w| *w = !*w);
+    self.clear_tail();
+  }
+
+  // Positions of the selected rows in order
+  pub fn iter(&self) -> BitmapIterator<'_> {
+    BitmapIterator {
+      words: &self.words,
+      index: 0,
+      current: self.words.first().cloned().unwrap_or(0),
+    }
+  }
+
+  // Bits past len stay clear so count and iter don't see them
+  fn clear_tail(&mut self) {
+    if self.len & 63 != 0 {
+      let last = self.words.len() - 1;
+      self.words[last] &= (1 << (self.len & 63)) - 1;
+    }
+  }
+}
+
+pub struct BitmapIterator<'a> {
+  words: &'a [u64],
+  index: usize,
+  current: u64,
+}
+
+impl<'a> Iterator for BitmapIterator<'a> {
+  type Item = usize;
+  fn next(&mut self) -> Option<usize> {
+    while self.current == 0 {
+      self.index += 1;
+      self.current = *self.words.get(self.index)?;
+    }
+    let bit = self.current.trailing_zeros() as usize;
+    self.current &= self.current - 1;
+    Some((self.index << 6) + bit)
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+
+  #[test]
+  pub fn set_get() {
+    let mut b = Bitmap::new(130);
+    assert!(b.count() == 0);
+    b.set(0);
+    b.set(64);
+    b.set(129);
+    assert!(b.get(0) && b.get(64) && b.get(129));
+    assert!(!b.get(1));
+    assert!(b.count() == 3);
+    assert!(b.iter().eq(vec![0, 64, 129]));
+    b.unset(64);
+    assert!(b.iter().eq(vec![0, 129]));
+
+    b.or_bits(60, 0b1011, 4);
+    b.or_bits(126, 0b101, 3);
+    assert!(b.iter().eq(vec![0, 60, 61, 63, 126, 128, 129]));
+  }
+
+  #[test]
+  pub fn combine() {
+    let mut evens = Bitmap::new(100);
+    (0..100).step_by(2).for_each(|i| evens.set(i));
+    let mut threes = Bitmap::new(100);
+    (0..100).step_by(3).for_each(|i| threes.set(i));
+
+    let mut both = evens.clone();
+    both.and(&threes);
+    assert!(both.iter().eq((0..100).step_by(6)));
+
+    let mut either = evens.clone();
+    either.or(&threes);
+    assert!(either.count() == 50 + 34 - 17);
+
+    evens.not();
+    assert!(evens.iter().eq((1..100).step_by(2)));
+    assert!(Bitmap::full(100).count() == 100);
+    assert!(Bitmap::new(0).iter().next().is_none());
+  }
+}
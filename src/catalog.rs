@@ -0,0 +1,824 @@
+// Catalog of the dictionaries and table columns in a set of pages
+// Dictionaries are created on their own and columns refer to them by ID, so FK/PK pairs can
+// share one dictionary and a join between them compares IDs without decoding values
+// A header page records where the two lists start, it's the only page that needs to be kept
+//  dictionaries: PagedVector<DictionaryEntry>
+//  columns: PagedVector<ColumnEntry>, data is the entry page of the column's values
+// Compacting a dictionary writes the new dictionary and columns to new pages, commits the new
+// page numbers to the journal and only then points the catalog at them, so a crash leaves
+// either the old pages in use or a journal entry that recover finishes applying
+
+#![allow(dead_code)]
+
+use crate::bit_array::{BitArray};
+use crate::database::{DbError, PageProvider};
+use crate::dict_column::{self, DictColumn};
+pub use crate::dict_column::{CatalogDictionary, NULL_ID};
+use crate::dictionary::{ArrayDictionary, Dictionary};
+use crate::journal::{Entry, Journal, JournalError};
+use crate::paged_vector::{self, PagedVectorIterator};
+use crate::sorted_dictionary::{SortedDictionary};
+
+const CATALOG_VERSION: u8 = 0;
+const NAME_SIZE: usize = 32;
+
+pub type DictionaryId = u32;
+pub type ColumnId = u32;
+
+#[derive(Debug)]
+pub enum CatalogError {
+  Exists(String),
+  NotFound(String),
+  NameTooLong(String),
+  WrongType(String),
+  Journal(JournalError),
+  Db(DbError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum ColumnType {
+  Int = 0,    // PagedVector<i64> with zones
+  Ids = 1,    // PagedVector<u32> with zones of IDs in the column's dictionary
+  String = 2, // DictColumn<String> over the column's dictionary, data is its header page
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct CatalogHeader {
+  version: u8,
+  padding: [u8; 3],
+  dictionaries: u32,
+  columns: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DictionaryEntry {
+  name: [u8; NAME_SIZE],
+  header: u32, // Header page of the dictionary
+  sorted: u8,
+  padding: [u8; 3],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct ColumnEntry {
+  table: [u8; NAME_SIZE],
+  name: [u8; NAME_SIZE],
+  column_type: ColumnType,
+  padding: [u8; 3],
+  dictionary: DictionaryId, // NO_DICTIONARY for Int columns
+  data: u32,
+}
+
+const NO_DICTIONARY: DictionaryId = u32::MAX;
+// New ID of a value no row refers to
+pub const REMOVED: u32 = u32::MAX;
+// Value that stands for NULL in Int columns, it can't be stored
+pub const NULL_INT: i64 = i64::MIN;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColumnDef {
+  pub id: ColumnId,
+  pub table: String,
+  pub name: String,
+  pub column_type: ColumnType,
+  pub dictionary: Option<DictionaryId>,
+}
+
+// Outcome of compacting a dictionary, remap[old ID] is the new ID or REMOVED
+// IDs keep their relative order so a sorted dictionary stays sorted
+pub struct Compaction {
+  pub dictionary: DictionaryId,
+  pub remap: Vec<u32>,
+  header: u32,
+  columns: Vec<(ColumnId, u32)>,
+}
+
+impl Compaction {
+  // Values left in the dictionary
+  pub fn kept(&self) -> usize {
+    self.remap.iter().filter(|&&id| id != REMOVED).count()
+  }
+
+  fn entry(&self) -> Entry {
+    Entry::CompactDictionary { dictionary: self.dictionary, header: self.header, columns: self.columns.clone() }
+  }
+
+  fn remap_bit_array(&self, ids: &mut BitArray) {
+    for i in 0..ids.len() {
+      let id = self.remap[ids.get(i) as usize];
+      ids.put(i, id as u64);
+    }
+  }
+}
+
+pub struct Catalog<'a> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  dictionaries: u32,
+  columns: u32,
+}
+
+// Names are stored zero padded in a fixed size field
+fn encode_name(name: &str) -> Result<[u8; NAME_SIZE], CatalogError> {
+  if name.len() > NAME_SIZE || name.as_bytes().contains(&0) {
+    return Err(CatalogError::NameTooLong(name.to_string()));
+  }
+  let mut out = [0; NAME_SIZE];
+  out[..name.len()].copy_from_slice(name.as_bytes());
+  Ok(out)
+}
+
+fn decode_name(name: &[u8; NAME_SIZE]) -> String {
+  let len = name.iter().position(|&b| b == 0).unwrap_or(NAME_SIZE);
+  String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+impl ColumnEntry {
+  fn def(&self, id: ColumnId) -> ColumnDef {
+    ColumnDef {
+      id,
+      table: decode_name(&self.table),
+      name: decode_name(&self.name),
+      column_type: self.column_type,
+      dictionary: if self.dictionary == NO_DICTIONARY { None } else { Some(self.dictionary) },
+    }
+  }
+}
+
+impl<'a> Catalog<'a> {
+  pub fn new(db: &'a mut dyn PageProvider) -> Catalog<'a> {
+    let header = db.alloc(1)[0];
+    let dictionaries = paged_vector::bulk_load::<DictionaryEntry>(&[], db);
+    let columns = paged_vector::bulk_load::<ColumnEntry>(&[], db);
+    let mut c = Catalog { db, header, dictionaries, columns };
+    c.write_header();
+    c
+  }
+
+  // Catalog previously created with new whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> Catalog<'a> {
+    let h = unsafe { *(db.page_bytes(header).as_ptr() as *const CatalogHeader) };
+    assert!(h.version == CATALOG_VERSION, "Unknown catalog version {} on page {}", h.version, header);
+    Catalog { db, header, dictionaries: h.dictionaries, columns: h.columns }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  fn write_header(&mut self) {
+    let h = CatalogHeader { version: CATALOG_VERSION, padding: [0; 3], dictionaries: self.dictionaries, columns: self.columns };
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut CatalogHeader) = h };
+  }
+
+  fn dictionary_entry(&self, id: DictionaryId) -> Result<DictionaryEntry, CatalogError> {
+    paged_vector::get::<DictionaryEntry>(self.dictionaries, id as usize, self.db)
+      .cloned()
+      .ok_or_else(|| CatalogError::NotFound(format!("dictionary {}", id)))
+  }
+
+  fn column_entry(&self, id: ColumnId) -> Result<ColumnEntry, CatalogError> {
+    paged_vector::get::<ColumnEntry>(self.columns, id as usize, self.db)
+      .cloned()
+      .ok_or_else(|| CatalogError::NotFound(format!("column {}", id)))
+  }
+
+  pub fn create_dictionary(&mut self, name: &str, sorted: bool) -> Result<DictionaryId, CatalogError> {
+    if self.find_dictionary(name).is_some() {
+      return Err(CatalogError::Exists(name.to_string()));
+    }
+    let entry_name = encode_name(name)?;
+    let header = if sorted {
+      SortedDictionary::<u8>::new(self.db).header_page()
+    } else {
+      ArrayDictionary::<u8>::new(self.db).header_page()
+    };
+    let id = paged_vector::len::<DictionaryEntry>(self.dictionaries, self.db) as DictionaryId;
+    let e = DictionaryEntry { name: entry_name, header, sorted: sorted as u8, padding: [0; 3] };
+    self.dictionaries = paged_vector::append_slice(self.dictionaries, &[e], self.db);
+    self.write_header();
+    Ok(id)
+  }
+
+  pub fn find_dictionary(&self, name: &str) -> Option<DictionaryId> {
+    let name = encode_name(name).ok()?;
+    paged_vector::iter_range::<DictionaryEntry>(self.dictionaries, 0..usize::MAX, self.db)
+      .position(|e| e.name == name)
+      .map(|i| i as DictionaryId)
+  }
+
+  pub fn dictionary(&mut self, id: DictionaryId) -> Result<CatalogDictionary<'_>, CatalogError> {
+    let e = self.dictionary_entry(id)?;
+    Ok(CatalogDictionary::open(self.db, e.header, e.sorted != 0))
+  }
+
+  // Ids and String columns need a dictionary, it can be shared with other columns
+  pub fn add_column(&mut self, table: &str, name: &str, column_type: ColumnType, dictionary: Option<DictionaryId>) -> Result<ColumnId, CatalogError> {
+    if self.find_column(table, name).is_some() {
+      return Err(CatalogError::Exists(format!("{}.{}", table, name)));
+    }
+    let dictionary = match (column_type, dictionary) {
+      (ColumnType::Ids, Some(d)) | (ColumnType::String, Some(d)) => Some((d, self.dictionary_entry(d)?)),
+      (ColumnType::Int, None) => None,
+      _ => return Err(CatalogError::WrongType(format!("{}.{}", table, name))),
+    };
+    let (table_name, column_name) = (encode_name(table)?, encode_name(name)?);
+    let data = match (column_type, dictionary) {
+      (ColumnType::String, Some((_, d))) => DictColumn::<String>::with_dictionary(self.db, d.header, d.sorted != 0).header_page(),
+      (ColumnType::Ids, _) => paged_vector::bulk_load_zoned::<u32>(&[], self.db),
+      _ => paged_vector::bulk_load_zoned::<i64>(&[], self.db),
+    };
+    let e = ColumnEntry {
+      table: table_name,
+      name: column_name,
+      column_type,
+      padding: [0; 3],
+      dictionary: dictionary.map(|(d, _)| d).unwrap_or(NO_DICTIONARY),
+      data,
+    };
+    let id = paged_vector::len::<ColumnEntry>(self.columns, self.db) as ColumnId;
+    self.columns = paged_vector::append_slice(self.columns, &[e], self.db);
+    self.write_header();
+    Ok(id)
+  }
+
+  pub fn find_column(&self, table: &str, name: &str) -> Option<ColumnId> {
+    let (table, name) = (encode_name(table).ok()?, encode_name(name).ok()?);
+    paged_vector::iter_range::<ColumnEntry>(self.columns, 0..usize::MAX, self.db)
+      .position(|e| e.table == table && e.name == name)
+      .map(|i| i as ColumnId)
+  }
+
+  pub fn column(&self, id: ColumnId) -> Result<ColumnDef, CatalogError> {
+    Ok(self.column_entry(id)?.def(id))
+  }
+
+  // Columns of table in the order they were added
+  pub fn columns(&self, table: &str) -> Vec<ColumnDef> {
+    let table = match encode_name(table) {
+      Ok(t) => t,
+      Err(_) => return Vec::new(),
+    };
+    paged_vector::iter_range::<ColumnEntry>(self.columns, 0..usize::MAX, self.db)
+      .enumerate()
+      .filter(|(_, e)| e.table == table)
+      .map(|(i, e)| e.def(i as ColumnId))
+      .collect()
+  }
+
+  // Columns whose IDs can be compared directly
+  pub fn shares_dictionary(&self, a: ColumnId, b: ColumnId) -> Result<bool, CatalogError> {
+    let (a, b) = (self.column(a)?, self.column(b)?);
+    Ok(a.dictionary.is_some() && a.dictionary == b.dictionary)
+  }
+
+  // For query operators reading columns through the shared provider
+  pub(crate) fn db(&self) -> &dyn PageProvider {
+    self.db
+  }
+
+  // Entry page of an Int or Ids column's values, header page of a String column
+  pub(crate) fn data_page(&self, id: ColumnId) -> Result<u32, CatalogError> {
+    Ok(self.column_entry(id)?.data)
+  }
+
+  fn typed_column(&self, id: ColumnId, column_type: ColumnType) -> Result<ColumnEntry, CatalogError> {
+    let e = self.column_entry(id)?;
+    if e.column_type != column_type {
+      return Err(CatalogError::WrongType(format!("{}.{}", decode_name(&e.table), decode_name(&e.name))));
+    }
+    Ok(e)
+  }
+
+  // The column's entry page moves as it grows
+  fn set_data(&mut self, id: ColumnId, mut e: ColumnEntry, data: u32) {
+    if e.data != data {
+      e.data = data;
+      paged_vector::set::<ColumnEntry>(self.columns, id as usize, e, self.db);
+    }
+  }
+
+  pub fn append_ints(&mut self, id: ColumnId, vs: &[i64]) -> Result<(), CatalogError> {
+    let e = self.typed_column(id, ColumnType::Int)?;
+    let data = paged_vector::append_slice(e.data, vs, self.db);
+    self.set_data(id, e, data);
+    Ok(())
+  }
+
+  // IDs must be in the column's dictionary or be NULL_ID
+  fn check_ids(&mut self, e: &ColumnEntry, ids: &[u32]) -> Result<(), CatalogError> {
+    let len = self.dictionary(e.dictionary)?.len();
+    match ids.iter().find(|&&id| id != NULL_ID && id as usize >= len) {
+      Some(id) => Err(CatalogError::NotFound(format!("{}.{} ID {}", decode_name(&e.table), decode_name(&e.name), id))),
+      None => Ok(()),
+    }
+  }
+
+  pub fn append_ids(&mut self, id: ColumnId, ids: &[u32]) -> Result<(), CatalogError> {
+    let e = self.typed_column(id, ColumnType::Ids)?;
+    self.check_ids(&e, ids)?;
+    let data = paged_vector::append_slice(e.data, ids, self.db);
+    self.set_data(id, e, data);
+    Ok(())
+  }
+
+  // Points an Ids or String column at new pages holding ids
+  fn write_ids(&mut self, id: ColumnId, e: ColumnEntry, ids: &[u32]) -> Result<(), CatalogError> {
+    let data = match e.column_type {
+      ColumnType::String => {
+        let d = self.dictionary_entry(e.dictionary)?;
+        let mut column = DictColumn::<String>::with_dictionary(self.db, d.header, d.sorted != 0);
+        column.push_ids(ids);
+        column.header_page()
+      }
+      _ => paged_vector::bulk_load_zoned::<u32>(ids, self.db),
+    };
+    self.set_data(id, e, data);
+    Ok(())
+  }
+
+  // Sets rows of an Int column to v in place
+  pub fn update_ints(&mut self, id: ColumnId, rows: &[usize], v: i64) -> Result<(), CatalogError> {
+    let e = self.typed_column(id, ColumnType::Int)?;
+    let len = self.rows(id)?;
+    if let Some(row) = rows.iter().find(|&&r| r >= len) {
+      return Err(CatalogError::NotFound(format!("{}.{} row {}", decode_name(&e.table), decode_name(&e.name), row)));
+    }
+    rows.iter().for_each(|&row| { paged_vector::set::<i64>(e.data, row, v, self.db); });
+    Ok(())
+  }
+
+  // Sets rows of an Ids or String column to the ID v in place
+  // A String column is only repacked to new pages when v needs more bits than it packs
+  pub fn update_ids(&mut self, id: ColumnId, rows: &[usize], v: u32) -> Result<(), CatalogError> {
+    let e = self.column_entry(id)?;
+    if e.column_type == ColumnType::Int {
+      return Err(CatalogError::WrongType(format!("{}.{}", decode_name(&e.table), decode_name(&e.name))));
+    }
+    self.check_ids(&e, &[v])?;
+    let len = self.rows(id)?;
+    if let Some(row) = rows.iter().find(|&&r| r >= len) {
+      return Err(CatalogError::NotFound(format!("{}.{} row {}", decode_name(&e.table), decode_name(&e.name), row)));
+    }
+    if e.column_type == ColumnType::String {
+      DictColumn::<String>::open(self.db, e.data).set_ids(rows, v);
+    } else {
+      rows.iter().for_each(|&row| { paged_vector::set::<u32>(e.data, row, v, self.db); });
+    }
+    Ok(())
+  }
+
+  // Keeps only rows, in that order, of every column of table, on new pages
+  // The old pages aren't reused, there's no free list yet
+  // Columns move to their new pages one at a time and not through the journal, so this isn't
+  // crash safe, a crash part way through leaves the table's columns holding different rows
+  pub fn retain_rows(&mut self, table: &str, rows: &[usize]) -> Result<(), CatalogError> {
+    let columns = self.columns(table);
+    if let Some(&max) = rows.iter().max() {
+      for c in columns.iter() {
+        if max >= self.rows(c.id)? {
+          return Err(CatalogError::NotFound(format!("{}.{} row {}", c.table, c.name, max)));
+        }
+      }
+    }
+    for c in columns.iter() {
+      let e = self.column_entry(c.id)?;
+      match e.column_type {
+        ColumnType::Int => {
+          let vs : Vec<i64> = paged_vector::iter_range::<i64>(e.data, 0..usize::MAX, self.db).collect();
+          let data = paged_vector::bulk_load_zoned::<i64>(&rows.iter().map(|&r| vs[r]).collect::<Vec<i64>>(), self.db);
+          self.set_data(c.id, e, data);
+        }
+        _ => {
+          let ids = self.column_ids(&e);
+          self.write_ids(c.id, e, &rows.iter().map(|&r| ids[r]).collect::<Vec<u32>>())?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn ints(&self, id: ColumnId) -> Result<PagedVectorIterator<'_, i64>, CatalogError> {
+    let e = self.typed_column(id, ColumnType::Int)?;
+    Ok(paged_vector::iter_range::<i64>(e.data, 0..usize::MAX, self.db))
+  }
+
+  pub fn ids(&self, id: ColumnId) -> Result<PagedVectorIterator<'_, u32>, CatalogError> {
+    let e = self.typed_column(id, ColumnType::Ids)?;
+    Ok(paged_vector::iter_range::<u32>(e.data, 0..usize::MAX, self.db))
+  }
+
+  // The column's values, pushing to it adds to its shared dictionary
+  pub fn strings(&mut self, id: ColumnId) -> Result<DictColumn<'_, String>, CatalogError> {
+    let e = self.typed_column(id, ColumnType::String)?;
+    Ok(DictColumn::open(self.db, e.data))
+  }
+
+  // IDs held by an Ids or String column
+  fn column_ids(&self, e: &ColumnEntry) -> Vec<u32> {
+    match e.column_type {
+      ColumnType::String => dict_column::ids(e.data, self.db).collect(),
+      _ => paged_vector::iter_range::<u32>(e.data, 0..usize::MAX, self.db).collect(),
+    }
+  }
+
+  pub fn rows(&self, id: ColumnId) -> Result<usize, CatalogError> {
+    let e = self.column_entry(id)?;
+    Ok(match e.column_type {
+      ColumnType::Int => paged_vector::len::<i64>(e.data, self.db),
+      ColumnType::Ids => paged_vector::len::<u32>(e.data, self.db),
+      ColumnType::String => dict_column::len(e.data, self.db),
+    })
+  }
+
+  fn dictionary_columns(&self, id: DictionaryId) -> Vec<(ColumnId, ColumnEntry)> {
+    paged_vector::iter_range::<ColumnEntry>(self.columns, 0..usize::MAX, self.db)
+      .enumerate()
+      .filter(|(_, e)| e.dictionary == id)
+      .map(|(i, e)| (i as ColumnId, e))
+      .collect()
+  }
+
+  // Writes the compacted dictionary and columns to new pages, the catalog is unchanged
+  fn prepare_compaction(&mut self, id: DictionaryId, arrays: &[&mut BitArray]) -> Result<Compaction, CatalogError> {
+    let entry = self.dictionary_entry(id)?;
+    let columns = self.dictionary_columns(id);
+    let size = self.dictionary(id)?.len();
+    for a in arrays.iter() {
+      if let Some(v) = (0..a.len()).map(|i| a.get(i)).find(|&v| v as usize >= size) {
+        return Err(CatalogError::NotFound(format!("dictionary {} ID {}", id, v)));
+      }
+    }
+
+    // Any reference keeps a value
+    let mut used = vec![false; size];
+    for (_, e) in columns.iter() {
+      self.column_ids(e).iter().filter(|&&v| v != NULL_ID).for_each(|&v| used[v as usize] = true);
+    }
+    for a in arrays.iter() {
+      (0..a.len()).for_each(|i| used[a.get(i) as usize] = true);
+    }
+    let mut remap = vec![REMOVED; size];
+    for (next, (i, _)) in used.iter().enumerate().filter(|(_, &u)| u).enumerate() {
+      remap[i] = next as u32;
+    }
+
+    let values : Vec<Vec<u8>> = {
+      let d = self.dictionary(id)?;
+      (0..size).filter(|&i| used[i]).map(|i| d.get(i as u32).into_owned()).collect()
+    };
+    let header = if entry.sorted != 0 {
+      let mut d = SortedDictionary::<u8>::new(self.db);
+      values.iter().for_each(|v| { d.add(v); });
+      d.header_page()
+    } else {
+      let mut d = ArrayDictionary::<u8>::new(self.db);
+      values.iter().for_each(|v| { d.add(v); });
+      d.header_page()
+    };
+
+    let columns = columns.iter().map(|(c, e)| {
+      let ids : Vec<u32> = self.column_ids(e).iter().map(|&v| if v == NULL_ID { NULL_ID } else { remap[v as usize] }).collect();
+      let data = match e.column_type {
+        ColumnType::String => {
+          let mut column = DictColumn::<String>::with_dictionary(self.db, header, entry.sorted != 0);
+          column.push_ids(&ids);
+          column.header_page()
+        }
+        _ => paged_vector::bulk_load_zoned::<u32>(&ids, self.db),
+      };
+      (*c, data)
+    }).collect();
+    Ok(Compaction { dictionary: id, remap, header, columns })
+  }
+
+  // Points the catalog at the compacted pages, applying it twice is harmless
+  fn apply_compaction(&mut self, id: DictionaryId, header: u32, columns: &[(ColumnId, u32)]) -> Result<(), CatalogError> {
+    let mut d = self.dictionary_entry(id)?;
+    d.header = header;
+    paged_vector::set::<DictionaryEntry>(self.dictionaries, id as usize, d, self.db);
+    for &(c, data) in columns {
+      let e = self.column_entry(c)?;
+      self.set_data(c, e, data);
+    }
+    Ok(())
+  }
+
+  // Drops values no row refers to and renumbers the rest, rewriting every column using the
+  // dictionary and the ID arrays passed in, which also count as references
+  // The old pages aren't reused, there's no free list yet
+  // The new pages are synced before the journal entry that points the catalog at them
+  pub fn compact_dictionary(&mut self, id: DictionaryId, arrays: &mut [&mut BitArray], journal: &mut dyn Journal) -> Result<Compaction, CatalogError> {
+    let c = self.prepare_compaction(id, arrays)?;
+    self.db.sync().map_err(CatalogError::Db)?;
+    journal.add(&c.entry()).map_err(CatalogError::Journal)?;
+    journal.sync().map_err(CatalogError::Journal)?;
+
+    self.apply_compaction(id, c.header, &c.columns)?;
+    journal.add(&Entry::CompactDone { dictionary: id }).map_err(CatalogError::Journal)?;
+    journal.sync().map_err(CatalogError::Journal)?;
+    arrays.iter_mut().for_each(|a| c.remap_bit_array(a));
+    Ok(c)
+  }
+
+  // Finishes compactions committed to the journal but not marked done, returns how many
+  pub fn recover(&mut self, entries: &[Entry], journal: &mut dyn Journal) -> Result<usize, CatalogError> {
+    let mut pending : Vec<&Entry> = Vec::new();
+    for e in entries {
+      match e {
+        Entry::CompactDictionary { .. } => pending.push(e),
+        Entry::CompactDone { dictionary } => pending.retain(|p| match p {
+          Entry::CompactDictionary { dictionary: d, .. } => d != dictionary,
+          _ => true,
+        }),
+        _ => {}
+      }
+    }
+    for e in pending.iter() {
+      if let Entry::CompactDictionary { dictionary, header, columns } = e {
+        self.apply_compaction(*dictionary, *header, columns)?;
+        journal.add(&Entry::CompactDone { dictionary: *dictionary }).map_err(CatalogError::Journal)?;
+      }
+    }
+    journal.sync().map_err(CatalogError::Journal)?;
+    Ok(pending.len())
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use crate::journal::{MemoryJournal};
+  #[allow(unused_imports)]
+  use std::collections::HashMap;
+
+  #[test]
+  pub fn shared_dictionary() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    let customers = c.create_dictionary("customer", false).unwrap();
+    let pk = c.add_column("customers", "name", ColumnType::Ids, Some(customers)).unwrap();
+    let fk = c.add_column("orders", "customer", ColumnType::Ids, Some(customers)).unwrap();
+    let amount = c.add_column("orders", "amount", ColumnType::Int, None).unwrap();
+    let other = c.create_dictionary("product", true).unwrap();
+    let product = c.add_column("orders", "product", ColumnType::Ids, Some(other)).unwrap();
+
+    assert!(c.shares_dictionary(pk, fk).unwrap());
+    assert!(!c.shares_dictionary(fk, product).unwrap());
+    assert!(!c.shares_dictionary(pk, amount).unwrap());
+
+    let names = ["alice", "bob", "carol"];
+    let ids : Vec<u32> = {
+      let mut d = c.dictionary(customers).unwrap();
+      names.iter().map(|n| d.add(n.as_bytes())).collect()
+    };
+    c.append_ids(pk, &ids).unwrap();
+
+    let orders = [("carol", 5), ("alice", 7), ("carol", 11), ("dave", 13)];
+    let order_ids : Vec<u32> = {
+      let mut d = c.dictionary(customers).unwrap();
+      orders.iter().map(|(n, _)| d.add(n.as_bytes())).collect()
+    };
+    c.append_ids(fk, &order_ids).unwrap();
+    c.append_ints(amount, &orders.iter().map(|o| o.1).collect::<Vec<i64>>()).unwrap();
+
+    // Join orders to customers on IDs alone
+    let by_id : HashMap<u32, usize> = c.ids(pk).unwrap().enumerate().map(|(row, id)| (id, row)).collect();
+    let joined : Vec<(usize, i64)> = c.ids(fk).unwrap().zip(c.ints(amount).unwrap())
+      .filter_map(|(id, amount)| by_id.get(&id).map(|&row| (row, amount)))
+      .collect();
+    assert!(joined == vec![(2, 5), (0, 7), (2, 11)]);
+    assert!(c.rows(fk).unwrap() == 4);
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let header = {
+      let mut c = Catalog::new(&mut pp);
+      let d = c.create_dictionary("status", true).unwrap();
+      let col = c.add_column("jobs", "status", ColumnType::Ids, Some(d)).unwrap();
+      let id = c.dictionary(d).unwrap().add(b"done");
+      // Enough rows to move the entry page
+      c.append_ids(col, &vec![id; 5000]).unwrap();
+      c.add_column("jobs", "runtime", ColumnType::Int, None).unwrap();
+      c.header_page()
+    };
+
+    let mut c = Catalog::open(&mut pp, header);
+    let cols = c.columns("jobs");
+    assert!(cols.len() == 2);
+    assert!(cols[0].name == "status" && cols[0].column_type == ColumnType::Ids);
+    assert!(cols[1].name == "runtime" && cols[1].dictionary.is_none());
+    assert!(c.find_column("jobs", "runtime") == Some(1));
+    assert!(c.rows(0).unwrap() == 5000);
+    let d = c.find_dictionary("status").unwrap();
+    assert!(c.dictionary(d).unwrap().sorted().is_some());
+    assert!(c.dictionary(d).unwrap().lookup(b"done") == Some(0));
+    assert!(c.ids(0).unwrap().all(|id| id == 0));
+  }
+
+  #[test]
+  pub fn errors() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    let d = c.create_dictionary("d", false).unwrap();
+    assert!(matches!(c.create_dictionary("d", false), Err(CatalogError::Exists(n)) if n == "d"));
+    assert!(c.add_column("t", "a", ColumnType::Ids, None).is_err());
+    assert!(c.add_column("t", "a", ColumnType::Int, Some(d)).is_err());
+    assert!(c.add_column("t", "a", ColumnType::Ids, Some(d + 1)).is_err());
+    let a = c.add_column("t", "a", ColumnType::Int, None).unwrap();
+    assert!(matches!(c.add_column("t", "a", ColumnType::Int, None), Err(CatalogError::Exists(n)) if n == "t.a"));
+    assert!(c.append_ids(a, &[1]).is_err());
+    assert!(c.column(a + 1).is_err());
+    let ids = c.add_column("t", "ids", ColumnType::Ids, Some(d)).unwrap();
+    c.dictionary(d).unwrap().add(b"only");
+    assert!(matches!(c.append_ids(ids, &[0, 1]), Err(CatalogError::NotFound(n)) if n == "t.ids ID 1"));
+    c.append_ids(ids, &[0, NULL_ID]).unwrap();
+    assert!(c.rows(ids).unwrap() == 2);
+    let long = "x".repeat(NAME_SIZE + 1);
+    assert!(matches!(c.add_column("t", &long, ColumnType::Int, None), Err(CatalogError::NameTooLong(n)) if n == long));
+  }
+
+  // Ids and String columns sharing a dictionary of 100 values that only refer to some of them
+  fn compaction_setup(c: &mut Catalog, sorted: bool) -> (DictionaryId, ColumnId, ColumnId, Vec<String>, Vec<String>) {
+    let d = c.create_dictionary("d", sorted).unwrap();
+    let a = c.add_column("t", "a", ColumnType::Ids, Some(d)).unwrap();
+    let b = c.add_column("u", "b", ColumnType::String, Some(d)).unwrap();
+    let words : Vec<String> = (0..100).map(|i| format!("word{:03}", (i * 37) % 100)).collect();
+    let (ids_a, ids_b) : (Vec<u32>, Vec<u32>) = {
+      let mut dict = c.dictionary(d).unwrap();
+      words.iter().for_each(|w| { dict.add(w.as_bytes()); });
+      ((0..3000).map(|i| ((i * 7) % 30) as u32).collect(), (0..2000).map(|i| (60 + i % 20) as u32).collect())
+    };
+    c.append_ids(a, &ids_a).unwrap();
+    c.strings(b).unwrap().push_ids(&ids_b);
+    let decode = |c: &mut Catalog, ids: &[u32]| -> Vec<String> {
+      let dict = c.dictionary(d).unwrap();
+      ids.iter().map(|&id| String::from_utf8(dict.get(id).into_owned()).unwrap()).collect()
+    };
+    let (va, vb) = (decode(c, &ids_a), decode(c, &ids_b));
+    (d, a, b, va, vb)
+  }
+
+  fn decoded(c: &mut Catalog, d: DictionaryId, col: ColumnId) -> Vec<String> {
+    if c.column(col).unwrap().column_type == ColumnType::String {
+      return c.strings(col).unwrap().iter().map(Option::unwrap).collect();
+    }
+    let ids : Vec<u32> = c.ids(col).unwrap().collect();
+    let dict = c.dictionary(d).unwrap();
+    ids.iter().map(|&id| String::from_utf8(dict.get(id).into_owned()).unwrap()).collect()
+  }
+
+  #[test]
+  pub fn compact() {
+    for &sorted in [false, true].iter() {
+      let mut pp = MemoryPageProvider::new();
+      let mut c = Catalog::new(&mut pp);
+      let (d, a, b, va, vb) = compaction_setup(&mut c, sorted);
+
+      // An in memory array of IDs keeps value 95 alive
+      let mut extra = BitArray::new(2, 7);
+      extra.put(0, 95);
+      extra.put(1, 5);
+      let mut journal = MemoryJournal::default();
+      let result = c.compact_dictionary(d, &mut [&mut extra], &mut journal).unwrap();
+      assert!(result.kept() == 51);
+      assert!(result.remap[95] == 50 && result.remap[30] == REMOVED);
+      assert!(c.dictionary(d).unwrap().len() == 51);
+      assert!(decoded(&mut c, d, a) == va);
+      assert!(decoded(&mut c, d, b) == vb);
+      assert!(extra.get(0) == 50 && extra.get(1) == 5);
+      assert!(journal.entries.len() == 2);
+      assert!(journal.entries[1] == Entry::CompactDone { dictionary: d });
+      if sorted {
+        let dict = c.dictionary(d).unwrap();
+        assert!(dict.sorted().unwrap().sorted_ids().len() == 51);
+      }
+      // Nothing left to remove while extra is still passed in, then value 95 goes
+      assert!(c.compact_dictionary(d, &mut [&mut extra], &mut journal).unwrap().kept() == 51);
+      assert!(c.compact_dictionary(d, &mut [], &mut journal).unwrap().kept() == 50);
+      assert!(decoded(&mut c, d, b) == vb);
+
+      // IDs past the end of the dictionary are an error before anything is written
+      let mut bad = BitArray::new(1, 7);
+      bad.put(0, 100);
+      let entries = journal.entries.len();
+      assert!(matches!(c.compact_dictionary(d, &mut [&mut bad], &mut journal), Err(CatalogError::NotFound(_))));
+      assert!(journal.entries.len() == entries);
+    }
+  }
+
+  #[test]
+  pub fn compact_recovery() {
+    let mut pp = MemoryPageProvider::new();
+    let (header, d, a, b, va, vb) = {
+      let mut c = Catalog::new(&mut pp);
+      let (d, a, b, va, vb) = compaction_setup(&mut c, false);
+      // Crash before the commit, the new pages are never used
+      c.prepare_compaction(d, &[]).unwrap();
+      (c.header_page(), d, a, b, va, vb)
+    };
+    let mut journal = MemoryJournal::default();
+    let mut c = Catalog::open(&mut pp, header);
+    assert!(c.recover(&[], &mut journal).unwrap() == 0);
+    assert!(c.dictionary(d).unwrap().len() == 100);
+    assert!(decoded(&mut c, d, a) == va);
+
+    // Crash after the commit, recovery finishes the swap
+    let prepared = c.prepare_compaction(d, &[]).unwrap();
+    journal.add(&prepared.entry()).unwrap();
+    let mut c = Catalog::open(&mut pp, header);
+    assert!(c.dictionary(d).unwrap().len() == 100);
+    let entries = journal.entries.clone();
+    assert!(c.recover(&entries, &mut journal).unwrap() == 1);
+    assert!(c.dictionary(d).unwrap().len() == 50);
+    assert!(decoded(&mut c, d, a) == va);
+    assert!(decoded(&mut c, d, b) == vb);
+    let entries = journal.entries.clone();
+    assert!(c.recover(&entries, &mut journal).unwrap() == 0);
+  }
+
+  #[test]
+  pub fn string_columns() {
+    let mut pp = MemoryPageProvider::new();
+    let header = {
+      let mut c = Catalog::new(&mut pp);
+      let d = c.create_dictionary("city", true).unwrap();
+      let home = c.add_column("people", "home", ColumnType::String, Some(d)).unwrap();
+      let work = c.add_column("people", "work", ColumnType::String, Some(d)).unwrap();
+      ["leeds", "york", "leeds"].iter().for_each(|v| c.strings(home).unwrap().push(v));
+      ["york", "hull", "leeds"].iter().for_each(|v| c.strings(work).unwrap().push(v));
+      assert!(c.shares_dictionary(home, work).unwrap());
+      c.header_page()
+    };
+
+    let mut c = Catalog::open(&mut pp, header);
+    assert!(c.rows(0).unwrap() == 3);
+    assert!(c.dictionary(0).unwrap().len() == 3);
+    let home : Vec<u32> = c.strings(0).unwrap().ids().collect();
+    let work = c.strings(1).unwrap();
+    assert!(work.iter().eq(["york", "hull", "leeds"].iter().map(|v| Some(v.to_string()))));
+    // Same value, same ID
+    assert!(home[0] == work.id(2) && home[0] != work.id(0));
+    assert!(work.select_eq("leeds").iter().eq(vec![2]));
+  }
+
+  #[test]
+  pub fn rewrite_rows() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    let d = c.create_dictionary("city", false).unwrap();
+    let n = c.add_column("t", "n", ColumnType::Int, None).unwrap();
+    let city = c.add_column("t", "city", ColumnType::String, Some(d)).unwrap();
+    c.append_ints(n, &(0..10).collect::<Vec<i64>>()).unwrap();
+    (0..10).for_each(|i| c.strings(city).unwrap().push(["leeds", "york"][i % 2]));
+
+    c.update_ints(n, &[1, 3], 100).unwrap();
+    assert!(c.ints(n).unwrap().eq(vec![0, 100, 2, 100, 4, 5, 6, 7, 8, 9]));
+    // A new value and NULL, both needing more bits than the column had
+    let hull = c.dictionary(d).unwrap().add(b"hull");
+    c.update_ids(city, &[0, 5], hull).unwrap();
+    c.update_ids(city, &[9], NULL_ID).unwrap();
+    let values : Vec<Option<String>> = { let s = c.strings(city).unwrap(); (0..10).map(|i| s.value(i)).collect() };
+    assert!(values[0].as_deref() == Some("hull") && values[5].as_deref() == Some("hull") && values[9].is_none());
+    assert!(values[1].as_deref() == Some("york") && values[2].as_deref() == Some("leeds"));
+
+    // Updates are in place, the String column's IDs are only repacked once they need more bits
+    let data = c.column_entry(city).unwrap().data;
+    assert!(c.strings(city).unwrap().width() == 2);
+    let ids = c.add_column("t", "ids", ColumnType::Ids, Some(d)).unwrap();
+    c.append_ids(ids, &[0; 10]).unwrap();
+    let ids_data = c.column_entry(ids).unwrap().data;
+    c.update_ids(ids, &[2, 4], hull).unwrap();
+    assert!(c.ids(ids).unwrap().eq(vec![0, 0, 2, 0, 2, 0, 0, 0, 0, 0]));
+    let more : Vec<u32> = { let mut dict = c.dictionary(d).unwrap(); (0..5).map(|i| dict.add(format!("town{}", i).as_bytes())).collect() };
+    c.update_ids(city, &[1], more[4]).unwrap();
+    assert!(c.strings(city).unwrap().width() == 4 && c.strings(city).unwrap().value(1).as_deref() == Some("town4"));
+    assert!(c.column_entry(city).unwrap().data == data && c.column_entry(ids).unwrap().data == ids_data);
+    assert!(matches!(c.update_ids(ids, &[0], 100), Err(CatalogError::NotFound(_))));
+    assert!(matches!(c.update_ids(ids, &[10], hull), Err(CatalogError::NotFound(_))));
+    c.update_ids(city, &[1], 1).unwrap();
+
+    c.retain_rows("t", &[9, 1, 2]).unwrap();
+    assert!(c.rows(n).unwrap() == 3 && c.rows(city).unwrap() == 3);
+    assert!(c.ints(n).unwrap().eq(vec![9, 100, 2]));
+    let values : Vec<Option<String>> = { let s = c.strings(city).unwrap(); (0..3).map(|i| s.value(i)).collect() };
+    assert!(values == vec![None, Some("york".to_string()), Some("leeds".to_string())]);
+
+    assert!(matches!(c.update_ints(city, &[0], 1), Err(CatalogError::WrongType(_))));
+    assert!(matches!(c.update_ints(n, &[3], 1), Err(CatalogError::NotFound(_))));
+    // A failed update changes no rows
+    assert!(matches!(c.update_ints(n, &[0, 1, 7], 5), Err(CatalogError::NotFound(_))));
+    assert!(c.ints(n).unwrap().eq(vec![9, 100, 2]));
+    assert!(matches!(c.retain_rows("t", &[5]), Err(CatalogError::NotFound(_))));
+  }
+}
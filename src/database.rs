@@ -46,13 +46,13 @@ union FreeListBody {
 #[repr(C)]
 #[derive(Copy, Clone)]
 struct FreeListLeaf {
-  d: [u8; (PAGE_SIZE - 4)],
+  d: [u8; PAGE_SIZE - 4],
 }
 
 #[repr(C)]
 #[derive(Copy, Clone)]
 struct FreeListPtrs {
-  d: [u32; ((PAGE_SIZE - 4) / 4)],
+  d: [u32; (PAGE_SIZE - 4) / 4],
 }
 
 #[repr(C)]
@@ -95,11 +95,28 @@ pub trait PageProvider {
   fn mut_page(&mut self, i: u32) -> (&mut dyn PageProvider, &mut Page);
   fn page(&self, i: u32) -> &Page;
   fn index_of(&self, page: &Page) -> u32;
+
+  // Raw page for structures with their own page layout
+  fn page_bytes(&self, i: u32) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(self.page(i) as *const Page as *const u8, PAGE_SIZE) }
+  }
+
+  fn mut_page_bytes(&mut self, i: u32) -> &mut [u8] {
+    let (_, page) = self.mut_page(i);
+    unsafe { std::slice::from_raw_parts_mut(page as *mut Page as *mut u8, PAGE_SIZE) }
+  }
+
+  // Makes every page written so far durable, in memory pages have nowhere to go
+  fn sync(&mut self) -> Result<(), DbError> {
+    Ok(())
+  }
 }
 
 
+// Pages are u64s so they're aligned for any page layout
+#[derive(Default)]
 pub struct MemoryPageProvider {
-  pages: Vec<Vec<u8>>
+  pages: Vec<Vec<u64>>
 }
 
 impl MemoryPageProvider {
@@ -109,25 +126,28 @@ impl MemoryPageProvider {
 }
 
 impl PageProvider for MemoryPageProvider {
-  fn alloc(&mut self, _count: usize) -> Vec<u32> {
-    self.pages.push(Vec::with_capacity(PAGE_SIZE));
-    vec![(self.pages.len() - 1) as u32]
+  fn alloc(&mut self, count: usize) -> Vec<u32> {
+    let first = self.pages.len();
+    for _ in 0..count {
+      self.pages.push(vec![0; PAGE_SIZE / 8]);
+    }
+    (first as u32..self.pages.len() as u32).collect()
   }
 
   fn page(&self, i: u32) -> &Page {
     let page = self.pages[i as usize].as_ptr();
-    unsafe { & *(page as *const u8 as *const Page) }
+    unsafe { & *(page as *const Page) }
   }
 
   fn mut_page(&mut self, i: u32) -> (&mut dyn PageProvider, &mut Page) {
     let page = self.pages[i as usize].as_mut_ptr();
-    (self, unsafe { &mut *(page as *const u8 as *mut Page) })
+    (self, unsafe { &mut *(page as *mut Page) })
   }
 
   fn index_of(&self, page: &Page) -> u32 {
-    let ptr = page as *const Page as *const u8;
+    let ptr = page as *const Page as *const u64;
     for i in 0..self.pages.len() {
-      if (&(self.pages[i])[0] as *const u8) == ptr {
+      if std::ptr::eq(self.pages[i].as_ptr(), ptr) {
         return i as u32
       }
     }
@@ -158,7 +178,7 @@ impl Database {
       .map_err(DbError::Io)?;
     let mmap = unsafe { MmapMut::map_mut(&file) }.map_err(DbError::Io)?;
 
-    let db = Database { mmap: mmap };
+    let db = Database { mmap };
 
     let hdr = db.header();
     *hdr = INIT_HEADER;
@@ -172,10 +192,17 @@ impl Database {
     Ok(db)
   }
 
+  // Flushes the mapped pages to the file
+  pub fn sync(&self) -> Result<(), DbError> {
+    self.mmap.flush().map_err(DbError::Io)
+  }
+
+  #[allow(clippy::mut_from_ref)]
   fn header(&self) -> &mut Header {
     unsafe { &mut *(self.mmap.as_ptr() as *mut Header) }
   }
 
+  #[allow(clippy::mut_from_ref)]
   fn free_list(&self) -> &mut FreeList {
     unsafe {
       let header = self.header();
@@ -189,7 +216,7 @@ impl Database {
 }
 
 impl FreeList {
-  fn init(&mut self) -> () {
+  fn init(&mut self) {
     assert_eq!(PAGE_SIZE, mem::size_of::<FreeList>());
     self.version = FREE_LIST_VERSION;
     self.depth = 0;
@@ -220,7 +247,7 @@ impl FreeListLeaf {
   fn set(&mut self, index: u32) {
     let byte = (index as usize) >> 3;
     let bit = index & 7;
-    self.d[byte] = self.d[byte] | (1 << bit);
+    self.d[byte] |= 1 << bit;
   }
 
   fn set_arr(&mut self, is: &[u32]) {
@@ -257,7 +284,7 @@ impl FreeListLeaf {
                 run = 1;
                 index = r;
               } else {
-                run = run + 1;
+                run += 1;
               }
             } else {
               run = 0
@@ -273,6 +300,7 @@ impl FreeListLeaf {
 
 #[cfg(test)]
 pub mod tests {
+  #[allow(unused_imports)]
   use super::*;
 
   #[test]
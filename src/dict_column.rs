@@ -0,0 +1,464 @@
+// Dictionary encoded column, each row is the ID of its value packed into width bits
+// IDs are stored plus one so a NULL row packs as 0 without widening the column
+// The dictionary may be shared with other columns, see Catalog, and is an ArrayDictionary or a
+// SortedDictionary behind CatalogDictionary
+// Width starts at 1 bit and grows as the dictionary does, repacking the IDs when it changes
+// Predicates can be tested against the IDs, or once per dictionary value and then against the IDs
+// A header page records the dictionary and the packed IDs, it's the only page to keep
+
+#![allow(dead_code)]
+
+use crate::bitmap::{Bitmap};
+use crate::database::{PageProvider};
+use crate::dictionary::{self, ArrayDictionary, Dictionary};
+use crate::packed_vector::{self, Packed, PackedVectorIterator};
+use crate::sorted_dictionary::{SortedDictionary};
+use std::borrow::Cow;
+
+const DICT_COLUMN_VERSION: u8 = 0;
+// Value that stands for NULL in Ids and String columns, it can't be stored
+pub const NULL_ID: u32 = u32::MAX;
+
+// Values a DictColumn can hold, stored in the dictionary as bytes
+pub trait DictValue {
+  type Ref: ?Sized;
+  fn bytes(v: &Self::Ref) -> &[u8];
+  fn decode(bytes: &[u8]) -> Self;
+}
+
+impl DictValue for String {
+  type Ref = str;
+  fn bytes(v: &str) -> &[u8] {
+    v.as_bytes()
+  }
+
+  fn decode(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+  }
+}
+
+impl DictValue for Vec<u8> {
+  type Ref = [u8];
+  fn bytes(v: &[u8]) -> &[u8] {
+    v
+  }
+
+  fn decode(bytes: &[u8]) -> Vec<u8> {
+    bytes.to_vec()
+  }
+}
+
+// A catalog dictionary, sorted ones also keep their IDs in value order
+pub enum CatalogDictionary<'a> {
+  Array(ArrayDictionary<'a, u8>),
+  Sorted(SortedDictionary<'a, u8>),
+}
+
+impl<'a> CatalogDictionary<'a> {
+  pub fn open(db: &'a mut dyn PageProvider, header: u32, sorted: bool) -> CatalogDictionary<'a> {
+    if sorted {
+      CatalogDictionary::Sorted(SortedDictionary::open(db, header))
+    } else {
+      CatalogDictionary::Array(ArrayDictionary::open(db, header))
+    }
+  }
+
+  // Header page of the values, for reads through dictionary::get
+  pub fn values_page(&self) -> u32 {
+    match self {
+      CatalogDictionary::Array(d) => d.header_page(),
+      CatalogDictionary::Sorted(d) => d.values_page(),
+    }
+  }
+
+  pub fn add(&mut self, v: &[u8]) -> u32 {
+    match self {
+      CatalogDictionary::Array(d) => d.add(v),
+      CatalogDictionary::Sorted(d) => d.add(v),
+    }
+  }
+
+  pub fn lookup(&self, v: &[u8]) -> Option<u32> {
+    match self {
+      CatalogDictionary::Array(d) => d.lookup(v),
+      CatalogDictionary::Sorted(d) => d.lookup(v),
+    }
+  }
+
+  pub fn get(&self, id: u32) -> Cow<'_, [u8]> {
+    match self {
+      CatalogDictionary::Array(d) => d.get(id),
+      CatalogDictionary::Sorted(d) => d.get(id),
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      CatalogDictionary::Array(d) => d.len(),
+      CatalogDictionary::Sorted(d) => d.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn sorted(&self) -> Option<&SortedDictionary<'a, u8>> {
+    match self {
+      CatalogDictionary::Array(_) => None,
+      CatalogDictionary::Sorted(d) => Some(d),
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DictColumnHeader {
+  version: u8,
+  sorted: u8,
+  width: u8,
+  padding: u8,
+  dictionary: u32, // Header page to open the dictionary with
+  values: u32,     // Header page of its ArrayDictionary, for reads
+  words: u32,
+  entries: u64,
+}
+
+pub struct DictColumn<'a, V> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  dictionary: u32,
+  values: u32,
+  sorted: bool,
+  ids: Packed,
+  _dummy: std::marker::PhantomData<V>,
+}
+
+fn read_header(db: &dyn PageProvider, header: u32) -> DictColumnHeader {
+  let h = unsafe { *(db.page_bytes(header).as_ptr() as *const DictColumnHeader) };
+  assert!(h.version == DICT_COLUMN_VERSION, "Unknown dictionary column version {} on page {}", h.version, header);
+  h
+}
+
+pub type IdIterator<'b> = std::iter::Map<PackedVectorIterator<'b>, fn(u64) -> u32>;
+
+// 0 wraps round to NULL_ID
+fn stored_id(v: u64) -> u32 {
+  (v as u32).wrapping_sub(1)
+}
+
+// IDs of a column from row i, for structures that share its PageProvider
+pub(crate) fn ids_from(header: u32, i: usize, db: &dyn PageProvider) -> IdIterator<'_> {
+  let h = read_header(db, header);
+  Packed { words: h.words, width: h.width, entries: h.entries as usize }.iter_from(i, db).map(stored_id as fn(u64) -> u32)
+}
+
+pub(crate) fn ids(header: u32, db: &dyn PageProvider) -> IdIterator<'_> {
+  ids_from(header, 0, db)
+}
+
+pub(crate) fn len(header: u32, db: &dyn PageProvider) -> usize {
+  read_header(db, header).entries as usize
+}
+
+impl<'a, V: DictValue> DictColumn<'a, V> {
+  // Column with a dictionary of its own
+  pub fn new(db: &'a mut dyn PageProvider) -> DictColumn<'a, V> {
+    let dictionary = ArrayDictionary::<u8>::new(db).header_page();
+    DictColumn::with_dictionary(db, dictionary, false)
+  }
+
+  // Column using an existing dictionary, from its header_page
+  pub fn with_dictionary(db: &'a mut dyn PageProvider, dictionary: u32, sorted: bool) -> DictColumn<'a, V> {
+    let values = CatalogDictionary::open(db, dictionary, sorted).values_page();
+    let header = db.alloc(1)[0];
+    let ids = Packed::new(1, db);
+    let mut c = DictColumn { db, header, dictionary, values, sorted, ids, _dummy: std::marker::PhantomData };
+    c.write_header();
+    c
+  }
+
+  // Column previously created whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> DictColumn<'a, V> {
+    let h = read_header(db, header);
+    let ids = Packed { words: h.words, width: h.width, entries: h.entries as usize };
+    DictColumn { db, header, dictionary: h.dictionary, values: h.values, sorted: h.sorted != 0, ids, _dummy: std::marker::PhantomData }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  pub fn dictionary_page(&self) -> u32 {
+    self.dictionary
+  }
+
+  // The packed IDs move as they grow
+  fn write_header(&mut self) {
+    let h = DictColumnHeader {
+      version: DICT_COLUMN_VERSION,
+      sorted: self.sorted as u8,
+      width: self.ids.width,
+      padding: 0,
+      dictionary: self.dictionary,
+      values: self.values,
+      words: self.ids.words,
+      entries: self.ids.entries as u64,
+    };
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut DictColumnHeader) = h };
+  }
+
+  pub fn dictionary(&mut self) -> CatalogDictionary<'_> {
+    CatalogDictionary::open(self.db, self.dictionary, self.sorted)
+  }
+
+  // Number of values in the dictionary, IDs are below this
+  pub fn dictionary_len(&self) -> usize {
+    dictionary::len(self.values, self.db)
+  }
+
+  pub fn width(&self) -> u8 {
+    self.ids.width
+  }
+
+  pub fn push(&mut self, v: &V::Ref) {
+    let id = self.dictionary().add(V::bytes(v));
+    self.push_ids(&[id]);
+  }
+
+  pub fn push_null(&mut self) {
+    self.push_ids(&[NULL_ID]);
+  }
+
+  // IDs must already be in the dictionary or be NULL_ID
+  pub fn push_ids(&mut self, ids: &[u32]) {
+    let stored : Vec<u64> = ids.iter().map(|&id| id.wrapping_add(1) as u64).collect();
+    let max = match stored.iter().max() {
+      Some(&m) => m,
+      None => return,
+    };
+    assert!((max as usize) <= self.dictionary_len(), "ID {} not in a dictionary of {} values", max - 1, self.dictionary_len());
+    let width = packed_vector::width_for(max);
+    if width > self.ids.width {
+      self.ids = self.ids.repack(width, self.db);
+    }
+    self.ids.append(&stored, self.db);
+    self.write_header();
+  }
+
+  // Sets rows to id in place, repacking first if id needs more bits than the column has
+  // IDs must already be in the dictionary or be NULL_ID, false if a row is past the end
+  pub fn set_ids(&mut self, rows: &[usize], id: u32) -> bool {
+    if rows.iter().any(|&r| r >= self.ids.entries) {
+      return false;
+    }
+    let stored = id.wrapping_add(1) as u64;
+    assert!((stored as usize) <= self.dictionary_len(), "ID {} not in a dictionary of {} values", id, self.dictionary_len());
+    let width = packed_vector::width_for(stored);
+    if width > self.ids.width {
+      self.ids = self.ids.repack(width, self.db);
+    }
+    rows.iter().for_each(|&r| { self.ids.set(r, stored, self.db); });
+    self.write_header();
+    true
+  }
+
+  pub fn len(&self) -> usize {
+    self.ids.entries
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn id(&self, i: usize) -> u32 {
+    match self.try_id(i) {
+      Some(id) => id,
+      None => panic!("Index {} out of bounds for DictColumn of length {}", i, self.len()),
+    }
+  }
+
+  // NULL_ID for NULL rows
+  pub fn try_id(&self, i: usize) -> Option<u32> {
+    self.ids.get(i, self.db).map(stored_id)
+  }
+
+  // Panics on NULL rows, see value
+  pub fn get(&self, i: usize) -> V {
+    self.decode(self.id(i))
+  }
+
+  // None for NULL rows
+  pub fn value(&self, i: usize) -> Option<V> {
+    match self.id(i) {
+      NULL_ID => None,
+      id => Some(self.decode(id)),
+    }
+  }
+
+  // None past the end, Some(None) for NULL rows
+  pub fn try_get(&self, i: usize) -> Option<Option<V>> {
+    self.try_id(i).map(|id| if id == NULL_ID { None } else { Some(self.decode(id)) })
+  }
+
+  pub fn decode(&self, id: u32) -> V {
+    assert!(id != NULL_ID, "NULL has no value to decode");
+    V::decode(&dictionary::get::<u8>(self.values, id, self.db).unwrap())
+  }
+
+  // ID of v if any row could hold it
+  pub fn lookup(&self, v: &V::Ref) -> Option<u32> {
+    dictionary::lookup::<u8>(self.values, V::bytes(v), self.db)
+  }
+
+  pub fn ids(&self) -> IdIterator<'_> {
+    self.ids.iter_from(0, self.db).map(stored_id as fn(u64) -> u32)
+  }
+
+  // None for NULL rows
+  pub fn iter(&self) -> DictColumnIterator<'a, '_, V> {
+    DictColumnIterator { column: self, ids: self.ids() }
+  }
+
+  // Rows whose ID matches f
+  pub fn select_ids<F: Fn(u32) -> bool>(&self, f: F) -> Bitmap {
+    let mut b = Bitmap::new(self.len());
+    self.ids().enumerate().filter(|&(_, id)| f(id)).for_each(|(i, _)| b.set(i));
+    b
+  }
+
+  // Rows whose ID is set in ids, such as a SortedDictionary range
+  pub fn select_in(&self, ids: &Bitmap) -> Bitmap {
+    self.select_ids(|id| (id as usize) < ids.len() && ids.get(id as usize))
+  }
+
+  pub fn select_eq(&self, v: &V::Ref) -> Bitmap {
+    match self.lookup(v) {
+      Some(id) => self.select_ids(|x| x == id),
+      None => Bitmap::new(self.len()),
+    }
+  }
+
+  // Rows whose value matches f, which is called once per dictionary value rather than per row
+  pub fn select_values<F: Fn(&V) -> bool>(&self, f: F) -> Bitmap {
+    let mut matches = Bitmap::new(self.dictionary_len());
+    (0..matches.len()).filter(|&id| f(&self.decode(id as u32))).for_each(|id| matches.set(id));
+    self.select_in(&matches)
+  }
+}
+
+pub struct DictColumnIterator<'a, 'b, V> {
+  column: &'b DictColumn<'a, V>,
+  ids: IdIterator<'b>,
+}
+
+impl<'a, 'b, V: DictValue> Iterator for DictColumnIterator<'a, 'b, V> {
+  type Item = Option<V>;
+  fn next(&mut self) -> Option<Option<V>> {
+    self.ids.next().map(|id| if id == NULL_ID { None } else { Some(self.column.decode(id)) })
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    self.ids.size_hint()
+  }
+}
+
+impl<'a, 'b, V: DictValue> ExactSizeIterator for DictColumnIterator<'a, 'b, V> {}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use crate::sorted_dictionary::{SortedDictionary};
+
+  fn city(i: usize) -> String {
+    format!("city{}", (i * 7919) % 300)
+  }
+
+  #[test]
+  pub fn push_get() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = DictColumn::<String>::new(&mut pp);
+    assert!(c.is_empty());
+    assert!(c.try_get(0).is_none());
+
+    let values : Vec<String> = (0..5000).map(city).collect();
+    for v in values.iter() {
+      c.push(v);
+    }
+    assert!(c.len() == 5000);
+    assert!(c.dictionary_len() == 300);
+    // 300 values need 9 bits per row
+    assert!(c.width() == 9);
+    for (i, v) in values.iter().enumerate() {
+      assert!(c.get(i) == *v);
+    }
+    assert!(c.iter().len() == 5000);
+    assert!(c.iter().eq(values.iter().cloned().map(Some)));
+    assert!(c.ids().map(|id| c.decode(id)).eq(values.iter().cloned()));
+
+    // NULL packs as 0, so it doesn't need a wider column
+    c.push_null();
+    c.push("city1");
+    assert!(c.width() == 9 && c.len() == 5002);
+    assert!(c.id(5000) == NULL_ID && c.value(5000).is_none());
+    assert!(c.value(5001) == Some("city1".to_string()));
+    assert!(c.try_get(5000) == Some(None) && c.try_get(5001) == Some(Some("city1".to_string())) && c.try_get(5002).is_none());
+    let mut expected : Vec<Option<String>> = values.iter().cloned().map(Some).collect();
+    expected.push(None);
+    expected.push(Some("city1".to_string()));
+    assert!(c.iter().eq(expected.iter().cloned()));
+    assert!(c.select_eq("city1").iter().last() == Some(5001));
+  }
+
+  #[test]
+  pub fn scans() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = DictColumn::<String>::new(&mut pp);
+    let values : Vec<String> = (0..3000).map(city).collect();
+    values.iter().for_each(|v| c.push(v));
+
+    let expected = |f: &dyn Fn(&String) -> bool| -> Vec<usize> { (0..values.len()).filter(|&i| f(&values[i])).collect() };
+    assert!(c.select_eq("city42").iter().eq(expected(&|v| v == "city42")));
+    assert!(c.select_eq("nowhere").count() == 0);
+    assert!(c.select_values(|v| v.ends_with('7')).iter().eq(expected(&|v| v.ends_with('7'))));
+    let id = c.lookup("city1").unwrap();
+    assert!(c.select_ids(|x| x == id).iter().eq(expected(&|v| v == "city1")));
+  }
+
+  #[test]
+  pub fn shared_sorted_dictionary() {
+    let mut pp = MemoryPageProvider::new();
+    let dictionary = SortedDictionary::<u8>::new(&mut pp).header_page();
+    let a = {
+      let mut a = DictColumn::<String>::with_dictionary(&mut pp, dictionary, true);
+      (0..1000).for_each(|i| a.push(&city(i)));
+      a.header_page()
+    };
+    let b = {
+      let mut b = DictColumn::<String>::with_dictionary(&mut pp, dictionary, true);
+      (500..1500).for_each(|i| b.push(&city(i)));
+      b.header_page()
+    };
+
+    // Equal values have equal IDs in both columns
+    let ids_a : Vec<u32> = ids(a, &pp).collect();
+    let ids_b : Vec<u32> = ids(b, &pp).collect();
+    assert!(ids_a[500..] == ids_b[..500]);
+    assert!(len(b, &pp) == 1000);
+
+    // A range on the sorted dictionary becomes a set of IDs
+    let mut c = DictColumn::<String>::open(&mut pp, a);
+    let range = match c.dictionary() {
+      CatalogDictionary::Sorted(d) => d.range(std::ops::Bound::Included(b"city2"), std::ops::Bound::Excluded(b"city3")),
+      CatalogDictionary::Array(_) => panic!("Dictionary should be sorted"),
+    };
+    let expected : Vec<usize> = (0..1000).filter(|&i| city(i).as_str() >= "city2" && city(i).as_str() < "city3").collect();
+    assert!(c.select_in(&range).iter().eq(expected));
+    assert!(c.get(999) == city(999));
+  }
+}
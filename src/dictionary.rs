@@ -1,12 +1,54 @@
 // Dictionary that works over a set of pages
-#![allow(unused_variables)]
+// IDs are positions in refs so they never change as values are added
+// A header page records where refs and arr start, it's the only page a column needs to keep
+//  refs: PagedVector<ArrayPosition> the span of each value in arr, indexed by ID
+//  arr: PagedVector<T> the values back to back
+//  index: HashIndex from the hash of a value to its ID, so lookup needn't scan or rebuild on open
+//  filters: optional PagedVector<PageFilter>, a 128 bit mask of value hashes per group of IDs
+// Without the index lookups scan, the filters let them skip groups that can't hold the value
+// 128 bits only help if groups are small, a few dozen values fill most of the mask
 #![allow(dead_code)]
 
+use crate::database::{PageProvider};
+use crate::hash_index::{HashIndex};
+use crate::paged_vector::{self};
+use fnv::{FnvHasher};
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
 
-use crate::paged_vector::{PagedVector};
+// Version 0 headers end after index, open reads them as having no filters
+const DICTIONARY_VERSION: u8 = 1;
+// Header flags, zero is an index and no filters
+const NO_INDEX: u8 = 1;
+// Bits set in a group's filter for each value
+const FILTER_HASHES: u32 = 2;
 
-trait Dictionary<T> {
-  fn append(&self, vs: &[T]) -> u32;
+#[derive(Clone, Copy, Debug)]
+pub struct DictionaryOptions {
+  pub index: bool,
+  pub filter_ids: u32, // IDs per filter, 0 for no filters
+}
+
+impl Default for DictionaryOptions {
+  fn default() -> DictionaryOptions {
+    DictionaryOptions { index: true, filter_ids: 0 }
+  }
+}
+
+pub trait Dictionary<T> {
+  // ID of v, adding it if it's new
+  fn add(&mut self, v: T) -> u32;
+  fn lookup(&self, v: T) -> Option<u32>;
+  fn len(&self) -> usize;
+
+  fn append(&mut self, vs: &[T]) -> Vec<u32> where T: Copy {
+    vs.iter().map(|&v| self.add(v)).collect()
+  }
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 }
 
 
@@ -18,16 +60,403 @@ struct ArrayPosition {
   len : u32
 }
 
-struct ArrayDictionary<'a, T> {
-  refs: PagedVector<'a, ArrayPosition>,
-  arr: PagedVector<'a, T>,
-  entries: u32,
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DictionaryHeader {
+  version: u8,
+  flags: u8,
+  padding: [u8; 2],
+  refs: u32, // Entry page of refs
+  arr: u32,  // Entry page of arr
+  index: HashIndex,
+  filters: u32, // Entry page of filters
+  filter_ids: u32,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct DictionaryHeaderV0 {
+  version: u8,
+  padding: [u8; 3],
+  refs: u32,
+  arr: u32,
+  index: HashIndex,
+}
 
-impl<'a, T> Dictionary<&[T]> for ArrayDictionary<'a, T> {
-  fn append(&self, vs: &[&[T]]) -> u32 {
-    0
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct PageFilter {
+  bits: [u64; 2],
+}
+
+impl PageFilter {
+  fn mask(hash: u64) -> PageFilter {
+    // High bits, the index buckets on the low ones
+    let mut bits = [0u64; 2];
+    for i in 0..FILTER_HASHES {
+      let b = (hash >> (57 - 7 * i)) & 127;
+      bits[(b >> 6) as usize] |= 1 << (b & 63);
+    }
+    PageFilter { bits }
+  }
+
+  fn contains(&self, mask: &PageFilter) -> bool {
+    self.bits[0] & mask.bits[0] == mask.bits[0] && self.bits[1] & mask.bits[1] == mask.bits[1]
+  }
+}
+
+pub struct ArrayDictionary<'a, T> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  state: DictionaryHeader,
+  _dummy: std::marker::PhantomData<T>,
+}
+
+// Older headers are upgraded in memory and written back as the current version on the next add
+fn read_header(db: &dyn PageProvider, page: u32) -> DictionaryHeader {
+  let bytes = db.page_bytes(page);
+  if bytes[0] == 0 {
+    let h = unsafe { *(bytes.as_ptr() as *const DictionaryHeaderV0) };
+    return DictionaryHeader {
+      version: DICTIONARY_VERSION,
+      flags: 0,
+      padding: [0; 2],
+      refs: h.refs,
+      arr: h.arr,
+      index: h.index,
+      filters: 0,
+      filter_ids: 0,
+    };
   }
+  let h = unsafe { *(bytes.as_ptr() as *const DictionaryHeader) };
+  assert!(h.version == DICTIONARY_VERSION, "Unknown dictionary version {} on page {}", h.version, page);
+  h
+}
 
-}
\ No newline at end of file
+fn hash<T: Hash>(v: &[T]) -> u64 {
+  let mut hasher = FnvHasher::default();
+  v.hash(&mut hasher);
+  hasher.finish()
+}
+
+fn value<T: Copy>(refs: u32, arr: u32, id: u32, db: &dyn PageProvider) -> Option<Cow<'_, [T]>> {
+  let p = *paged_vector::get::<ArrayPosition>(refs, id as usize, db)?;
+  let pos = p.pos as usize;
+  paged_vector::read_range::<T>(arr, pos..pos + p.len as usize, db)
+}
+
+// Groups of IDs whose filter matches hash, all of them without filters
+fn candidate_groups(h: &DictionaryHeader, hash: u64, db: &dyn PageProvider) -> Vec<std::ops::Range<u32>> {
+  let len = paged_vector::len::<ArrayPosition>(h.refs, db) as u32;
+  if h.filter_ids == 0 {
+    return std::iter::once(0..len).collect();
+  }
+  let mask = PageFilter::mask(hash);
+  let filters = paged_vector::len::<PageFilter>(h.filters, db);
+  paged_vector::iter_range::<PageFilter>(h.filters, 0..filters, db).enumerate()
+    .filter(|(_, f)| f.contains(&mask))
+    .map(|(g, _)| g as u32 * h.filter_ids..std::cmp::min(len, (g as u32 + 1) * h.filter_ids))
+    .collect()
+}
+
+fn find<T: Copy + PartialEq + Hash>(h: &DictionaryHeader, v: &[T], db: &dyn PageProvider) -> Option<u32> {
+  let hash = hash(v);
+  let matches = |id| *value::<T>(h.refs, h.arr, id, db).unwrap() == *v;
+  if h.flags & NO_INDEX == 0 {
+    return h.index.find(hash, db, matches);
+  }
+  candidate_groups(h, hash, db).into_iter().flatten().find(|&id| matches(id))
+}
+
+// Reads for structures that share their PageProvider, header is the dictionary's header page
+pub(crate) fn get<T: Copy>(header: u32, id: u32, db: &dyn PageProvider) -> Option<Cow<'_, [T]>> {
+  let h = read_header(db, header);
+  value(h.refs, h.arr, id, db)
+}
+
+pub(crate) fn lookup<T: Copy + PartialEq + Hash>(header: u32, v: &[T], db: &dyn PageProvider) -> Option<u32> {
+  find(&read_header(db, header), v, db)
+}
+
+pub(crate) fn len(header: u32, db: &dyn PageProvider) -> usize {
+  paged_vector::len::<ArrayPosition>(read_header(db, header).refs, db)
+}
+
+impl<'a, T: Debug + Copy + PartialEq + Hash> ArrayDictionary<'a, T> {
+  pub fn new(db: &'a mut dyn PageProvider) -> ArrayDictionary<'a, T> {
+    ArrayDictionary::with_options(db, DictionaryOptions::default())
+  }
+
+  // Without an index the pages for it are never allocated, lookups scan instead
+  pub fn with_options(db: &'a mut dyn PageProvider, options: DictionaryOptions) -> ArrayDictionary<'a, T> {
+    let header = db.alloc(1)[0];
+    let refs = paged_vector::bulk_load::<ArrayPosition>(&[], db);
+    let arr = paged_vector::bulk_load::<T>(&[], db);
+    let index = if options.index { HashIndex::new(db) } else { HashIndex::default() };
+    let filters = if options.filter_ids > 0 { paged_vector::bulk_load::<PageFilter>(&[], db) } else { 0 };
+    let state = DictionaryHeader {
+      version: DICTIONARY_VERSION,
+      flags: if options.index { 0 } else { NO_INDEX },
+      padding: [0; 2],
+      refs,
+      arr,
+      index,
+      filters,
+      filter_ids: options.filter_ids,
+    };
+    let mut d = ArrayDictionary { db, header, state, _dummy: std::marker::PhantomData };
+    d.write_header();
+    d
+  }
+
+  // Dictionary previously created with new whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> ArrayDictionary<'a, T> {
+    let state = read_header(db, header);
+    ArrayDictionary { db, header, state, _dummy: std::marker::PhantomData }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  // Entry pages move and the index state changes as values are added
+  fn write_header(&mut self) {
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut DictionaryHeader) = self.state };
+  }
+
+  pub fn options(&self) -> DictionaryOptions {
+    DictionaryOptions { index: self.state.flags & NO_INDEX == 0, filter_ids: self.state.filter_ids }
+  }
+
+  // Groups of IDs a scan for v would read and the number of groups, one group without filters
+  pub fn filter_hits(&self, v: &[T]) -> (usize, usize) {
+    let groups = candidate_groups(&self.state, hash(v), self.db);
+    let total = if self.state.filter_ids == 0 { 1 } else { paged_vector::len::<PageFilter>(self.state.filters, self.db) };
+    (groups.len(), total)
+  }
+
+  pub fn get(&self, id: u32) -> Cow<'_, [T]> {
+    match self.try_get(id) {
+      Some(v) => v,
+      None => panic!("ID {} out of bounds for ArrayDictionary of length {}", id, self.len()),
+    }
+  }
+
+  pub fn try_get(&self, id: u32) -> Option<Cow<'_, [T]>> {
+    value(self.state.refs, self.state.arr, id, self.db)
+  }
+
+  // Provider shared with structures layered over the dictionary
+  pub(crate) fn db(&self) -> &dyn PageProvider {
+    self.db
+  }
+
+  pub(crate) fn db_mut(&mut self) -> &mut dyn PageProvider {
+    self.db
+  }
+
+  pub fn iter(&self) -> ArrayDictionaryIterator<'a, '_, T> {
+    ArrayDictionaryIterator { dictionary: self, id: 0, end: self.len() as u32 }
+  }
+
+  // Adds v without checking whether it's already present
+  fn push(&mut self, v: &[T]) -> u32 {
+    let id = self.len() as u32;
+    let hash = hash(v);
+    let s = &mut self.state;
+    let p = ArrayPosition { pos: paged_vector::len::<T>(s.arr, self.db) as u64, len: v.len() as u32 };
+    s.arr = paged_vector::append_slice(s.arr, v, self.db);
+    s.refs = paged_vector::append_slice(s.refs, &[p], self.db);
+    if s.flags & NO_INDEX == 0 {
+      s.index.insert(hash, id, self.db);
+    }
+    if let Some(group) = id.checked_div(s.filter_ids) {
+      let group = group as usize;
+      let mask = PageFilter::mask(hash);
+      match paged_vector::get::<PageFilter>(s.filters, group, self.db).cloned() {
+        Some(f) => {
+          let bits = [f.bits[0] | mask.bits[0], f.bits[1] | mask.bits[1]];
+          paged_vector::set(s.filters, group, PageFilter { bits }, self.db);
+        },
+        None => s.filters = paged_vector::append_slice(s.filters, &[mask], self.db),
+      }
+    }
+    self.write_header();
+    id
+  }
+}
+
+impl<'a, 'b, T: Debug + Copy + PartialEq + Hash> Dictionary<&'b [T]> for ArrayDictionary<'a, T> {
+  fn add(&mut self, v: &'b [T]) -> u32 {
+    match self.lookup(v) {
+      Some(id) => id,
+      None => self.push(v),
+    }
+  }
+
+  // Only values with the same hash, or in groups whose filter matches, are compared
+  fn lookup(&self, v: &'b [T]) -> Option<u32> {
+    find(&self.state, v, self.db)
+  }
+
+  fn len(&self) -> usize {
+    paged_vector::len::<ArrayPosition>(self.state.refs, self.db)
+  }
+}
+
+pub struct ArrayDictionaryIterator<'a, 'b, T> {
+  dictionary: &'b ArrayDictionary<'a, T>,
+  id: u32,
+  end: u32,
+}
+
+impl<'a, 'b, T: Debug + Copy + PartialEq + Hash> Iterator for ArrayDictionaryIterator<'a, 'b, T> {
+  type Item = Cow<'b, [T]>;
+  fn next(&mut self) -> Option<Cow<'b, [T]>> {
+    if self.id >= self.end {
+      return None;
+    }
+    let v = self.dictionary.try_get(self.id);
+    self.id += 1;
+    v
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  #[test]
+  pub fn add() {
+    let mut pp = MemoryPageProvider::new();
+    let mut d = ArrayDictionary::<u8>::new(&mut pp);
+    assert!(d.is_empty());
+    assert!(d.add(b"This is a test") == 0);
+    assert!(d.add(b"And another test") == 1);
+    assert!(d.add(b"This is a test") == 0);
+    assert!(d.add(b"") == 2);
+    assert!(d.add(b"") == 2);
+    assert!(d.lookup(b"And another test") == Some(1));
+    assert!(d.lookup(b"Missing").is_none());
+    assert!(d.len() == 3);
+    assert!(&*d.get(0) == b"This is a test");
+    assert!(d.get(2).is_empty());
+    assert!(d.try_get(3).is_none());
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<Vec<u8>> = (0..3000).map(|i| format!("value {}", i * 31).into_bytes()).collect();
+    let header = {
+      let mut d = ArrayDictionary::<u8>::new(&mut pp);
+      for v in values[..2000].iter() {
+        d.add(v);
+      }
+      d.header_page()
+    };
+
+    // Existing IDs are kept and new values follow on
+    let mut d = ArrayDictionary::<u8>::open(&mut pp, header);
+    assert!(d.len() == 2000);
+    assert!(d.lookup(&values[1234]) == Some(1234));
+    let refs : Vec<&[u8]> = values.iter().map(|v| &v[..]).collect();
+    let ids = d.append(&refs[1000..]);
+    assert!(ids.iter().cloned().eq(1000..3000));
+    assert!(d.iter().zip(values.iter()).all(|(a, b)| *a == b[..]));
+    assert!(d.iter().count() == 3000);
+
+    let d = ArrayDictionary::<u8>::open(&mut pp, header);
+    assert!(d.len() == 3000);
+    assert!(*d.get(2999) == values[2999][..]);
+    // The index is read from its pages, not rebuilt
+    assert!(values.iter().enumerate().all(|(i, v)| d.lookup(v) == Some(i as u32)));
+  }
+
+  #[test]
+  pub fn version_0() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<Vec<u8>> = (0..500).map(|i| format!("value {}", i).into_bytes()).collect();
+    let header = {
+      let mut d = ArrayDictionary::<u8>::new(&mut pp);
+      values[..400].iter().for_each(|v| { d.add(v); });
+      d.header_page()
+    };
+    // Rewrite the header in the old layout, with whatever happened to follow it on the page
+    let h = read_header(&pp, header);
+    let bytes = pp.mut_page_bytes(header);
+    bytes.iter_mut().for_each(|b| *b = 0xff);
+    let old = DictionaryHeaderV0 { version: 0, padding: [0; 3], refs: h.refs, arr: h.arr, index: h.index };
+    unsafe { *(bytes.as_mut_ptr() as *mut DictionaryHeaderV0) = old };
+
+    let mut d = ArrayDictionary::<u8>::open(&mut pp, header);
+    assert!(d.options().index && d.options().filter_ids == 0);
+    assert!(d.len() == 400 && d.lookup(&values[123]) == Some(123));
+    values[400..].iter().for_each(|v| { d.add(v); });
+    assert!(pp.page_bytes(header)[0] == DICTIONARY_VERSION);
+    let d = ArrayDictionary::<u8>::open(&mut pp, header);
+    assert!(values.iter().enumerate().all(|(i, v)| d.lookup(v) == Some(i as u32)));
+  }
+
+  #[test]
+  pub fn filters() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<Vec<u8>> = (0..3000).map(|i| format!("value {}", i * 31).into_bytes()).collect();
+    let options = DictionaryOptions { index: false, filter_ids: 16 };
+    let header = {
+      let mut d = ArrayDictionary::<u8>::with_options(&mut pp, options);
+      for v in values.iter() {
+        d.add(v);
+      }
+      assert!(d.add(&values[17]) == 17);
+      d.header_page()
+    };
+
+    let d = ArrayDictionary::<u8>::open(&mut pp, header);
+    assert!(d.options().filter_ids == 16 && !d.options().index);
+    assert!(values.iter().enumerate().all(|(i, v)| d.lookup(v) == Some(i as u32)));
+    assert!(lookup::<u8>(header, &values[2999], d.db()) == Some(2999));
+
+    // A value's own group always matches, absent values match a few percent of groups
+    let (hits, groups) = d.filter_hits(&values[100]);
+    assert!(groups == 3000 / 16 + 1 && hits >= 1);
+    let misses : Vec<Vec<u8>> = (0..1000).map(|i| format!("missing {}", i).into_bytes()).collect();
+    assert!(misses.iter().all(|v| d.lookup(v).is_none()));
+    let hits : usize = misses.iter().map(|v| d.filter_hits(v).0).sum();
+    assert!(hits * 10 < groups * misses.len(), "hit rate {}", hits as f64 / (groups * misses.len()) as f64);
+  }
+
+  #[test]
+  pub fn filter_hit_rates() {
+    // Fraction of groups an absent lookup still scans, the group sizes the benchmark compares
+    let values : Vec<Vec<u8>> = (0..5000).map(|i| format!("value {}", i * 31).into_bytes()).collect();
+    let misses : Vec<Vec<u8>> = (0..100).map(|i| format!("missing {}", i).into_bytes()).collect();
+    let rates : Vec<f64> = [8u32, 16, 64, 340].iter().map(|&filter_ids| {
+      let mut pp = MemoryPageProvider::new();
+      let mut d = ArrayDictionary::<u8>::with_options(&mut pp, DictionaryOptions { index: false, filter_ids });
+      values.iter().for_each(|v| { d.add(v); });
+      let (hits, groups) = misses.iter().map(|v| d.filter_hits(v)).fold((0, 0), |(h, g), (a, b)| (h + a, g + b));
+      hits as f64 / groups as f64
+    }).collect();
+    assert!(rates[0] < 0.02 && rates[1] < 0.05, "hit rates {:?}", rates);
+    // Bigger groups fill more of the mask until it stops filtering
+    assert!(rates.windows(2).all(|r| r[0] <= r[1]), "hit rates {:?}", rates);
+    assert!(rates[3] > 0.9, "hit rates {:?}", rates);
+  }
+
+  #[test]
+  pub fn scan() {
+    let mut pp = MemoryPageProvider::new();
+    let mut d = ArrayDictionary::<u8>::with_options(&mut pp, DictionaryOptions { index: false, filter_ids: 0 });
+    let values : Vec<Vec<u8>> = (0..500).map(|i| format!("{}", i).into_bytes()).collect();
+    let refs : Vec<&[u8]> = values.iter().map(|v| &v[..]).collect();
+    assert!(d.append(&refs).iter().cloned().eq(0..500));
+    assert!(d.append(&refs[..10]).iter().cloned().eq(0..10));
+    assert!(d.lookup(b"500").is_none());
+    assert!(d.filter_hits(b"500") == (1, 1));
+  }
+}
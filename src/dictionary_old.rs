@@ -1,11 +1,12 @@
 #![allow(dead_code)]
 use fnv::{FnvHashMap, FnvHasher};
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
 
 
-pub struct Dictionary<A> {
+pub struct Dictionary<A, S = BuildHasherDefault<FnvHasher>> {
   entries: Vec<A>,
   index: Option<FnvHashMap<u64, Vec<usize>>>, 
+  hasher: S,
 }
 
 
@@ -14,47 +15,77 @@ pub struct Dictionary<A> {
 impl<A : Copy + Hash + Eq> Dictionary<A> {
 
   pub fn new() -> Dictionary<A> {
+    Dictionary::with_hasher(BuildHasherDefault::default())
+  }
+}
+
+impl<A : Copy + Hash + Eq> Default for Dictionary<A> {
+  fn default() -> Dictionary<A> {
+    Dictionary::new()
+  }
+}
+
+impl<A : Copy + Hash + Eq, S: BuildHasher> Dictionary<A, S> {
+
+  pub fn with_hasher(hasher: S) -> Dictionary<A, S> {
     Dictionary {
       entries : Vec::new(),
       index: None,
+      hasher,
     }
   }
 
-  pub fn add(&mut self, val: &A) -> usize {
-    let mut hasher:FnvHasher = FnvHasher::default();
-
+  fn hash(&self, val: &A) -> u64 {
+    self.hasher.hash_one(val)
+  }
 
-    match &self.index {
-      None => { 
-        let map = FnvHashMap::default(); // TODO should construct this out of entries
-        self.index = Some(map);
-      }
-      Some(_val) => ()
-    };
-
-    let index = self.index.as_mut().unwrap();
-
-    val.hash(&mut hasher);
-    let hash = hasher.finish();
-    match index.get(&hash) {
-      None => {}
-      Some(idxs) => {
-        for i in idxs {
-          if self.entries[*i] == *val { return *i; }
-        };
+  fn index(&mut self) -> &mut FnvHashMap<u64, Vec<usize>> {
+    if self.index.is_none() {
+      let mut map : FnvHashMap<u64, Vec<usize>> = FnvHashMap::default();
+      for (i, val) in self.entries.iter().enumerate() {
+        map.entry(self.hash(val)).or_default().push(i);
       }
+      self.index = Some(map);
+    }
+    self.index.as_mut().unwrap()
+  }
+
+  // Every value with the same hash shares a bucket
+  fn find(&self, hash: u64, val: &A) -> Option<usize> {
+    let idxs = self.index.as_ref()?.get(&hash)?;
+    idxs.iter().cloned().find(|&i| self.entries[i] == *val)
+  }
+
+  pub fn add(&mut self, val: &A) -> usize {
+    let hash = self.hash(val);
+    self.index();
+    if let Some(i) = self.find(hash, val) {
+      return i;
     }
 
     let i = self.entries.len();
     self.entries.push(*val);
-    index.insert(hash, vec![i]);
+    self.index().entry(hash).or_default().push(i);
     i
 
   }
 
+  // ID of val without adding it, there's no index until the first add
+  pub fn lookup(&self, val: &A) -> Option<usize> {
+    self.find(self.hash(val), val)
+  }
+
   pub fn get(&self, i: usize) -> &A {
     & self.entries[i]
   }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
 }
 
 
@@ -62,6 +93,8 @@ impl<A : Copy + Hash + Eq> Dictionary<A> {
 pub mod tests {
   #[allow(unused_imports)]
   use super::*;
+  #[allow(unused_imports)]
+  use std::hash::Hasher;
 
   #[test]
   pub fn add() {
@@ -73,4 +106,38 @@ pub mod tests {
     assert!(d.add(&"And a third test") == 2);
     assert!(d.add(&"This is a test") == 0);
   }
-}
\ No newline at end of file
+
+  // Keeps the low 4 bits of the last byte written, the top byte of a little endian u32,
+  // so every value below 2^24 hashes to 0 and they all collide
+  #[derive(Default)]
+  struct Colliding(u64);
+
+  impl Hasher for Colliding {
+    fn finish(&self) -> u64 {
+      self.0 & 15
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+      for &b in bytes {
+        self.0 = (self.0 << 8) | b as u64;
+      }
+    }
+  }
+
+  #[test]
+  pub fn collisions() {
+    let mut d = Dictionary::<u32, BuildHasherDefault<Colliding>>::with_hasher(BuildHasherDefault::default());
+    for i in 0..1000u32 {
+      assert!(d.lookup(&i).is_none());
+      assert!(d.add(&i) == i as usize);
+    }
+    // Every value is still found once its bucket has been added to
+    for i in 0..1000u32 {
+      assert!(d.lookup(&i) == Some(i as usize));
+      assert!(d.add(&i) == i as usize);
+    }
+    assert!(d.len() == 1000);
+    assert!(d.lookup(&1000).is_none());
+    assert!(d.len() == 1000);
+  }
+}
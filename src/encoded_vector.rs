@@ -0,0 +1,529 @@
+// Integer column with a per page encoding
+// Each page holds one block of values packed in one of
+//  FrameOfReference: value - min, for values clustered in a small range
+//  Delta: value - previous value - min delta, for sorted values such as timestamps
+//   get sums every delta before the row, up to MAX_BLOCK_ENTRIES of them, iterators don't
+// whichever needs fewer bits, so every page is filled as far as its values allow
+// A directory PagedVector maps the first row of each block to its page for random access
+// Values collect in a tail until they fill a page or the column is flushed, the tail is
+// mirrored into pages of its own that are reused for every block
+// A header page records the directory, the tail and the row counts, it's the only page to keep
+
+#![allow(dead_code)]
+
+use crate::database::{PageProvider};
+use crate::paged_vector::{self};
+
+const BLOCK_VERSION: u8 = 0;
+const ENCODED_VECTOR_VERSION: u8 = 0;
+const PAGE_SIZE_SHIFT: u8 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SIZE_SHIFT;
+// Caps constant blocks, which pack to 0 bits, and the cost of a Delta decode
+const MAX_BLOCK_ENTRIES: usize = 4096;
+const TAIL_PAGE_ENTRIES: usize = PAGE_SIZE / 8;
+const TAIL_PAGES: usize = MAX_BLOCK_ENTRIES / TAIL_PAGE_ENTRIES;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Encoding {
+  FrameOfReference = 0,
+  Delta = 1,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BlockHeader {
+  version: u8,
+  encoding: Encoding,
+  width: u8, // bits per packed value
+  padding: u8,
+  entries: u32,
+  min: i64,
+  max: i64,
+  first: i64,     // Delta: first value, the remaining entries - 1 are packed deltas
+  delta_min: i64, // Delta: subtracted from every delta before packing
+}
+
+const HEADER_SIZE: usize = std::mem::size_of::<BlockHeader>();
+const CAPACITY_BITS: usize = (PAGE_SIZE - HEADER_SIZE) * 8;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+struct BlockRef {
+  first_row: u64,
+  page: u32,
+  padding: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct EncodedVectorHeader {
+  version: u8,
+  padding: [u8; 3],
+  directory: u32,
+  sealed: u64,
+  tail_entries: u64,
+  tail: [u32; TAIL_PAGES],
+}
+
+pub struct EncodedVector<'a> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  tail_pages: [u32; TAIL_PAGES],
+  directory: u32,
+  sealed: usize, // rows in pages
+  tail: Vec<i64>,
+  stats: BlockStats,
+}
+
+// Bits needed for an unsigned range, 0 if every value is the same
+fn width(range: u64) -> u8 {
+  (64 - range.leading_zeros()) as u8
+}
+
+fn range(min: i64, max: i64) -> u64 {
+  max.wrapping_sub(min) as u64
+}
+
+// Running min/max of a block's values and deltas, to size it as it grows
+#[derive(Clone, Copy, Debug)]
+struct BlockStats {
+  entries: usize,
+  min: i64,
+  max: i64,
+  last: i64,
+  delta_min: i64,
+  delta_max: i64,
+}
+
+impl BlockStats {
+  fn new() -> BlockStats {
+    BlockStats { entries: 0, min: i64::MAX, max: i64::MIN, last: 0, delta_min: i64::MAX, delta_max: i64::MIN }
+  }
+
+  fn add(&mut self, v: i64) {
+    if self.entries > 0 {
+      let d = v.wrapping_sub(self.last);
+      self.delta_min = std::cmp::min(self.delta_min, d);
+      self.delta_max = std::cmp::max(self.delta_max, d);
+    }
+    self.min = std::cmp::min(self.min, v);
+    self.max = std::cmp::max(self.max, v);
+    self.last = v;
+    self.entries += 1;
+  }
+
+  fn for_width(&self) -> u8 {
+    width(range(self.min, self.max))
+  }
+
+  fn delta_width(&self) -> u8 {
+    if self.entries < 2 { 0 } else { width(range(self.delta_min, self.delta_max)) }
+  }
+
+  // Narrowest encoding, None if the block no longer fits in a page
+  fn encoding(&self, capacity_bits: usize) -> Option<(Encoding, u8)> {
+    if self.entries > MAX_BLOCK_ENTRIES {
+      return None;
+    }
+    let for_width = self.for_width();
+    let delta_width = self.delta_width();
+    // Ties go to FrameOfReference as it doesn't need a prefix sum to read
+    let (encoding, width, packed) = if for_width <= delta_width {
+      (Encoding::FrameOfReference, for_width, self.entries)
+    } else {
+      (Encoding::Delta, delta_width, self.entries - 1)
+    };
+    if packed * width as usize <= capacity_bits { Some((encoding, width)) } else { None }
+  }
+}
+
+fn header(bytes: &[u8]) -> &BlockHeader {
+  unsafe { &*(bytes.as_ptr() as *const BlockHeader) }
+}
+
+fn read_word(data: &[u8], word: usize) -> u64 {
+  let mut b = [0u8; 8];
+  b.copy_from_slice(&data[word * 8..word * 8 + 8]);
+  u64::from_le_bytes(b)
+}
+
+fn write_word(data: &mut [u8], word: usize, v: u64) {
+  data[word * 8..word * 8 + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+// Packed values are LSB first in little endian u64 words, as in PackedVector
+fn read_bits(data: &[u8], i: usize, width: u8) -> u64 {
+  if width == 0 {
+    return 0;
+  }
+  let w = width as usize;
+  let bit = i * w;
+  let shift = bit % 64;
+  let mut v = read_word(data, bit / 64) >> shift;
+  if shift + w > 64 {
+    v |= read_word(data, bit / 64 + 1) << (64 - shift);
+  }
+  if w == 64 { v } else { v & ((1 << w) - 1) }
+}
+
+fn write_bits(data: &mut [u8], i: usize, width: u8, v: u64) {
+  if width == 0 {
+    return;
+  }
+  let w = width as usize;
+  let bit = i * w;
+  let shift = bit % 64;
+  let word = bit / 64;
+  write_word(data, word, read_word(data, word) | (v << shift));
+  if shift + w > 64 {
+    write_word(data, word + 1, read_word(data, word + 1) | (v >> (64 - shift)));
+  }
+}
+
+fn encode_block(bytes: &mut [u8], values: &[i64], stats: &BlockStats, encoding: Encoding, width: u8) {
+  let (head, data) = bytes.split_at_mut(HEADER_SIZE);
+  data.iter_mut().for_each(|b| *b = 0);
+  let hdr = BlockHeader {
+    version: BLOCK_VERSION,
+    encoding,
+    width,
+    padding: 0,
+    entries: values.len() as u32,
+    min: stats.min,
+    max: stats.max,
+    first: values[0],
+    delta_min: stats.delta_min,
+  };
+  unsafe { *(head.as_mut_ptr() as *mut BlockHeader) = hdr };
+  match encoding {
+    Encoding::FrameOfReference => {
+      for (i, &v) in values.iter().enumerate() {
+        write_bits(data, i, width, range(stats.min, v));
+      }
+    }
+    Encoding::Delta => {
+      for (i, pair) in values.windows(2).enumerate() {
+        let d = pair[1].wrapping_sub(pair[0]);
+        write_bits(data, i, width, range(stats.delta_min, d));
+      }
+    }
+  }
+}
+
+fn decode_one(bytes: &[u8], i: usize) -> i64 {
+  let hdr = header(bytes);
+  let data = &bytes[HEADER_SIZE..];
+  match hdr.encoding {
+    Encoding::FrameOfReference => hdr.min.wrapping_add(read_bits(data, i, hdr.width) as i64),
+    Encoding::Delta => (0..i).fold(hdr.first, |acc, j| {
+      acc.wrapping_add(hdr.delta_min).wrapping_add(read_bits(data, j, hdr.width) as i64)
+    }),
+  }
+}
+
+fn decode_block(bytes: &[u8], out: &mut Vec<i64>) {
+  let hdr = header(bytes);
+  let data = &bytes[HEADER_SIZE..];
+  out.clear();
+  match hdr.encoding {
+    Encoding::FrameOfReference => {
+      out.extend((0..hdr.entries as usize).map(|i| hdr.min.wrapping_add(read_bits(data, i, hdr.width) as i64)));
+    }
+    Encoding::Delta => {
+      let mut v = hdr.first;
+      out.push(v);
+      for j in 0..hdr.entries as usize - 1 {
+        v = v.wrapping_add(hdr.delta_min).wrapping_add(read_bits(data, j, hdr.width) as i64);
+        out.push(v);
+      }
+    }
+  }
+}
+
+impl<'a> EncodedVector<'a> {
+  pub fn new(db: &'a mut dyn PageProvider) -> EncodedVector<'a> {
+    let directory = paged_vector::bulk_load::<BlockRef>(&[], db);
+    let header = db.alloc(1)[0];
+    let mut tail_pages = [0; TAIL_PAGES];
+    tail_pages.copy_from_slice(&db.alloc(TAIL_PAGES));
+    let mut v = EncodedVector {
+      db,
+      header,
+      tail_pages,
+      directory,
+      sealed: 0,
+      tail: Vec::new(),
+      stats: BlockStats::new(),
+    };
+    v.write_header();
+    v
+  }
+
+  // Vector previously created whose header is on page header, with the tail as it was left
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> EncodedVector<'a> {
+    let h = unsafe { *(db.page_bytes(header).as_ptr() as *const EncodedVectorHeader) };
+    assert!(h.version == ENCODED_VECTOR_VERSION, "Unknown encoded vector version {} on page {}", h.version, header);
+    let tail : Vec<i64> = (0..h.tail_entries as usize).map(|i| {
+      let data = db.page_bytes(h.tail[i / TAIL_PAGE_ENTRIES]);
+      read_word(data, i % TAIL_PAGE_ENTRIES) as i64
+    }).collect();
+    let mut stats = BlockStats::new();
+    tail.iter().for_each(|&v| stats.add(v));
+    EncodedVector { db, header, tail_pages: h.tail, directory: h.directory, sealed: h.sealed as usize, tail, stats }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  // The directory moves as it grows
+  fn write_header(&mut self) {
+    let h = EncodedVectorHeader {
+      version: ENCODED_VECTOR_VERSION,
+      padding: [0; 3],
+      directory: self.directory,
+      sealed: self.sealed as u64,
+      tail_entries: self.tail.len() as u64,
+      tail: self.tail_pages,
+    };
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut EncodedVectorHeader) = h };
+  }
+
+  pub fn push(&mut self, v: i64) {
+    let mut grown = self.stats;
+    grown.add(v);
+    if grown.encoding(CAPACITY_BITS).is_none() {
+      // v doesn't fit, the tail is a full page without it
+      self.seal();
+      grown = BlockStats::new();
+      grown.add(v);
+    }
+    let i = self.tail.len();
+    write_word(self.db.mut_page_bytes(self.tail_pages[i / TAIL_PAGE_ENTRIES]), i % TAIL_PAGE_ENTRIES, v as u64);
+    self.tail.push(v);
+    self.stats = grown;
+    self.write_header();
+  }
+
+  pub fn append(&mut self, vs: &[i64]) {
+    vs.iter().for_each(|&v| self.push(v));
+  }
+
+  // Writes the tail to a page even if it isn't full, later values start a new page
+  pub fn flush(&mut self) {
+    if !self.tail.is_empty() {
+      self.seal();
+    }
+  }
+
+  fn seal(&mut self) {
+    let (encoding, width) = self.stats.encoding(CAPACITY_BITS).unwrap();
+    let page = self.db.alloc(1)[0];
+    encode_block(self.db.mut_page_bytes(page), &self.tail, &self.stats, encoding, width);
+    let block = BlockRef { first_row: self.sealed as u64, page, padding: 0 };
+    self.directory = paged_vector::append_slice(self.directory, &[block], self.db);
+    self.sealed += self.tail.len();
+    self.tail.clear();
+    self.stats = BlockStats::new();
+    self.write_header();
+  }
+
+  pub fn len(&self) -> usize {
+    self.sealed + self.tail.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn get(&self, i: usize) -> i64 {
+    match self.try_get(i) {
+      Some(v) => v,
+      None => panic!("Index {} out of bounds for EncodedVector of length {}", i, self.len()),
+    }
+  }
+
+  pub fn try_get(&self, i: usize) -> Option<i64> {
+    if i >= self.sealed {
+      return self.tail.get(i - self.sealed).cloned();
+    }
+    let block = self.block(self.block_of(i));
+    Some(decode_one(self.db.page_bytes(block.page), i - block.first_row as usize))
+  }
+
+  fn blocks(&self) -> usize {
+    paged_vector::len::<BlockRef>(self.directory, self.db)
+  }
+
+  fn block(&self, b: usize) -> BlockRef {
+    *paged_vector::get::<BlockRef>(self.directory, b, self.db).unwrap()
+  }
+
+  // Last block starting at or before row i
+  fn block_of(&self, i: usize) -> usize {
+    let (mut lo, mut hi) = (0, self.blocks());
+    while hi - lo > 1 {
+      let mid = (lo + hi) / 2;
+      if self.block(mid).first_row as usize <= i { lo = mid } else { hi = mid }
+    }
+    lo
+  }
+
+  // Encoding of each sealed page in order
+  pub fn encodings(&self) -> Vec<Encoding> {
+    (0..self.blocks()).map(|b| header(self.db.page_bytes(self.block(b).page)).encoding).collect()
+  }
+
+  pub fn iter_from(&self, i: usize) -> EncodedVectorIterator<'a, '_> {
+    let i = std::cmp::min(i, self.len());
+    let mut it = EncodedVectorIterator {
+      vector: self,
+      block: self.blocks(),
+      values: Vec::new(),
+      offset: 0,
+      row: i,
+    };
+    if i < self.sealed {
+      it.block = self.block_of(i);
+      decode_block(self.db.page_bytes(self.block(it.block).page), &mut it.values);
+      it.offset = i - self.block(it.block).first_row as usize;
+    }
+    it
+  }
+
+  pub fn iter(&self) -> EncodedVectorIterator<'a, '_> {
+    self.iter_from(0)
+  }
+}
+
+// Decodes a page at a time, values holds the current block
+pub struct EncodedVectorIterator<'a, 'b> {
+  vector: &'b EncodedVector<'a>,
+  block: usize,
+  values: Vec<i64>,
+  offset: usize,
+  row: usize,
+}
+
+impl<'a, 'b> Iterator for EncodedVectorIterator<'a, 'b> {
+  type Item = i64;
+  fn next(&mut self) -> Option<i64> {
+    if self.row >= self.vector.sealed {
+      let v = self.vector.tail.get(self.row - self.vector.sealed).cloned()?;
+      self.row += 1;
+      return Some(v);
+    }
+    if self.offset >= self.values.len() {
+      self.block += 1;
+      decode_block(self.vector.db.page_bytes(self.vector.block(self.block).page), &mut self.values);
+      self.offset = 0;
+    }
+    let v = self.values[self.offset];
+    self.offset += 1;
+    self.row += 1;
+    Some(v)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let remaining = self.vector.len() - self.row;
+    (remaining, Some(remaining))
+  }
+}
+
+impl<'a, 'b> ExactSizeIterator for EncodedVectorIterator<'a, 'b> {}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  fn check(v: &EncodedVector, values: &[i64]) {
+    assert!(v.len() == values.len());
+    for (i, &x) in values.iter().enumerate() {
+      assert!(v.get(i) == x, "index {}", i);
+    }
+    assert!(v.try_get(values.len()).is_none());
+    assert!(v.iter().len() == values.len());
+    assert!(v.iter().eq(values.iter().cloned()));
+    for &start in [1, 4095, 4096, values.len() / 2, values.len()].iter() {
+      assert!(v.iter_from(start).eq(values[start..].iter().cloned()));
+    }
+  }
+
+  #[test]
+  pub fn timestamps_use_delta() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = EncodedVector::new(&mut pp);
+    // Increasing with jitter, big values but small gaps
+    let values : Vec<i64> = (0..50000i64).map(|i| 1_600_000_000_000 + i * 1000 + (i * 7919) % 13).collect();
+    v.append(&values);
+    check(&v, &values);
+    let encodings = v.encodings();
+    assert!(!encodings.is_empty());
+    assert!(encodings.iter().all(|&e| e == Encoding::Delta));
+
+    v.flush();
+    check(&v, &values);
+  }
+
+  #[test]
+  pub fn clustered_use_frame_of_reference() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = EncodedVector::new(&mut pp);
+    // Unordered within a narrow band
+    let values : Vec<i64> = (0..50000i64).map(|i| -1_000_000 + (i * 7919) % 200).collect();
+    v.append(&values);
+    v.flush();
+    check(&v, &values);
+    let encodings = v.encodings();
+    assert!(encodings.iter().all(|&e| e == Encoding::FrameOfReference));
+    // 8 bit offsets, about 4000 to a page rather than 500 raw i64s
+    assert!(encodings.len() <= 50000 / 4000 + 1);
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<i64> = (0..20000i64).map(|i| i * 10 + (i * 7919) % 7).collect();
+    let header = {
+      let mut v = EncodedVector::new(&mut pp);
+      v.append(&values[..9000]);
+      v.header_page()
+    };
+    // Sealed pages and the tail left part way through a block
+    let mut v = EncodedVector::open(&mut pp, header);
+    check(&v, &values[..9000]);
+    v.append(&values[9000..]);
+    v.flush();
+    let v = EncodedVector::open(&mut pp, header);
+    check(&v, &values);
+    assert!(v.encodings().iter().all(|&e| e == Encoding::Delta));
+  }
+
+  #[test]
+  pub fn mixed_and_extremes() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = EncodedVector::new(&mut pp);
+    let mut values = Vec::new();
+    values.extend((0..5000).map(|_| 42i64));
+    values.extend((0..5000i64).map(|i| i * 3));
+    values.extend((0..3000i64).map(|i| if i % 2 == 0 { i64::MIN } else { i64::MAX }));
+    values.extend((0..3000i64).map(|i| (i * 2654435761) % 1000));
+    for &x in values.iter() {
+      v.push(x);
+    }
+    check(&v, &values);
+    v.flush();
+    v.push(-1);
+    values.push(-1);
+    check(&v, &values);
+
+    let encodings = v.encodings();
+    assert!(encodings.contains(&Encoding::Delta));
+    assert!(encodings.contains(&Encoding::FrameOfReference));
+  }
+}
@@ -0,0 +1,282 @@
+// Linear hash index over a set of pages, maps a u64 hash to the IDs with that hash
+// Callers check candidates against the real value, so colliding hashes are fine
+// Buckets are chains of pages, a directory PagedVector<u32> holds the first page of each
+// With n buckets at level l, n is 2^l + split and hash h lives in bucket
+//  h mod 2^l, or h mod 2^(l+1) if that's below split
+// Once the load passes MAX_LOAD bucket split is rehashed into itself and bucket 2^l + split
+// so the index grows a bucket at a time and is never rebuilt
+// Pages a split frees are chained through overflow into a free list and reused before allocating
+// HashIndex is only the state, small enough for the owner to keep in its own header page
+
+#![allow(dead_code)]
+
+use crate::database::{PageProvider};
+use crate::paged_vector::{self};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct BucketHeader {
+  count: u32,
+  overflow: u32, // Next page in the chain, 0 for none as page 0 can only start one
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct HashEntry {
+  hash: u64,
+  id: u32,
+  padding: u32,
+}
+
+const PAGE_SIZE_SHIFT: u8 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SIZE_SHIFT;
+const HEADER_SIZE: usize = std::mem::size_of::<BucketHeader>();
+const ENTRIES_PER_PAGE: usize = (PAGE_SIZE - HEADER_SIZE) / std::mem::size_of::<HashEntry>();
+// Split once buckets average this many entries per page, in 1/4s
+const MAX_LOAD: usize = 3;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HashIndex {
+  directory: u32,
+  level: u32,
+  split: u32,
+  free: u32, // First page of the free list, 0 for none as page 0 is never freed
+  entries: u64,
+}
+
+fn bucket_header(db: &dyn PageProvider, page: u32) -> BucketHeader {
+  unsafe { *(db.page_bytes(page).as_ptr() as *const BucketHeader) }
+}
+
+fn bucket_entries(db: &dyn PageProvider, page: u32) -> &[HashEntry] {
+  let bytes = db.page_bytes(page);
+  let count = bucket_header(db, page).count as usize;
+  unsafe { std::slice::from_raw_parts(bytes[HEADER_SIZE..].as_ptr() as *const HashEntry, count) }
+}
+
+// Writes a full page, entries must fit
+fn write_bucket(db: &mut dyn PageProvider, page: u32, entries: &[HashEntry], overflow: u32) {
+  let bytes = db.mut_page_bytes(page);
+  let h = BucketHeader { count: entries.len() as u32, overflow };
+  unsafe {
+    *(bytes.as_mut_ptr() as *mut BucketHeader) = h;
+    let out = bytes[HEADER_SIZE..].as_mut_ptr() as *mut HashEntry;
+    std::ptr::copy_nonoverlapping(entries.as_ptr(), out, entries.len());
+  }
+}
+
+fn push_entry(db: &mut dyn PageProvider, page: u32, e: HashEntry) {
+  let h = bucket_header(db, page);
+  let bytes = db.mut_page_bytes(page);
+  unsafe {
+    *(bytes[HEADER_SIZE..].as_mut_ptr() as *mut HashEntry).add(h.count as usize) = e;
+    (*(bytes.as_mut_ptr() as *mut BucketHeader)).count = h.count + 1;
+  }
+}
+
+// Pages of a bucket's chain in order
+fn chain(db: &dyn PageProvider, first: u32) -> Vec<u32> {
+  let mut pages = vec![first];
+  loop {
+    let next = bucket_header(db, *pages.last().unwrap()).overflow;
+    if next == 0 {
+      return pages;
+    }
+    pages.push(next);
+  }
+}
+
+// Writes entries as a chain reusing pages from the front of pool, returns the first page
+fn write_chain(db: &mut dyn PageProvider, entries: &[HashEntry], pool: &mut Vec<u32>) -> u32 {
+  let per_page = ENTRIES_PER_PAGE;
+  let chunks = std::cmp::max(1, entries.len().div_ceil(per_page));
+  let mut pages : Vec<u32> = pool.drain(..std::cmp::min(chunks, pool.len())).collect();
+  if pages.len() < chunks {
+    pages.extend(db.alloc(chunks - pages.len()));
+  }
+  for (i, &page) in pages.iter().enumerate() {
+    let chunk = &entries[std::cmp::min(i * per_page, entries.len())..std::cmp::min((i + 1) * per_page, entries.len())];
+    let overflow = pages.get(i + 1).cloned().unwrap_or(0);
+    write_bucket(db, page, chunk, overflow);
+  }
+  pages[0]
+}
+
+impl HashIndex {
+  pub fn new(db: &mut dyn PageProvider) -> HashIndex {
+    let bucket = write_chain(db, &[], &mut Vec::new());
+    let directory = paged_vector::bulk_load::<u32>(&[bucket], db);
+    HashIndex { directory, level: 0, split: 0, free: 0, entries: 0 }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries as usize
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries == 0
+  }
+
+  pub fn buckets(&self) -> usize {
+    (1usize << self.level) + self.split as usize
+  }
+
+  fn bucket_of(&self, hash: u64) -> usize {
+    let b = (hash & ((1u64 << self.level) - 1)) as usize;
+    if b < self.split as usize { (hash & ((2u64 << self.level) - 1)) as usize } else { b }
+  }
+
+  fn alloc_page(&mut self, db: &mut dyn PageProvider) -> u32 {
+    if self.free == 0 {
+      return db.alloc(1)[0];
+    }
+    let page = self.free;
+    self.free = bucket_header(db, page).overflow;
+    page
+  }
+
+  fn free_page(&mut self, page: u32, db: &mut dyn PageProvider) {
+    write_bucket(db, page, &[], self.free);
+    self.free = page;
+  }
+
+  fn first_page(&self, bucket: usize, db: &dyn PageProvider) -> u32 {
+    *paged_vector::get::<u32>(self.directory, bucket, db).unwrap()
+  }
+
+  pub fn insert(&mut self, hash: u64, id: u32, db: &mut dyn PageProvider) {
+    let first = self.first_page(self.bucket_of(hash), db);
+    let last = *chain(db, first).last().unwrap();
+    let e = HashEntry { hash, id, padding: 0 };
+    if (bucket_header(db, last).count as usize) < ENTRIES_PER_PAGE {
+      push_entry(db, last, e);
+    } else {
+      let page = self.alloc_page(db);
+      write_bucket(db, page, &[e], 0);
+      unsafe { (*(db.mut_page_bytes(last).as_mut_ptr() as *mut BucketHeader)).overflow = page };
+    }
+    self.entries += 1;
+    if self.entries as usize * 4 > self.buckets() * ENTRIES_PER_PAGE * MAX_LOAD {
+      self.split_bucket(db);
+    }
+  }
+
+  fn split_bucket(&mut self, db: &mut dyn PageProvider) {
+    let from = self.split as usize;
+    let mut pool = chain(db, self.first_page(from, db));
+    let entries : Vec<HashEntry> = pool.iter().flat_map(|&p| bucket_entries(db, p).iter().cloned()).collect();
+    let (moved, kept) : (Vec<HashEntry>, Vec<HashEntry>) = entries.iter().partition(|e| (e.hash >> self.level) & 1 == 1);
+
+    // Chains are full but for the last page so kept and moved fit in the old pages plus one
+    let spare = self.alloc_page(db);
+    pool.push(spare);
+    let first = write_chain(db, &kept, &mut pool);
+    debug_assert!(first == self.first_page(from, db));
+    let new_first = write_chain(db, &moved, &mut pool);
+    debug_assert!(new_first != 0);
+    self.directory = paged_vector::append_slice(self.directory, &[new_first], db);
+    pool.into_iter().for_each(|page| self.free_page(page, db));
+
+    self.split += 1;
+    if self.split as usize == 1 << self.level {
+      self.level += 1;
+      self.split = 0;
+    }
+  }
+
+  // First ID with this hash that f accepts
+  pub fn find<F: FnMut(u32) -> bool>(&self, hash: u64, db: &dyn PageProvider, mut f: F) -> Option<u32> {
+    let mut page = self.first_page(self.bucket_of(hash), db);
+    loop {
+      for e in bucket_entries(db, page) {
+        if e.hash == hash && f(e.id) {
+          return Some(e.id);
+        }
+      }
+      page = bucket_header(db, page).overflow;
+      if page == 0 {
+        return None;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  fn hash(i: u64) -> u64 {
+    i.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+  }
+
+  #[test]
+  pub fn insert_find() {
+    let mut pp = MemoryPageProvider::new();
+    let mut index = HashIndex::new(&mut pp);
+    assert!(index.find(hash(1), &pp, |_| true).is_none());
+
+    let entries = 100000u32;
+    for i in 0..entries {
+      index.insert(hash(i as u64), i, &mut pp);
+    }
+    assert!(index.len() == entries as usize);
+    // Buckets grow with the entries rather than chains getting longer
+    assert!(index.buckets() * ENTRIES_PER_PAGE * MAX_LOAD >= entries as usize * 4);
+    for i in 0..entries {
+      assert!(index.find(hash(i as u64), &pp, |_| true) == Some(i), "id {}", i);
+    }
+    assert!(index.find(hash(entries as u64), &pp, |_| true).is_none());
+
+    // The state is all that's needed to carry on using the pages
+    let copy = index;
+    assert!(copy.find(hash(77), &pp, |_| true) == Some(77));
+  }
+
+  // Pages in every chain and in the free list
+  fn pages(index: &HashIndex, db: &dyn PageProvider) -> usize {
+    let chains : usize = (0..index.buckets()).map(|b| chain(db, index.first_page(b, db)).len()).sum();
+    let mut free = 0;
+    let mut page = index.free;
+    while page != 0 {
+      free += 1;
+      page = bucket_header(db, page).overflow;
+    }
+    chains + free
+  }
+
+  #[test]
+  pub fn reuses_pages() {
+    let mut pp = MemoryPageProvider::new();
+    let start = pp.alloc(1)[0] as usize;
+    let mut index = HashIndex::new(&mut pp);
+    for i in 0..100000u32 {
+      index.insert(hash(i as u64), i, &mut pp);
+    }
+    // Every page but the directory's is in a chain or free, and the free list stays short
+    let directory = paged_vector::len::<u32>(index.directory, &pp) * 4 / PAGE_SIZE + 2;
+    let allocated = pp.alloc(1)[0] as usize - start - 1;
+    assert!(allocated <= pages(&index, &pp) + directory);
+    assert!(pages(&index, &pp) <= index.buckets() * 2);
+  }
+
+  #[test]
+  pub fn collisions() {
+    let mut pp = MemoryPageProvider::new();
+    let mut index = HashIndex::new(&mut pp);
+    // Every ID has one of 3 hashes, so chains overflow many pages
+    for i in 0..3000 {
+      index.insert(i % 3, i as u32, &mut pp);
+    }
+    for i in 0..3000u32 {
+      assert!(index.find(i as u64 % 3, &pp, |id| id == i) == Some(i));
+    }
+    assert!(index.find(1, &pp, |id| id % 3 == 0).is_none());
+    let mut seen = 0;
+    index.find(2, &pp, |_| { seen += 1; false });
+    assert!(seen == 1000);
+  }
+}
@@ -0,0 +1,260 @@
+// Equi-join of two tables on one column each, as pairs of matching row positions
+// The right side's matching rows are indexed first, then the left side is scanned against them,
+// so pairs come out ordered by left row and then right row. NULL never matches, not even NULL
+// Int columns are joined through a hash map of the right side's values
+// Ids and String columns are joined by dictionary ID through a dense array, with no hashing.
+// When the columns don't share a dictionary the right side's IDs are first translated,
+// once per distinct value, into IDs of the left column's dictionary
+// Values come from scan::fetch with the positions, so only the joined rows are read
+
+#![allow(dead_code)]
+
+use crate::catalog::{Catalog, CatalogError, ColumnType, NULL_ID, NULL_INT};
+use crate::scan::{self, BatchColumn, Predicate};
+use fnv::{FnvHashMap};
+
+// Table, column joined on and the predicate its rows must match
+#[derive(Clone, Debug)]
+pub struct JoinSide<'p> {
+  pub table: &'p str,
+  pub column: &'p str,
+  pub predicate: &'p Predicate,
+}
+
+// Pair i is left[i] with right[i]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Joined {
+  pub left: Vec<usize>,
+  pub right: Vec<usize>,
+}
+
+impl Joined {
+  pub fn len(&self) -> usize {
+    self.left.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.left.is_empty()
+  }
+}
+
+const NO_ROW: u32 = u32::MAX;
+
+// Rows with each ID as chains through next, in increasing row order
+struct IdIndex {
+  heads: Vec<u32>,
+  next: Vec<u32>,
+  rows: Vec<usize>,
+}
+
+impl IdIndex {
+  fn new(ids: usize, keys: &[(u32, usize)]) -> IdIndex {
+    let mut index = IdIndex { heads: vec![NO_ROW; ids], next: vec![NO_ROW; keys.len()], rows: Vec::with_capacity(keys.len()) };
+    keys.iter().for_each(|&(_, row)| index.rows.push(row));
+    for (i, &(id, _)) in keys.iter().enumerate().rev() {
+      if let Some(head) = index.heads.get_mut(id as usize) {
+        index.next[i] = *head;
+        *head = i as u32;
+      }
+    }
+    index
+  }
+
+  fn rows(&self, id: u32) -> impl Iterator<Item = usize> + '_ {
+    let mut i = self.heads.get(id as usize).cloned().unwrap_or(NO_ROW);
+    std::iter::from_fn(move || {
+      if i == NO_ROW {
+        return None;
+      }
+      let row = self.rows[i as usize];
+      i = self.next[i as usize];
+      Some(row)
+    })
+  }
+}
+
+fn column_type(catalog: &Catalog, side: &JoinSide) -> Result<(ColumnType, Option<u32>), CatalogError> {
+  let id = catalog.find_column(side.table, side.column).ok_or_else(|| CatalogError::NotFound(format!("{}.{}", side.table, side.column)))?;
+  let c = catalog.column(id)?;
+  Ok((c.column_type, c.dictionary))
+}
+
+fn wrong_type(left: &JoinSide, right: &JoinSide) -> CatalogError {
+  CatalogError::WrongType(format!("{}.{} = {}.{}", left.table, left.column, right.table, right.column))
+}
+
+pub fn join(catalog: &mut Catalog, left: &JoinSide, right: &JoinSide) -> Result<Joined, CatalogError> {
+  let (left_type, left_dictionary) = column_type(catalog, left)?;
+  let (right_type, right_dictionary) = column_type(catalog, right)?;
+  match (left_type, right_type) {
+    (ColumnType::Int, ColumnType::Int) => hash_join(catalog, left, right),
+    (ColumnType::Int, _) | (_, ColumnType::Int) => Err(wrong_type(left, right)),
+    _ => id_join(catalog, left, right, left_dictionary.unwrap(), right_dictionary.unwrap()),
+  }
+}
+
+fn hash_join(catalog: &mut Catalog, left: &JoinSide, right: &JoinSide) -> Result<Joined, CatalogError> {
+  let mut index : FnvHashMap<i64, Vec<usize>> = FnvHashMap::default();
+  for batch in scan::scan(catalog, right.table, &[right.column], right.predicate)? {
+    if let BatchColumn::Int(vs) = &batch.columns[0] {
+      for (&v, &row) in vs.iter().zip(batch.rows.iter()).filter(|(&v, _)| v != NULL_INT) {
+        index.entry(v).or_default().push(row);
+      }
+    }
+  }
+
+  let mut joined = Joined::default();
+  for batch in scan::scan(catalog, left.table, &[left.column], left.predicate)? {
+    if let BatchColumn::Int(vs) = &batch.columns[0] {
+      for (v, &row) in vs.iter().zip(batch.rows.iter()) {
+        for &r in index.get(v).map(|rows| &rows[..]).unwrap_or(&[]) {
+          joined.left.push(row);
+          joined.right.push(r);
+        }
+      }
+    }
+  }
+  Ok(joined)
+}
+
+fn ids(catalog: &mut Catalog, side: &JoinSide) -> Result<Vec<(u32, usize)>, CatalogError> {
+  let mut keys = Vec::new();
+  for batch in scan::scan(catalog, side.table, &[side.column], side.predicate)? {
+    if let BatchColumn::Ids(ids) = &batch.columns[0] {
+      keys.extend(ids.iter().cloned().zip(batch.rows.iter().cloned()).filter(|&(id, _)| id != NULL_ID));
+    }
+  }
+  Ok(keys)
+}
+
+fn id_join(catalog: &mut Catalog, left: &JoinSide, right: &JoinSide, left_dictionary: u32, right_dictionary: u32) -> Result<Joined, CatalogError> {
+  let mut keys = ids(catalog, right)?;
+  if left_dictionary != right_dictionary {
+    let mut distinct : Vec<u32> = keys.iter().map(|&(id, _)| id).collect();
+    distinct.sort_unstable();
+    distinct.dedup();
+    let values : Vec<Vec<u8>> = {
+      let d = catalog.dictionary(right_dictionary)?;
+      distinct.iter().map(|&id| d.get(id).into_owned()).collect()
+    };
+    let translated : Vec<u32> = {
+      let d = catalog.dictionary(left_dictionary)?;
+      values.iter().map(|v| d.lookup(v).unwrap_or(NULL_ID)).collect()
+    };
+    keys = keys.into_iter()
+      .map(|(id, row)| (translated[distinct.binary_search(&id).unwrap()], row))
+      .filter(|&(id, _)| id != NULL_ID)
+      .collect();
+  }
+  let index = IdIndex::new(catalog.dictionary(left_dictionary)?.len(), &keys);
+
+  let mut joined = Joined::default();
+  for batch in scan::scan(catalog, left.table, &[left.column], left.predicate)? {
+    if let BatchColumn::Ids(ids) = &batch.columns[0] {
+      for (&id, &row) in ids.iter().zip(batch.rows.iter()) {
+        for r in index.rows(id) {
+          joined.left.push(row);
+          joined.right.push(r);
+        }
+      }
+    }
+  }
+  Ok(joined)
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use crate::scan::Value;
+
+  // orders(customer_id, customer, city), customers(id, name, city) with customer names in a shared
+  // dictionary and the cities in one dictionary per table
+  fn setup(pp: &mut MemoryPageProvider) -> u32 {
+    let mut c = Catalog::new(pp);
+    let names = c.create_dictionary("names", false).unwrap();
+    let order_cities = c.create_dictionary("order_cities", false).unwrap();
+    let customer_cities = c.create_dictionary("customer_cities", true).unwrap();
+    let order_customer_id = c.add_column("orders", "customer_id", ColumnType::Int, None).unwrap();
+    let order_customer = c.add_column("orders", "customer", ColumnType::String, Some(names)).unwrap();
+    let order_city = c.add_column("orders", "city", ColumnType::String, Some(order_cities)).unwrap();
+    let customer_id = c.add_column("customers", "id", ColumnType::Int, None).unwrap();
+    let customer_name = c.add_column("customers", "name", ColumnType::String, Some(names)).unwrap();
+    let customer_city = c.add_column("customers", "city", ColumnType::String, Some(customer_cities)).unwrap();
+
+    // Customer i lives in city i % 5, and customers 30.. aren't named
+    c.append_ints(customer_id, &(0..40).collect::<Vec<i64>>()).unwrap();
+    {
+      let mut column = c.strings(customer_name).unwrap();
+      (0..40).for_each(|i| if i < 30 { column.push(&format!("customer{}", i)) } else { column.push_null() });
+    }
+    {
+      let mut column = c.strings(customer_city).unwrap();
+      (0..40).for_each(|i| column.push(&format!("city{}", i % 5)));
+    }
+
+    // Order i is by customer (i * 7) % 50, which may not exist, and shipped to city i % 8
+    c.append_ints(order_customer_id, &(0..3000).map(|i| (i * 7) % 50).collect::<Vec<i64>>()).unwrap();
+    {
+      let mut column = c.strings(order_customer).unwrap();
+      (0..3000).for_each(|i| if i % 10 == 9 { column.push_null() } else { column.push(&format!("customer{}", (i * 7) % 50)) });
+    }
+    {
+      let mut column = c.strings(order_city).unwrap();
+      (0..3000).for_each(|i| column.push(&format!("city{}", i % 8)));
+    }
+    c.header_page()
+  }
+
+  fn pairs(j: &Joined) -> Vec<(usize, usize)> {
+    j.left.iter().cloned().zip(j.right.iter().cloned()).collect()
+  }
+
+  fn expected(left: usize, right: usize, f: impl Fn(usize, usize) -> bool) -> Vec<(usize, usize)> {
+    (0..left).flat_map(|l| (0..right).map(move |r| (l, r))).filter(|&(l, r)| f(l, r)).collect()
+  }
+
+  #[test]
+  pub fn joins() {
+    let mut pp = MemoryPageProvider::new();
+    let header = setup(&mut pp);
+    let mut c = Catalog::open(&mut pp, header);
+    let all = Predicate::all();
+    let side = |table, column| JoinSide { table, column, predicate: &all };
+
+    // Hash join on Ints
+    let j = join(&mut c, &side("orders", "customer_id"), &side("customers", "id")).unwrap();
+    assert!(pairs(&j) == expected(3000, 40, |o, c| (o * 7) % 50 == c));
+
+    // ID join through the shared dictionary, NULLs on both sides don't match
+    let j = join(&mut c, &side("orders", "customer"), &side("customers", "name")).unwrap();
+    assert!(pairs(&j) == expected(3000, 40, |o, c| o % 10 != 9 && c < 30 && (o * 7) % 50 == c));
+
+    // ID join translating between dictionaries, with predicates on both sides
+    let big = Predicate::Ge("id".into(), Value::Int(20));
+    let late = Predicate::Ge("customer_id".into(), Value::Int(45));
+    let j = join(&mut c, &JoinSide { table: "orders", column: "city", predicate: &late }, &JoinSide { table: "customers", column: "city", predicate: &big }).unwrap();
+    assert!(pairs(&j) == expected(3000, 40, |o, c| (o * 7) % 50 >= 45 && c >= 20 && o % 8 == c % 5));
+
+    // The joined rows' values
+    let names = scan::fetch(&c, "customers", "name", &j.right).unwrap();
+    let cities = scan::fetch(&c, "orders", "city", &j.left).unwrap();
+    assert!(names.len() == j.len() && cities.len() == j.len());
+    if let (BatchColumn::Ids(names), BatchColumn::Ids(cities)) = (names, cities) {
+      let d = c.find_dictionary("names").unwrap();
+      let d = c.dictionary(d).unwrap();
+      assert!(names.iter().zip(j.right.iter()).all(|(&id, &r)| id == NULL_ID && r >= 30 || d.get(id) == format!("customer{}", r).as_bytes()));
+      let d = c.find_dictionary("order_cities").unwrap();
+      let d = c.dictionary(d).unwrap();
+      assert!(cities.iter().zip(j.left.iter()).all(|(&id, &o)| d.get(id) == format!("city{}", o % 8).as_bytes()));
+    } else {
+      panic!("Expected IDs");
+    }
+
+    assert!(matches!(join(&mut c, &side("orders", "customer_id"), &side("customers", "name")), Err(CatalogError::WrongType(_))));
+    assert!(matches!(join(&mut c, &side("orders", "nope"), &side("customers", "name")), Err(CatalogError::NotFound(_))));
+  }
+}
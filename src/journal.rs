@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use serde_json::{Value};
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::BufWriter;
@@ -13,11 +12,14 @@ pub struct DiskJournal<'a> {
 }
 
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type")]
 pub enum Entry {
   AppendU32s { id: u64, u32s: Vec<u32> },
   Msg{v: String},
+  // New dictionary header and (column, data page) pairs written, committed once synced
+  CompactDictionary { dictionary: u32, header: u32, columns: Vec<(u32, u32)> },
+  CompactDone { dictionary: u32 },
 }
 
 #[derive(Debug)]
@@ -29,6 +31,24 @@ pub enum JournalError {
 
 pub trait Journal {
   fn add(&mut self, entry: &Entry) -> Result<(), JournalError>;
+
+  // Entries added so far are durable once this returns
+  fn sync(&mut self) -> Result<(), JournalError> {
+    Ok(())
+  }
+}
+
+// Journal for tests and callers that don't need durability
+#[derive(Default)]
+pub struct MemoryJournal {
+  pub entries: Vec<Entry>,
+}
+
+impl Journal for MemoryJournal {
+  fn add(&mut self, entry: &Entry) -> Result<(), JournalError> {
+    self.entries.push(entry.clone());
+    Ok(())
+  }
 }
 
 
@@ -39,16 +59,20 @@ impl<'a> Journal for DiskJournal<'a> {
     Ok(())
   }
 
+  fn sync(&mut self) -> Result<(), JournalError> {
+    self.writer.flush().map_err(JournalError::IoError)?;
+    self.writer.get_ref().sync_all().map_err(JournalError::IoError)
+  }
+
 
 }
 
 
 impl<'a> DiskJournal<'a> {
-  pub fn new(file_name: &str) -> Result<DiskJournal, JournalError> {
+  pub fn new(file_name: &str) -> Result<DiskJournal<'_>, JournalError> {
     let file = OpenOptions::new()
       .create(true)
       .append(true)
-      .write(true)
       .open(file_name).map_err(JournalError::IoError)?;
 
     Ok(DiskJournal {
@@ -77,14 +101,13 @@ impl<'a> DiskJournal<'a> {
     reader.seek(std::io::SeekFrom::Start(0)).map_err(JournalError::IoError)?;
     let mut des = ser::Deserializer::from_reader(reader).into_iter::<Entry>();
     let out = Vec::new();
-    let res = des.try_fold(out, |mut acc, r| { 
+    des.try_fold(out, |mut acc, r| { 
         match r {
           Err(e) => Err(JournalError::SerError(e)),
           Ok(v) => { acc.push(v); Ok(acc) },
         }
       }
-    );
-    res
+    )
   }
 }
 
@@ -0,0 +1,23 @@
+// Paged storage, dictionaries, columns and the SQL front-end over them
+// The journal binary and the sql REPL both use the modules through this library
+
+pub mod bit_array;
+pub mod bitmap;
+pub mod dictionary_old;
+pub mod hash_index;
+pub mod dictionary;
+pub mod sorted_dictionary;
+pub mod catalog;
+pub mod dict_column;
+pub mod scan;
+pub mod aggregate;
+pub mod join;
+pub mod sort;
+pub mod sql;
+pub mod database;
+pub mod paged_vector;
+pub mod journal;
+pub mod var_vector;
+pub mod packed_vector;
+pub mod encoded_vector;
+pub mod rle_vector;
@@ -9,12 +9,8 @@ use std::io::prelude::*;
 use std::fs::File;
 use std::fs::OpenOptions;
 
-mod bit_array;
-mod dictionary_old;
-mod dictionary;
-mod database;
-mod paged_vector;
-mod journal;
+use journal::database::{self, Database};
+use journal::journal::{Journal, Entry, DiskJournal};
 
 
 fn write_file() -> Result<(), std::io::Error> {
@@ -22,6 +18,7 @@ fn write_file() -> Result<(), std::io::Error> {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(true)
             .open("foo.txt")?;
 
   for v in 0..10 {
@@ -68,15 +65,13 @@ fn write_file() -> Result<(), std::io::Error> {
 // }
 
 
-use database::Database;
-use journal::{Journal, Entry, DiskJournal};
 
 use AppError::JournalError;
 
 #[derive(Debug)]
 enum AppError {
   DbError(database::DbError),
-  JournalError(journal::JournalError),
+  JournalError(journal::journal::JournalError),
 }
 
 fn main() -> Result<(), AppError> {
@@ -0,0 +1,380 @@
+// Bit packed integers over a set of pages
+// Values are packed LSB first into a PagedVector<u64>, the same layout BitArray uses in memory
+// value i occupies bits [i * width, (i + 1) * width) and may straddle words and so leaf pages
+// Width is fixed per column, a 1000 entry dictionary needs 10 bits per ID rather than 32
+// BitArray isn't used for the leaves as it owns a heap allocation, where these words have to live
+// on the provider's pages, and leaf sized BitArrays would stop values straddling leaves
+// A header page records the words, width and entries, it's the only page to keep
+
+#![allow(dead_code)]
+
+use crate::database::{PageProvider};
+use crate::paged_vector::{self, PagedVectorIterator};
+
+const PACKED_VECTOR_VERSION: u8 = 0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct PackedVectorHeader {
+  version: u8,
+  width: u8,
+  padding: [u8; 2],
+  words: u32,
+  entries: u64,
+}
+
+pub struct PackedVector<'a> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  packed: Packed,
+}
+
+// Where a packed vector's values are, for structures that share their PageProvider
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Packed {
+  pub(crate) words: u32,
+  pub(crate) width: u8,
+  pub(crate) entries: usize,
+}
+
+// Smallest width that can hold max_value, never 0 so every value has a position
+pub fn width_for(max_value: u64) -> u8 {
+  std::cmp::max(1, 64 - max_value.leading_zeros()) as u8
+}
+
+fn mask(width: u8) -> u64 {
+  if width == 64 { !0 } else { (1u64 << width) - 1 }
+}
+
+impl Packed {
+  pub(crate) fn new(width: u8, db: &mut dyn PageProvider) -> Packed {
+    assert!((1..=64).contains(&width), "PackedVector width must be 1 to 64 bits, not {}", width);
+    let words = paged_vector::bulk_load::<u64>(&[], db);
+    Packed { words, width, entries: 0 }
+  }
+
+  pub(crate) fn append(&mut self, vs: &[u64], db: &mut dyn PageProvider) {
+    if vs.is_empty() {
+      return;
+    }
+    let width = self.width as usize;
+    let mask = mask(self.width);
+    let bit = self.entries * width;
+    let first_shift = bit % 64;
+    let mut shift = first_shift;
+
+    // Values that start in the partially filled last word are or'ed into it
+    let mut last = if first_shift == 0 { 0 } else { *paged_vector::get::<u64>(self.words, bit / 64, db).unwrap() };
+    let mut words = Vec::with_capacity((vs.len() * width) / 64 + 1);
+    for &v in vs {
+      assert!(v & !mask == 0, "Value {} doesn't fit in {} bits", v, self.width);
+      last |= v << shift;
+      shift += width;
+      if shift >= 64 {
+        words.push(last);
+        shift -= 64;
+        // Top bits of a straddling value start the next word
+        last = if shift == 0 { 0 } else { v >> (width - shift) };
+      }
+    }
+    if shift != 0 {
+      words.push(last);
+    }
+
+    // The first new word replaces the partial one
+    let mut words = &words[..];
+    if first_shift != 0 {
+      paged_vector::set::<u64>(self.words, bit / 64, words[0], db);
+      words = &words[1..];
+    }
+    self.words = paged_vector::append_slice(self.words, words, db);
+    self.entries += vs.len();
+  }
+
+  // Overwrites value i in place, false if there's no value i
+  pub(crate) fn set(&mut self, i: usize, v: u64, db: &mut dyn PageProvider) -> bool {
+    if i >= self.entries {
+      return false;
+    }
+    let mask = mask(self.width);
+    assert!(v & !mask == 0, "Value {} doesn't fit in {} bits", v, self.width);
+    let width = self.width as usize;
+    let bit = i * width;
+    let shift = bit % 64;
+    let lo = *paged_vector::get::<u64>(self.words, bit / 64, db).unwrap();
+    paged_vector::set::<u64>(self.words, bit / 64, (lo & !(mask << shift)) | (v << shift), db);
+    if shift + width > 64 {
+      // Top bits of a straddling value
+      let hi_mask = self::mask((shift + width - 64) as u8);
+      let hi = *paged_vector::get::<u64>(self.words, bit / 64 + 1, db).unwrap();
+      paged_vector::set::<u64>(self.words, bit / 64 + 1, (hi & !hi_mask) | (v >> (64 - shift)), db);
+    }
+    true
+  }
+
+  pub(crate) fn get(&self, i: usize, db: &dyn PageProvider) -> Option<u64> {
+    if i >= self.entries {
+      return None;
+    }
+    let width = self.width as usize;
+    let bit = i * width;
+    let shift = bit % 64;
+    let lo = *paged_vector::get::<u64>(self.words, bit / 64, db)?;
+    let v = if shift + width <= 64 {
+      lo >> shift
+    } else {
+      let hi = *paged_vector::get::<u64>(self.words, bit / 64 + 1, db)?;
+      (lo >> shift) | (hi << (64 - shift))
+    };
+    Some(v & mask(self.width))
+  }
+
+  pub(crate) fn iter_from<'b>(&self, i: usize, db: &'b dyn PageProvider) -> PackedVectorIterator<'b> {
+    let i = std::cmp::min(i, self.entries);
+    let bit = i * self.width as usize;
+    let mut words = paged_vector::iter_range::<u64>(self.words, bit / 64..usize::MAX, db);
+    let lo = words.next().unwrap_or(0);
+    PackedVectorIterator {
+      words,
+      lo,
+      bit: (bit % 64) as u32,
+      width: self.width as u32,
+      mask: mask(self.width),
+      remaining: self.entries - i,
+    }
+  }
+
+  // Copy of the values at a new width, the old pages are left as they are
+  pub(crate) fn repack(&self, width: u8, db: &mut dyn PageProvider) -> Packed {
+    let values : Vec<u64> = self.iter_from(0, db).collect();
+    let mut out = Packed::new(width, db);
+    out.append(&values, db);
+    out
+  }
+}
+
+impl<'a> PackedVector<'a> {
+  pub fn new(db: &'a mut dyn PageProvider, width: u8) -> PackedVector<'a> {
+    let packed = Packed::new(width, db);
+    let header = db.alloc(1)[0];
+    let mut p = PackedVector { db, header, packed };
+    p.write_header();
+    p
+  }
+
+  // Vector previously created whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> PackedVector<'a> {
+    let h = unsafe { *(db.page_bytes(header).as_ptr() as *const PackedVectorHeader) };
+    assert!(h.version == PACKED_VECTOR_VERSION, "Unknown packed vector version {} on page {}", h.version, header);
+    let packed = Packed { words: h.words, width: h.width, entries: h.entries as usize };
+    PackedVector { db, header, packed }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  // The words move as they grow
+  fn write_header(&mut self) {
+    let h = PackedVectorHeader {
+      version: PACKED_VECTOR_VERSION,
+      width: self.packed.width,
+      padding: [0; 2],
+      words: self.packed.words,
+      entries: self.packed.entries as u64,
+    };
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut PackedVectorHeader) = h };
+  }
+
+  pub fn width(&self) -> u8 {
+    self.packed.width
+  }
+
+  pub fn push(&mut self, v: u64) {
+    self.append(&[v])
+  }
+
+  pub fn append(&mut self, vs: &[u64]) {
+    self.packed.append(vs, self.db);
+    self.write_header();
+  }
+
+  pub fn set(&mut self, i: usize, v: u64) {
+    if !self.packed.set(i, v, self.db) {
+      panic!("Index {} out of bounds for PackedVector of length {}", i, self.len());
+    }
+  }
+
+  pub fn get(&self, i: usize) -> u64 {
+    match self.try_get(i) {
+      Some(v) => v,
+      None => panic!("Index {} out of bounds for PackedVector of length {}", i, self.len()),
+    }
+  }
+
+  pub fn try_get(&self, i: usize) -> Option<u64> {
+    self.packed.get(i, self.db)
+  }
+
+  pub fn iter_from(&self, i: usize) -> PackedVectorIterator<'_> {
+    self.packed.iter_from(i, self.db)
+  }
+
+  pub fn iter(&self) -> PackedVectorIterator<'_> {
+    self.iter_from(0)
+  }
+
+  pub fn len(&self) -> usize {
+    self.packed.entries
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.packed.entries == 0
+  }
+
+  // Number of u64 words backing the column
+  pub fn words(&self) -> usize {
+    paged_vector::len::<u64>(self.packed.words, self.db)
+  }
+}
+
+// lo is the current word with bit the position of the next value within it
+pub struct PackedVectorIterator<'a> {
+  words: PagedVectorIterator<'a, u64>,
+  lo: u64,
+  bit: u32,
+  width: u32,
+  mask: u64,
+  remaining: usize,
+}
+
+impl<'a> Iterator for PackedVectorIterator<'a> {
+  type Item = u64;
+  fn next(&mut self) -> Option<u64> {
+    if self.remaining == 0 {
+      return None;
+    }
+    if self.bit == 64 {
+      self.lo = self.words.next()?;
+      self.bit = 0;
+    }
+    let mut v = self.lo >> self.bit;
+    if self.bit + self.width > 64 {
+      let hi = self.words.next()?;
+      v |= hi << (64 - self.bit);
+      self.lo = hi;
+      self.bit = self.bit + self.width - 64;
+    } else {
+      self.bit += self.width;
+    }
+    self.remaining -= 1;
+    Some(v & self.mask)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.remaining, Some(self.remaining))
+  }
+}
+
+impl<'a> ExactSizeIterator for PackedVectorIterator<'a> {}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  #[test]
+  pub fn widths() {
+    assert!(width_for(0) == 1);
+    assert!(width_for(1) == 1);
+    assert!(width_for(999) == 10);
+    assert!(width_for(1023) == 10);
+    assert!(width_for(1024) == 11);
+    assert!(width_for(u64::MAX) == 64);
+  }
+
+  #[test]
+  pub fn push_get() {
+    for &width in [1u8, 3, 7, 10, 17, 32, 33, 63, 64].iter() {
+      let mut pp = MemoryPageProvider::new();
+      let mut p = PackedVector::new(&mut pp, width);
+      let m = mask(width);
+
+      // Enough values to fill several leaves at every width
+      let entries = 20000;
+      let values : Vec<u64> = (0..entries as u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) & m).collect();
+      for v in values.iter().take(100) {
+        p.push(*v);
+      }
+      p.append(&values[100..]);
+
+      assert!(p.len() == entries);
+      assert!(p.words() == (entries * width as usize).div_ceil(64));
+      for (i, v) in values.iter().enumerate() {
+        assert!(p.get(i) == *v, "width {} index {}", width, i);
+      }
+      assert!(p.try_get(entries).is_none());
+      assert!(p.iter().len() == entries);
+      assert!(p.iter().eq(values.iter().cloned()));
+      for &start in [0, 1, 63, 64, 4095, entries - 1, entries].iter() {
+        assert!(p.iter_from(start).eq(values[start..].iter().cloned()), "width {} from {}", width, start);
+      }
+    }
+  }
+
+  #[test]
+  pub fn set() {
+    for &width in [1u8, 7, 33, 64].iter() {
+      let mut pp = MemoryPageProvider::new();
+      let mut p = PackedVector::new(&mut pp, width);
+      let m = mask(width);
+      let mut values : Vec<u64> = (0..3000u64).map(|i| i.wrapping_mul(0x9E37_79B9_7F4A_7C15) & m).collect();
+      p.append(&values);
+      // Every value straddling a word boundary is among these, neighbours must keep their bits
+      for i in (0..3000).step_by(3) {
+        values[i] = !values[i] & m;
+        p.set(i, values[i]);
+      }
+      assert!(p.iter().eq(values.iter().cloned()), "width {}", width);
+    }
+  }
+
+  #[test]
+  pub fn dictionary_ids() {
+    // A 1000 value dictionary costs 10 bits per row rather than 32
+    let mut pp = MemoryPageProvider::new();
+    let mut p = PackedVector::new(&mut pp, width_for(999));
+    let ids : Vec<u64> = (0..100000).map(|i| i % 1000).collect();
+    p.append(&ids);
+    assert!(p.words() * 64 == 100000 * 10);
+    assert!(p.iter().eq(ids.iter().cloned()));
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<u64> = (0..5000).map(|i| (i * 7) % 300).collect();
+    let header = {
+      let mut p = PackedVector::new(&mut pp, width_for(299));
+      p.append(&values[..1234]);
+      p.header_page()
+    };
+    let mut p = PackedVector::open(&mut pp, header);
+    assert!(p.len() == 1234 && p.width() == 9);
+    p.append(&values[1234..]);
+    let p = PackedVector::open(&mut pp, header);
+    assert!(p.len() == values.len());
+    assert!(p.iter().eq(values.iter().cloned()));
+  }
+
+  #[test]
+  #[should_panic]
+  pub fn too_wide() {
+    let mut pp = MemoryPageProvider::new();
+    let mut p = PackedVector::new(&mut pp, 4);
+    p.push(16);
+  }
+}
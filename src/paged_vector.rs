@@ -8,11 +8,18 @@
 // Random access is then available through just the index depth
 // The index reduces the number of disk page reads when fetching the required page
 
+// Zoned vectors of integers also keep a min/max Zone per index entry, covering the child's subtree
+// so range scans skip subtrees without reading them. The header flags say how to read the values
+// so every append and set keeps the zones up to date whatever T the caller uses
+// A type's NULL value is only flagged in the zone, so it doesn't widen min/max to the type's limit
+
 #![allow(unused_variables)]
 #![allow(dead_code)]
 
 use crate::database::{PageProvider};
+use std::borrow::Cow;
 use std::fmt::Debug;
+use std::ops::Range;
 
 const PAGE_SIZE_SHIFT: u8 = 12;
 const PAGE_SIZE: usize = 1 << PAGE_SIZE_SHIFT;
@@ -20,7 +27,7 @@ const PAGE_SIZE: usize = 1 << PAGE_SIZE_SHIFT;
 #[derive(Debug)]
 #[repr(C)]
 struct PageHeader {
-  version: u8,
+  flags: u8, // Zone kind of zoned trees, 0 otherwise, was a version that was always 0
   depth: u8, // Stores the tree depth at the root set to 0 if a leaf
   entries: u16,
   next: u32, // Adjacency Chain like
@@ -33,12 +40,86 @@ impl PageHeader {
 }
 
 const EMPTY_HEADER: PageHeader = PageHeader {
-  version: 0,
+  flags: 0,
   depth: 0,
   entries: 0,
   next: 0,
 };
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zone {
+  pub min: i64,
+  pub max: i64,
+  pub nulls: bool,
+}
+
+impl Zone {
+  // Summary of no values, merging with it changes nothing
+  pub const EMPTY: Zone = Zone { min: i64::MAX, max: i64::MIN, nulls: false };
+
+  pub fn of<V: ZoneValue>(v: &[V]) -> Zone {
+    v.iter().fold(Zone::EMPTY, |z, &x| {
+      if Some(x) == V::NULL {
+        return Zone { nulls: true, ..z };
+      }
+      let x = x.into();
+      Zone { min: std::cmp::min(z.min, x), max: std::cmp::max(z.max, x), nulls: z.nulls }
+    })
+  }
+
+  pub fn merge(self, other: Zone) -> Zone {
+    Zone { min: std::cmp::min(self.min, other.min), max: std::cmp::max(self.max, other.max), nulls: self.nulls || other.nulls }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.min > self.max && !self.nulls
+  }
+
+  // Could any value in [lo, hi] be in the zone
+  pub fn overlaps(&self, lo: i64, hi: i64) -> bool {
+    self.min <= hi && self.max >= lo
+  }
+}
+
+// Values a zoned vector can hold, KIND is stored in every page's flags
+// NULL is the value the catalog uses for a missing one, if the type has one
+pub trait ZoneValue: Copy + Into<i64> + PartialEq {
+  const KIND: u8;
+  const NULL: Option<Self>;
+}
+
+impl ZoneValue for i64 { const KIND: u8 = 1; const NULL: Option<i64> = Some(i64::MIN); }
+impl ZoneValue for i32 { const KIND: u8 = 2; const NULL: Option<i32> = None; }
+impl ZoneValue for u32 { const KIND: u8 = 3; const NULL: Option<u32> = Some(u32::MAX); }
+impl ZoneValue for i16 { const KIND: u8 = 4; const NULL: Option<i16> = None; }
+impl ZoneValue for u16 { const KIND: u8 = 5; const NULL: Option<u16> = None; }
+impl ZoneValue for i8 { const KIND: u8 = 6; const NULL: Option<i8> = None; }
+impl ZoneValue for u8 { const KIND: u8 = 7; const NULL: Option<u8> = None; }
+
+fn zone_of_kind<T>(kind: u8, v: &[T]) -> Zone {
+  unsafe fn cast<T, V: ZoneValue>(v: &[T]) -> Zone {
+    assert!(std::mem::size_of::<T>() == std::mem::size_of::<V>(), "Zone kind {} doesn't match value size", V::KIND);
+    Zone::of(std::slice::from_raw_parts(v.as_ptr() as *const V, v.len()))
+  }
+  unsafe {
+    match kind {
+      1 => cast::<T, i64>(v),
+      2 => cast::<T, i32>(v),
+      3 => cast::<T, u32>(v),
+      4 => cast::<T, i16>(v),
+      5 => cast::<T, u16>(v),
+      6 => cast::<T, i8>(v),
+      7 => cast::<T, u8>(v),
+      _ => panic!("Unknown zone kind {}", kind),
+    }
+  }
+}
+
+// Zoned index pages hold the child page numbers then a Zone for each child
+const ZONED_INDEX_CAPACITY: usize = (PAGE_SIZE - std::mem::size_of::<PageHeader>()) / (4 + std::mem::size_of::<Zone>());
+const ZONES_OFFSET: usize = (ZONED_INDEX_CAPACITY * 4).div_ceil(8) * 8;
+
 #[repr(C)]
 pub struct Page {
   header: PageHeader,
@@ -60,7 +141,7 @@ impl Page {
     (PAGE_SIZE - std::mem::size_of::<PageHeader>()) / std::mem::size_of::<T>()
   }
 
-  fn mut_pref<T>(&mut self) -> MutPageRef<T> {
+  fn mut_pref<T>(&mut self) -> MutPageRef<'_, T> {
     let entries = self.header.entries as usize;
     MutPageRef::<T> {
       header: &mut self.header,
@@ -70,7 +151,7 @@ impl Page {
     }
   }
 
-  fn pref<T>(&self) -> PageRef<T> {
+  fn pref<T>(&self) -> PageRef<'_, T> {
     let entries = self.header.entries as usize;
     PageRef::<T> {
       header: &self.header,
@@ -81,6 +162,40 @@ impl Page {
   fn init(&mut self) {
     self.header = EMPTY_HEADER;
   }
+
+  fn zone_kind(&self) -> u8 {
+    self.header.flags
+  }
+
+  fn index_capacity(&self) -> usize {
+    if self.zone_kind() == 0 { Page::capacity::<u32>() } else { ZONED_INDEX_CAPACITY }
+  }
+
+  // Values under each index entry of a zoned tree
+  fn page_contains<T>(&self) -> usize {
+    self.index_capacity().pow(self.header.depth as u32 - 1) * Page::capacity::<T>()
+  }
+
+  fn zones(&self) -> &[Zone] {
+    debug_assert!(self.zone_kind() != 0 && !self.header.is_leaf());
+    let entries = self.header.entries as usize;
+    unsafe { std::slice::from_raw_parts((&self.data[0] as *const u32 as *const u8).add(ZONES_OFFSET) as *const Zone, entries) }
+  }
+
+  fn mut_zones(&mut self) -> &mut [Zone] {
+    debug_assert!(self.zone_kind() != 0 && !self.header.is_leaf());
+    let entries = self.header.entries as usize;
+    unsafe { std::slice::from_raw_parts_mut((&mut self.data[0] as *mut u32 as *mut u8).add(ZONES_OFFSET) as *mut Zone, entries) }
+  }
+
+  // Summary of the whole subtree, an index page's zones already cover its children
+  fn subtree_zone<T>(&self) -> Zone {
+    if self.header.is_leaf() {
+      zone_of_kind(self.zone_kind(), self.pref::<T>().data)
+    } else {
+      self.zones().iter().fold(Zone::EMPTY, |a, &z| a.merge(z))
+    }
+  }
 }
 
 #[inline(always)]
@@ -94,24 +209,31 @@ fn last_page(page_index: u32, pp: &dyn PageProvider) -> u32 {
   }
 }
 
+// zone is the summary of page_index's subtree for zoned trees
 fn rotate_slice(
   page_index: u32,
   pp: &mut dyn PageProvider,
   depth: u8,
+  kind: u8,
+  zone: Zone,
 ) -> u32 {
   let new_index = pp.alloc(1)[0];
   let (_, new_page) = pp.mut_page(new_index);
   new_page.header = EMPTY_HEADER;
+  new_page.header.flags = kind;
   new_page.header.depth = depth;
   new_page.header.entries = 1;
   let new_page_ref = new_page.mut_pref::<u32>();
   new_page_ref.data[0] = page_index;
+  if kind != 0 {
+    new_page.mut_zones()[0] = zone;
+  }
   new_index
 }
 
-fn append_slice<'a, T: Debug + Copy>(
+pub(crate) fn append_slice<T: Debug + Copy>(
   page_index: u32,
-  v: &'a [T],
+  v: &[T],
   pp: &mut dyn PageProvider,
 ) -> u32 {
   // Root case
@@ -121,10 +243,14 @@ fn append_slice<'a, T: Debug + Copy>(
   // If everything is full rotate existing tree left and create a new right tree
   if !remaining_values.is_empty() {
     println!("Rotation to depth {}", page.header.depth + 1);
+    let kind = page.zone_kind();
+    let zone = if kind != 0 { page.subtree_zone::<T>() } else { Zone::EMPTY };
     let page_index = rotate_slice(
       page_index,
       pp,
       page.header.depth + 1,
+      kind,
+      zone,
     );
     // If rotate didn't create enough space insert what's left into the newly rotated tree
     if !remaining_values.is_empty() {
@@ -148,22 +274,31 @@ fn append_slice_i<'a, T: Debug+Copy>(
     append_slice_leaf(page, v, pp)
   } else {
     // Proceed down to next level
+    let kind = page.zone_kind();
     let page_ref = page.mut_pref::<u32>();
     let page_data = page_ref.data;
-    let next_page_index = page_data[page_ref.header.entries as usize - 1];
+    let last = page_ref.header.entries as usize - 1;
+    let next_page_index = page_data[last];
     let (pp, next_page) = pp.mut_page(next_page_index);
     let residual = append_slice_i(next_page, v, pp);
+    // Widen the last child's zone by whatever went into it
+    if kind != 0 {
+      let added = zone_of_kind(kind, &v[..v.len() - residual.len()]);
+      let zones = page.mut_zones();
+      zones[last] = zones[last].merge(added);
+    }
     if !residual.is_empty() {
       // if the tree is not full
-      if next_page.header.depth + 1 != page_ref.header.depth {
-        let new_index = rotate_slice(next_page_index, pp, next_page.header.depth + 1);
-        page_data[page_data.len() as usize - 1] = new_index;
+      if next_page.header.depth + 1 != page.header.depth {
+        let zone = if kind != 0 { page.zones()[last] } else { Zone::EMPTY };
+        let new_index = rotate_slice(next_page_index, pp, next_page.header.depth + 1, kind, zone);
+        page.mut_pref::<u32>().data[last] = new_index;
         // attempt reinsert since subtrees may not be full
         append_slice_i(page, residual, pp)
       } else {
         // Append a new page to this index layer if it's not full
         let entries = page.header.entries as usize;
-        if entries == Page::capacity::<u32>() {
+        if entries == page.index_capacity() {
           residual
         } else {
           let page_capacity = Page::capacity::<T>();
@@ -171,6 +306,7 @@ fn append_slice_i<'a, T: Debug+Copy>(
           let new_page_index = pp.alloc(1)[0];
           let (pp, new_page) = pp.mut_page(new_page_index);
           new_page.header = EMPTY_HEADER;
+          new_page.header.flags = kind;
           new_page.header.entries = to_take as u16;
           let new_page_ref = new_page.mut_pref::<T>();
           new_page_ref.data.copy_from_slice(&residual[..to_take]);
@@ -181,9 +317,12 @@ fn append_slice_i<'a, T: Debug+Copy>(
           last_page.header.next = new_page_index;          // Link to previous page
 
           // Add the new page to the index
-          page.header.entries += 1 as u16;
+          page.header.entries += 1_u16;
           let page_ref = page.mut_pref::<u32>();
           page_ref.data[entries] = new_page_index;
+          if kind != 0 {
+            page.mut_zones()[entries] = zone_of_kind(kind, &residual[..to_take]);
+          }
 
           let residual = &residual[to_take..];
           if residual.is_empty() {
@@ -213,47 +352,266 @@ fn append_slice_leaf<'a, T: Debug+Copy>(page: &mut Page, v: &'a [T], pp: &mut dy
     page.header.entries += to_take as u16;
     let page_ref = page.mut_pref::<T>();
     (page_ref.data[entries..]).copy_from_slice(&v[..to_take]);
-    let residual = &v[to_take..];
-    residual
+    &v[to_take..]
   }
 }
 
 
-// Shared by get and iterator code
-fn page_ref<T>(page_index: u32, index: usize, pp: &dyn PageProvider) -> (u32, &Page, usize) {
+// Builds a new tree in one pass, leaves are filled in order then index levels are stacked on top
+// Produces the same shape as repeated appends without walking the tree for every page
+pub(crate) fn bulk_load<T: Debug + Copy>(v: &[T], pp: &mut dyn PageProvider) -> u32 {
+  build(v, 0, pp)
+}
+
+// Same as bulk_load but the tree keeps zones, which appends and sets then maintain
+pub(crate) fn bulk_load_zoned<T: Debug + ZoneValue>(v: &[T], pp: &mut dyn PageProvider) -> u32 {
+  build(v, T::KIND, pp)
+}
+
+fn build<T: Debug + Copy>(v: &[T], kind: u8, pp: &mut dyn PageProvider) -> u32 {
+  let leaf_capacity = Page::capacity::<T>();
+  let index_capacity = if kind == 0 { Page::capacity::<u32>() } else { ZONED_INDEX_CAPACITY };
+
+  // Always at least one leaf so an empty vector still has a root
+  let leaf_count = std::cmp::max(1, v.len().div_ceil(leaf_capacity));
+  let mut level = pp.alloc(leaf_count);
+  let mut zones = Vec::new();
+  for i in 0..leaf_count {
+    let start = i * leaf_capacity;
+    let end = std::cmp::min(start + leaf_capacity, v.len());
+    let (_, page) = pp.mut_page(level[i]);
+    page.header = EMPTY_HEADER;
+    page.header.flags = kind;
+    page.header.entries = (end - start) as u16;
+    page.header.next = if i + 1 < leaf_count { level[i + 1] } else { 0 };
+    page.mut_pref::<T>().data.copy_from_slice(&v[start..end]);
+    if kind != 0 {
+      zones.push(zone_of_kind(kind, &v[start..end]));
+    }
+  }
+
+  let mut depth = 0;
+  while level.len() > 1 {
+    depth += 1;
+    let parents = pp.alloc(level.len().div_ceil(index_capacity));
+    let mut parent_zones = Vec::new();
+    for (i, (&parent, children)) in parents.iter().zip(level.chunks(index_capacity)).enumerate() {
+      let (_, page) = pp.mut_page(parent);
+      page.header = EMPTY_HEADER;
+      page.header.flags = kind;
+      page.header.depth = depth;
+      page.header.entries = children.len() as u16;
+      page.mut_pref::<u32>().data.copy_from_slice(children);
+      if kind != 0 {
+        let child_zones = &zones[i * index_capacity..i * index_capacity + children.len()];
+        page.mut_zones().copy_from_slice(child_zones);
+        parent_zones.push(child_zones.iter().fold(Zone::EMPTY, |a, &z| a.merge(z)));
+      }
+    }
+    level = parents;
+    zones = parent_zones;
+  }
+  level[0]
+}
+
+// Shared by get and iterator code, None if the index is past the end of the vector
+fn page_ref<T>(page_index: u32, index: usize, pp: &dyn PageProvider) -> Option<(u32, &Page, usize)> {
   // Get the current page
   let page = pp.page(page_index);
   if page.header.is_leaf() {
-    (page_index, page, index)
+    if index < page.header.entries as usize {
+      Some((page_index, page, index))
+    } else {
+      None
+    }
   } else {
-    let leaf_capacity = Page::capacity::<T>();
-    let index_capacity = Page::capacity::<u32>();
-    let page_contains = index_capacity.pow(page.header.depth as u32 - 1) * leaf_capacity;
+    let page_contains = page.page_contains::<T>();
     let page_data = page.pref::<u32>().data;
-    let next_page = page_data[index / page_contains];
+    let next_page = *page_data.get(index / page_contains)?;
     let next_index = index % page_contains;
     page_ref::<T>(next_page, next_index, pp)
   }
 }
 
-fn get<'a, T: Debug>(
+pub(crate) fn get<T: Debug>(
   page_index: u32,
   index: usize,
-  pp: &'a dyn PageProvider,
-) -> &'a T {
-  let (page_index, page, index) = page_ref::<T>(page_index, index, pp);
+  pp: &dyn PageProvider,
+) -> Option<&T> {
+  let (page_index, page, index) = page_ref::<T>(page_index, index, pp)?;
+  let data = page.pref::<T>().data;
+  Some(&data[index])
+}
+
+// Borrows the range if it sits within one leaf, otherwise copies it out following the leaf chain
+pub(crate) fn read_range<T: Copy>(
+  page_index: u32,
+  r: Range<usize>,
+  pp: &dyn PageProvider,
+) -> Option<Cow<'_, [T]>> {
+  if r.start >= r.end {
+    return Some(Cow::Borrowed(&[]));
+  }
+  let (_page_index, mut page, mut offset) = page_ref::<T>(page_index, r.start, pp)?;
+  let count = r.end - r.start;
   let data = page.pref::<T>().data;
-  &data[index]
+  if offset + count <= data.len() {
+    return Some(Cow::Borrowed(&data[offset..offset + count]));
+  }
+
+  let mut out = Vec::with_capacity(count);
+  while out.len() < count {
+    let data = page.pref::<T>().data;
+    let to_take = std::cmp::min(count - out.len(), data.len() - offset);
+    out.extend_from_slice(&data[offset..offset + to_take]);
+    if out.len() < count {
+      if page.header.next == 0 {
+        return None;
+      }
+      page = pp.page(page.header.next);
+      offset = 0;
+    }
+  }
+  Some(Cow::Owned(out))
+}
+
+pub(crate) fn iter_range<T>(page_index: u32, r: Range<usize>, pp: &dyn PageProvider) -> PagedVectorIterator<'_, T> {
+  let end = std::cmp::min(r.end, len::<T>(page_index, pp));
+  let start = std::cmp::min(r.start, end);
+  let root = pp.page(page_index);
+  let mut it = PagedVectorIterator {
+    db: pp,
+    entry_page: page_index,
+    page: root,
+    offset: 0,
+    back_page: root,
+    back_offset: 0,
+    front: start,
+    back: end,
+    _dummy: std::marker::PhantomData,
+  };
+  // Nothing to walk for an empty range, start may be one past the last leaf
+  if start < end {
+    if let Some((_page_index, page, offset)) = page_ref::<T>(page_index, start, pp) {
+      it.page = page;
+      it.offset = offset;
+    }
+    if let Some((_page_index, page, index)) = page_ref::<T>(page_index, end - 1, pp) {
+      it.back_page = page;
+      it.back_offset = index + 1;
+    }
+  }
+  it
+}
+
+// Overwrite in place, false if the index is past the end of the vector
+// Zones are widened to take the new value but not narrowed for the one it replaces
+pub(crate) fn set<T: Copy>(page_index: u32, index: usize, v: T, pp: &mut dyn PageProvider) -> bool {
+  let (leaf_index, offset) = match page_ref::<T>(page_index, index, pp) {
+    Some((leaf_index, _page, offset)) => (leaf_index, offset),
+    None => return false,
+  };
+  let kind = pp.page(page_index).zone_kind();
+  if kind != 0 {
+    let zone = zone_of_kind(kind, &[v]);
+    let (mut page_index, mut index) = (page_index, index);
+    loop {
+      let (_, page) = pp.mut_page(page_index);
+      if page.header.is_leaf() {
+        break;
+      }
+      let page_contains = page.page_contains::<T>();
+      let child = index / page_contains;
+      page.mut_zones()[child] = page.zones()[child].merge(zone);
+      page_index = page.pref::<u32>().data[child];
+      index %= page_contains;
+    }
+  }
+  let (_, page) = pp.mut_page(leaf_index);
+  page.mut_pref::<T>().data[offset] = v;
+  true
+}
+
+// Zone of the whole vector, None if it doesn't keep zones
+pub(crate) fn zone<T>(page_index: u32, pp: &dyn PageProvider) -> Option<Zone> {
+  let page = pp.page(page_index);
+  if page.zone_kind() == 0 {
+    None
+  } else {
+    Some(page.subtree_zone::<T>())
+  }
+}
+
+// Index ranges that may hold values in [lo, hi], in order and merged where they touch
+// Subtrees whose zone misses are skipped without reading them, leaves are never read
+// Vectors without zones give back the whole range
+pub(crate) fn zone_ranges<T>(page_index: u32, lo: i64, hi: i64, pp: &dyn PageProvider) -> Vec<Range<usize>> {
+  matching_ranges::<T>(page_index, &|z| z.overlaps(lo, hi), pp)
+}
+
+// Index ranges that may hold the NULL value
+pub(crate) fn null_ranges<T>(page_index: u32, pp: &dyn PageProvider) -> Vec<Range<usize>> {
+  matching_ranges::<T>(page_index, &|z| z.nulls, pp)
+}
+
+fn matching_ranges<T>(page_index: u32, f: &dyn Fn(&Zone) -> bool, pp: &dyn PageProvider) -> Vec<Range<usize>> {
+  let len = len::<T>(page_index, pp);
+  let mut ranges = Vec::new();
+  zone_ranges_i::<T>(page_index, 0, f, pp, &mut ranges);
+  let mut out : Vec<Range<usize>> = Vec::new();
+  for r in ranges {
+    let r = std::cmp::min(r.start, len)..std::cmp::min(r.end, len);
+    match out.last_mut() {
+      _ if r.is_empty() => {},
+      Some(last) if last.end == r.start => last.end = r.end,
+      _ => out.push(r),
+    }
+  }
+  out
+}
+
+fn zone_ranges_i<T>(page_index: u32, base: usize, f: &dyn Fn(&Zone) -> bool, pp: &dyn PageProvider, out: &mut Vec<Range<usize>>) {
+  let page = pp.page(page_index);
+  if page.header.is_leaf() {
+    out.push(base..base + page.header.entries as usize);
+    return;
+  }
+  let page_contains = page.page_contains::<T>();
+  if page.zone_kind() == 0 {
+    out.push(base..base + page.header.entries as usize * page_contains);
+    return;
+  }
+  let children = page.pref::<u32>().data;
+  for (i, (&child, zone)) in children.iter().zip(page.zones()).enumerate() {
+    if !f(zone) {
+      continue;
+    }
+    let start = base + i * page_contains;
+    // Only the last leaf can be part full, zone_ranges trims to the length
+    if page.header.depth == 1 {
+      out.push(start..start + page_contains);
+    } else {
+      zone_ranges_i::<T>(child, start, f, pp, out);
+    }
+  }
 }
 
 // Walking the index is common regardless of type
-pub trait PagedVectorFns<'a, T> {
+pub trait PagedVectorFns<T> {
   fn push(&mut self, v: &T);
   fn append(&mut self, v: &[T]);
   fn get(&self, i: usize) -> &T;
-  fn iter_from(&'a self, i: usize) -> PagedVectorIterator<T>;
-  fn iter(&'a self) -> PagedVectorIterator<T>;
+  fn try_get(&self, i: usize) -> Option<&T>;
+  fn set(&mut self, i: usize, v: &T) -> bool;
+  fn slice(&self, r: Range<usize>) -> Option<Cow<'_, [T]>> where [T]: ToOwned;
+  fn iter_range(&self, r: Range<usize>) -> PagedVectorIterator<'_, T>;
+  fn iter_from(&self, i: usize) -> PagedVectorIterator<'_, T>;
+  fn iter(&self) -> PagedVectorIterator<'_, T>;
   fn len(&self) -> usize;
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
 }
 
 pub struct PagedVector<'a, T> {
@@ -262,7 +620,43 @@ pub struct PagedVector<'a, T> {
   _dummy: std::marker::PhantomData<T>
 }
 
-impl<'a, T: Debug+Copy> PagedVectorFns<'a, T> for PagedVector<'a, T> {
+impl<'a, T: Debug+Copy> PagedVector<'a, T> {
+  // Faster than append for building a vector from existing data
+  pub fn bulk_load(db: &'a mut dyn PageProvider, v: &[T]) -> PagedVector<'a, T> {
+    let entry_page = bulk_load(v, db);
+    PagedVector {
+      db,
+      entry_page,
+      _dummy: std::marker::PhantomData,
+    }
+  }
+
+  pub fn zone(&self) -> Option<Zone> {
+    zone::<T>(self.entry_page, self.db)
+  }
+
+  pub fn zone_ranges(&self, lo: i64, hi: i64) -> Vec<Range<usize>> {
+    zone_ranges::<T>(self.entry_page, lo, hi, self.db)
+  }
+
+  pub fn null_ranges(&self) -> Vec<Range<usize>> {
+    null_ranges::<T>(self.entry_page, self.db)
+  }
+}
+
+impl<'a, T: Debug+ZoneValue> PagedVector<'a, T> {
+  // Keeps a min/max zone for each index entry so zone_ranges can skip subtrees
+  pub fn bulk_load_zoned(db: &'a mut dyn PageProvider, v: &[T]) -> PagedVector<'a, T> {
+    let entry_page = bulk_load_zoned(v, db);
+    PagedVector {
+      db,
+      entry_page,
+      _dummy: std::marker::PhantomData,
+    }
+  }
+}
+
+impl<'a, T: Debug+Copy> PagedVectorFns<T> for PagedVector<'a, T> {
   fn push(&mut self, v: &T) {
     self.entry_page = append_slice(self.entry_page, &[*v], self.db);
   }
@@ -272,19 +666,36 @@ impl<'a, T: Debug+Copy> PagedVectorFns<'a, T> for PagedVector<'a, T> {
   }
 
   fn get(&self, i: usize) -> &T {
+    match self.try_get(i) {
+      Some(v) => v,
+      None => panic!("Index {} out of bounds for PagedVector of length {}", i, self.len()),
+    }
+  }
+
+  fn try_get(&self, i: usize) -> Option<&T> {
     get(self.entry_page, i, self.db)
   }
 
-  fn iter_from(&'a self, i: usize) -> PagedVectorIterator<T> {
-    let (_page_index, page, index) = page_ref::<T>(self.entry_page, i, self.db);
-    PagedVectorIterator {
-      vector: self,
-      page: page,
-      offset: index,
+  fn set(&mut self, i: usize, v: &T) -> bool {
+    set(self.entry_page, i, *v, self.db)
+  }
+
+  fn slice(&self, r: Range<usize>) -> Option<Cow<'_, [T]>> {
+    if r.end > self.len() {
+      return None;
     }
+    read_range(self.entry_page, r, self.db)
+  }
+
+  fn iter_range(&self, r: Range<usize>) -> PagedVectorIterator<'_, T> {
+    iter_range(self.entry_page, r, self.db)
+  }
+
+  fn iter_from(&self, i: usize) -> PagedVectorIterator<'_, T> {
+    self.iter_range(i..usize::MAX)
   }
 
-  fn iter(&'a self) -> PagedVectorIterator<T> {
+  fn iter(&self) -> PagedVectorIterator<'_, T> {
     self.iter_from(0)
   }
 
@@ -294,14 +705,12 @@ impl<'a, T: Debug+Copy> PagedVectorFns<'a, T> for PagedVector<'a, T> {
 }
 
 
-fn len<T>(page_index: u32, pp: &dyn PageProvider) -> usize {
+pub(crate) fn len<T>(page_index: u32, pp: &dyn PageProvider) -> usize {
   let page = pp.page(page_index);
   if page.header.is_leaf() {
     page.header.entries as usize
   } else {
-    let leaf_capacity = Page::capacity::<T>();
-    let index_capacity = Page::capacity::<u32>();
-    let page_contains = index_capacity.pow(page.header.depth as u32 - 1) * leaf_capacity;
+    let page_contains = page.page_contains::<T>();
     let page_data = page.pref::<u32>().data;
     let entries = page.header.entries;
     (entries as usize - 1) * page_contains + len::<T>(page_data[entries as usize - 1], pp)
@@ -309,51 +718,92 @@ fn len<T>(page_index: u32, pp: &dyn PageProvider) -> usize {
 }
 
 
-impl<'a, T:'a+Copy+Debug> IntoIterator for &'a PagedVector<'a, T> {
+impl<'a, 'b, T: Copy+Debug> IntoIterator for &'b PagedVector<'a, T> {
   type Item = T;
-  type IntoIter = PagedVectorIterator<'a, Self::Item>;
+  type IntoIter = PagedVectorIterator<'b, Self::Item>;
 
   fn into_iter(self) -> Self::IntoIter {
     self.iter()
   }
 }
 
+// front/back are absolute indices of the remaining range [front, back)
+// page/offset is the next value from the front, back_page/back_offset is one past the next value from the back
 pub struct PagedVectorIterator<'a, T> {
-  vector: &'a PagedVector<'a, T>,
+  db: &'a dyn PageProvider,
+  entry_page: u32,
   page: &'a Page,
   offset: usize,
+  back_page: &'a Page,
+  back_offset: usize,
+  front: usize,
+  back: usize,
+  _dummy: std::marker::PhantomData<T>
 }
 
 impl<'a, T : Copy> Iterator for PagedVectorIterator<'a, T> {
   type Item = T;
   fn next(&mut self) -> Option<T> {
-    let page_data = self.page.pref::<T>().data;
+    if self.front >= self.back {
+      return None;
+    }
 
-    if self.offset < page_data.len() {
-      let val = page_data[self.offset];
-      self.offset += 1;
-      Some(val)
-    } else {
-      if self.page.header.next != 0 {
-        self.page = self.vector.db.page(self.page.header.next);
-        self.offset = 0;
-        let page_data = self.page.pref::<T>().data;
-        let val = page_data[self.offset];
-        self.offset += 1;
-        Some(val)
-      } else {
-        None
+    // Skip over exhausted and empty leaves
+    while self.offset >= self.page.pref::<T>().data.len() {
+      if self.page.header.next == 0 {
+        self.front = self.back;
+        return None;
       }
+      self.page = self.db.page(self.page.header.next);
+      self.offset = 0;
     }
+    let val = self.page.pref::<T>().data[self.offset];
+    self.offset += 1;
+    self.front += 1;
+    Some(val)
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    let remaining = self.back.saturating_sub(self.front);
+    (remaining, Some(remaining))
   }
 }
 
-#[cfg(test)]
-use crate::database::{MemoryPageProvider};
+// Leaves are only linked forwards, so stepping back over a page boundary descends the index again
+impl<'a, T : Copy> DoubleEndedIterator for PagedVectorIterator<'a, T> {
+  fn next_back(&mut self) -> Option<T> {
+    if self.front >= self.back {
+      return None;
+    }
 
+    if self.back_offset == 0 {
+      match page_ref::<T>(self.entry_page, self.back - 1, self.db) {
+        Some((_page_index, page, index)) => {
+          self.back_page = page;
+          self.back_offset = index + 1;
+        }
+        None => {
+          self.back = self.front;
+          return None;
+        }
+      }
+    }
+    self.back_offset -= 1;
+    self.back -= 1;
+    Some(self.back_page.pref::<T>().data[self.back_offset])
+  }
+}
+
+impl<'a, T : Copy> ExactSizeIterator for PagedVectorIterator<'a, T> {}
+
+#[cfg(test)]
 pub mod tests {
   #[allow(unused_imports)]
   use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use proptest::prelude::*;
 
   #[test]
   // #[ignore] // Takes way to long to run, but necessary
@@ -568,6 +1018,370 @@ pub mod tests {
     assert!(p.len() == inserts * slice_len);
   }
 
+  #[test]
+  pub fn iter_rev() {
+    let mut pp = MemoryPageProvider::new();
+    let root = pp.alloc(1)[0];
+    let (pp, page) = pp.mut_page(root);
+    page.header = EMPTY_HEADER;
+
+    let mut p = PagedVector::<u32> {
+      db: pp,
+      entry_page: root,
+      _dummy : std::marker::PhantomData,
+    };
+
+    // Enough for a second index level
+    let entries = 1500000u32;
+    let values : Vec<u32> = (0..entries).collect();
+    p.append(&values);
+
+    let mut count = entries;
+    p.iter().rev().for_each(|x| {
+      count -= 1;
+      assert!(x == count);
+    });
+    assert!(count == 0);
+
+    // Latest N
+    let latest : Vec<u32> = p.iter().rev().take(3000).collect();
+    assert!(latest.len() == 3000);
+    assert!(latest[0] == entries - 1);
+    assert!(latest[2999] == entries - 3000);
+
+    // Meet in the middle
+    let mut it = p.iter();
+    let mut front = 0;
+    let mut back = entries;
+    while let Some(x) = it.next() {
+      assert!(x == front);
+      front += 1;
+      match it.next_back() {
+        Some(y) => { back -= 1; assert!(y == back) },
+        None => break,
+      }
+    }
+    assert!(front == back);
+  }
+
+  #[test]
+  pub fn iter_range() {
+    let mut pp = MemoryPageProvider::new();
+    let root = pp.alloc(1)[0];
+    let (pp, page) = pp.mut_page(root);
+    page.header = EMPTY_HEADER;
+
+    let mut p = PagedVector::<u32> {
+      db: pp,
+      entry_page: root,
+      _dummy : std::marker::PhantomData,
+    };
+
+    // Exactly fill two leaves so the end of the range is on a page boundary
+    let entries = Page::capacity::<u32>() * 2;
+    let values : Vec<u32> = (0..entries as u32).collect();
+    p.append(&values);
+
+    let it = p.iter_range(1000..1100);
+    assert!(it.len() == 100);
+    assert!(it.eq(1000..1100));
+
+    let it = p.iter_range(1000..1100).rev();
+    assert!(it.eq((1000..1100).rev()));
+
+    assert!(p.iter().len() == entries);
+    assert!(p.iter_range(entries..entries).next().is_none());
+    assert!(p.iter_range(entries - 1..entries * 2).eq(entries as u32 - 1..entries as u32));
+    assert!(p.iter_from(entries).len() == 0);
+
+    let mut it = p.iter_range(10..20);
+    it.next();
+    it.next_back();
+    assert!(it.len() == 8);
+
+    let mut pp = MemoryPageProvider::new();
+    let root = pp.alloc(1)[0];
+    let (pp, page) = pp.mut_page(root);
+    page.header = EMPTY_HEADER;
+
+    let empty = PagedVector::<u32> {
+      db: pp,
+      entry_page: root,
+      _dummy : std::marker::PhantomData,
+    };
+    assert!(empty.iter().len() == 0);
+    assert!(empty.iter().next_back().is_none());
+  }
+
+  #[test]
+  pub fn try_get() {
+    let mut pp = MemoryPageProvider::new();
+    let root = pp.alloc(1)[0];
+    let (pp, page) = pp.mut_page(root);
+    page.header = EMPTY_HEADER;
+
+    let mut p = PagedVector::<u32> {
+      db: pp,
+      entry_page: root,
+      _dummy : std::marker::PhantomData,
+    };
+
+    assert!(p.try_get(0).is_none());
+
+    let entries = Page::capacity::<u32>() * 3;
+    let values : Vec<u32> = (0..entries as u32).collect();
+    p.append(&values);
+
+    assert!(*p.try_get(0).unwrap() == 0);
+    assert!(*p.try_get(entries - 1).unwrap() == entries as u32 - 1);
+    assert!(p.try_get(entries).is_none());
+    assert!(p.try_get(entries * 1000).is_none());
+
+    p.push(&7);
+    assert!(*p.try_get(entries).unwrap() == 7);
+    assert!(p.try_get(entries + 1).is_none());
+
+    // Within a leaf borrows, across leaves copies
+    let cap = Page::capacity::<u32>();
+    assert!(matches!(p.slice(10..20), Some(Cow::Borrowed(s)) if s == &values[10..20]));
+    assert!(matches!(p.slice(cap - 5..cap * 2 + 5), Some(Cow::Owned(ref s)) if s[..] == values[cap - 5..cap * 2 + 5]));
+    assert!(p.slice(entries..entries + 2).is_none());
+
+    assert!(p.set(cap + 1, &42));
+    assert!(*p.get(cap + 1) == 42);
+    assert!(!p.set(entries + 1, &42));
+  }
+
+  #[test]
+  #[should_panic]
+  pub fn get_out_of_bounds() {
+    let mut pp = MemoryPageProvider::new();
+    let root = pp.alloc(1)[0];
+    let (pp, page) = pp.mut_page(root);
+    page.header = EMPTY_HEADER;
+
+    let mut p = PagedVector::<u32> {
+      db: pp,
+      entry_page: root,
+      _dummy : std::marker::PhantomData,
+    };
+    p.push(&1);
+    p.get(1);
+  }
+
+  #[test]
+  pub fn iter_empty_leaves() {
+    // Chain leaves by hand 3 values -> empty -> 2 values -> empty
+    let mut pp = MemoryPageProvider::new();
+    let pages = [pp.alloc(1)[0], pp.alloc(1)[0], pp.alloc(1)[0], pp.alloc(1)[0]];
+    let counts = [3, 0, 2, 0];
+    let mut value = 0u32;
+    for i in 0..pages.len() {
+      let (_, page) = pp.mut_page(pages[i]);
+      page.header = EMPTY_HEADER;
+      page.header.entries = counts[i];
+      page.header.next = if i + 1 < pages.len() { pages[i + 1] } else { 0 };
+      let page_ref = page.mut_pref::<u32>();
+      for v in page_ref.data.iter_mut() {
+        *v = value;
+        value += 1;
+      }
+    }
+
+    let first = pp.page(pages[0]);
+    let it = PagedVectorIterator::<u32> {
+      db: &pp,
+      entry_page: pages[0],
+      page: first,
+      offset: 0,
+      back_page: first,
+      back_offset: 0,
+      front: 0,
+      back: 5,
+      _dummy: std::marker::PhantomData,
+    };
+    assert!(it.eq(0..5));
+
+    // Claims more than the chain holds, must stop rather than walk off the end
+    let mut it = PagedVectorIterator::<u32> {
+      db: &pp,
+      entry_page: pages[0],
+      page: first,
+      offset: 0,
+      back_page: first,
+      back_offset: 0,
+      front: 0,
+      back: 10,
+      _dummy: std::marker::PhantomData,
+    };
+    assert!(it.by_ref().take(5).eq(0..5));
+    assert!(it.next().is_none());
+    assert!(it.len() == 0);
+  }
+
+  #[test]
+  pub fn bulk_load() {
+    let leaf_capacity = Page::capacity::<u32>();
+    let index_capacity = Page::capacity::<u32>();
+    let sizes = [0, 1, leaf_capacity, leaf_capacity + 1, leaf_capacity * index_capacity, leaf_capacity * index_capacity + 7];
+    for &size in sizes.iter() {
+      let values : Vec<u32> = (0..size as u32).collect();
+
+      let mut bulk_pp = MemoryPageProvider::new();
+      let mut bulk = PagedVector::<u32>::bulk_load(&mut bulk_pp, &values);
+
+      let mut pp = MemoryPageProvider::new();
+      let root = pp.alloc(1)[0];
+      let (pp, page) = pp.mut_page(root);
+      page.header = EMPTY_HEADER;
+      let mut appended = PagedVector::<u32> {
+        db: pp,
+        entry_page: root,
+        _dummy : std::marker::PhantomData,
+      };
+      appended.append(&values);
+
+      // Same shape as the appended tree
+      assert!(bulk.db.page(bulk.entry_page).header.depth == appended.db.page(appended.entry_page).header.depth);
+      assert!(bulk.len() == size);
+      assert!(bulk.iter().eq(values.iter().cloned()));
+      assert!(bulk.iter().rev().eq(values.iter().rev().cloned()));
+      for i in (0..size).step_by(97) {
+        assert!(*bulk.get(i) == i as u32);
+      }
+
+      // Appending carries on from a bulk loaded tree
+      let extra : Vec<u32> = (size as u32..size as u32 + 3000).collect();
+      bulk.append(&extra);
+      appended.append(&extra);
+      assert!(bulk.len() == size + 3000);
+      assert!(bulk.iter().eq(appended.iter()));
+    }
+  }
+
+  #[test]
+  pub fn zones() {
+    // Timestamps that mostly increase, so each leaf covers a narrow band
+    let values : Vec<i64> = (0..250000i64).map(|i| 1_000_000 + i * 10 + (i * 7919) % 13).collect();
+    let mut bulk_pp = MemoryPageProvider::new();
+    let mut bulk = PagedVector::<i64>::bulk_load_zoned(&mut bulk_pp, &values[..100000]);
+    bulk.append(&values[100000..]);
+    let mut pp = MemoryPageProvider::new();
+    let mut appended = PagedVector::<i64>::bulk_load_zoned(&mut pp, &[]);
+    for chunk in values.chunks(777) {
+      appended.append(chunk);
+    }
+
+    let leaf_capacity = Page::capacity::<i64>();
+    for p in [&bulk, &appended].iter() {
+      assert!(p.len() == values.len() && p.iter().eq(values.iter().cloned()));
+      // Deep enough that zones are checked below the root
+      assert!(p.db.page(p.entry_page).header.depth == 2);
+      assert!(p.zone() == Some(Zone::of(&values)));
+      let bounds = [(1_000_500, 1_001_000), (2_000_000, 2_000_000), (0, 999_999), (3_400_000, i64::MAX), (i64::MIN, i64::MAX)];
+      for &(lo, hi) in bounds.iter() {
+        let ranges = p.zone_ranges(lo, hi);
+        let matches : Vec<usize> = (0..values.len()).filter(|&i| values[i] >= lo && values[i] <= hi).collect();
+        assert!(matches.iter().all(|i| ranges.iter().any(|r| r.contains(i))), "{}..={}", lo, hi);
+        let scanned : usize = ranges.iter().map(|r| r.len()).sum();
+        assert!(scanned <= matches.len() + 2 * leaf_capacity, "{}..={} scans {}", lo, hi, scanned);
+      }
+    }
+    assert!(bulk.zone_ranges(0, 999_999).is_empty());
+    assert!(bulk.zone_ranges(i64::MIN, i64::MAX).iter().eq(std::iter::once(&(0..values.len()))));
+
+    // Sets widen the zones on the way down
+    bulk.set(150000, &5_000_000);
+    assert!(bulk.zone().unwrap().max == 5_000_000);
+    let ranges = bulk.zone_ranges(5_000_000, 5_000_000);
+    assert!(ranges.len() == 1 && ranges[0].contains(&150000) && ranges[0].len() <= leaf_capacity);
+
+    // NULL is flagged rather than pulling the minimum down
+    assert!(bulk.null_ranges().is_empty());
+    bulk.set(200000, &i64::MIN);
+    bulk.append(&[i64::MIN]);
+    assert!(bulk.zone().unwrap().nulls && bulk.zone().unwrap().min == values[0]);
+    let ranges = bulk.null_ranges();
+    assert!(ranges.len() == 2 && ranges[0].contains(&200000) && ranges[1].contains(&values.len()));
+    assert!(bulk.zone_ranges(1_000_500, 1_001_000)[0].start == 0);
+    assert!(bulk.zone_ranges(1_000_500, 1_001_000).iter().map(|r| r.len()).sum::<usize>() <= leaf_capacity);
+
+    // Without zones the whole vector is a candidate
+    let mut plain_pp = MemoryPageProvider::new();
+    let plain = PagedVector::<i64>::bulk_load(&mut plain_pp, &values);
+    assert!(plain.zone().is_none());
+    assert!(plain.zone_ranges(0, 1).iter().eq(std::iter::once(&(0..values.len()))));
+  }
+
+  #[derive(Debug, Clone)]
+  pub enum Op {
+    Push(u32),
+    Append(Vec<u32>),
+    Get(prop::sample::Index),
+    IterRange(prop::sample::Index, prop::sample::Index),
+    IterRev(prop::sample::Index),
+  }
+
+  fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+      any::<u32>().prop_map(Op::Push),
+      // Up to a few pages at a time so appends straddle leaves
+      prop::collection::vec(any::<u32>(), 0..(Page::capacity::<u32>() * 3)).prop_map(Op::Append),
+      any::<prop::sample::Index>().prop_map(Op::Get),
+      (any::<prop::sample::Index>(), any::<prop::sample::Index>()).prop_map(|(a, b)| Op::IterRange(a, b)),
+      any::<prop::sample::Index>().prop_map(Op::IterRev),
+    ]
+  }
+
+  proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn matches_vec(ops in prop::collection::vec(op(), 1..40)) {
+      let mut pp = MemoryPageProvider::new();
+      let root = pp.alloc(1)[0];
+      let (pp, page) = pp.mut_page(root);
+      page.header = EMPTY_HEADER;
+
+      let mut p = PagedVector::<u32> {
+        db: pp,
+        entry_page: root,
+        _dummy : std::marker::PhantomData,
+      };
+      let mut model : Vec<u32> = Vec::new();
+
+      for op in ops {
+        match op {
+          Op::Push(v) => { p.push(&v); model.push(v); }
+          Op::Append(vs) => { p.append(&vs); model.extend_from_slice(&vs); }
+          Op::Get(i) => {
+            if !model.is_empty() {
+              let i = i.index(model.len());
+              prop_assert_eq!(p.try_get(i), model.get(i));
+            }
+          }
+          Op::IterRange(a, b) => {
+            // Allow one past the end on both sides
+            let a = a.index(model.len() + 2);
+            let b = b.index(model.len() + 2);
+            let (start, end) = (std::cmp::min(a, b), std::cmp::max(a, b));
+            let expected = model.iter().skip(start).take(end - start).cloned();
+            prop_assert_eq!(p.iter_range(start..end).len(), expected.len());
+            prop_assert!(p.iter_range(start..end).eq(expected));
+          }
+          Op::IterRev(n) => {
+            let n = n.index(model.len() + 1);
+            prop_assert!(p.iter().rev().take(n).eq(model.iter().rev().take(n).cloned()));
+          }
+        }
+        prop_assert_eq!(p.len(), model.len());
+        prop_assert_eq!(p.try_get(model.len()), None);
+        prop_assert_eq!(p.iter().len(), model.len());
+      }
+      prop_assert!(p.iter().eq(model.iter().cloned()));
+    }
+  }
+
   #[test]
   #[ignore] // Takes way to long to run, but necessary
   pub fn add_even_more() {
@@ -0,0 +1,315 @@
+// Run length encoded values over a set of pages
+// Two PagedVectors sharing one PageProvider
+//  values: the value of each run
+//  ends: row after the last row of each run, run r covers ends[r-1]..ends[r]
+// Row i is found by binary search over ends, aggregates work a run at a time
+// A header page records both roots, it's the only page to keep
+
+#![allow(dead_code)]
+
+use crate::database::{PageProvider};
+use crate::paged_vector::{self, PagedVectorIterator};
+use std::fmt::Debug;
+use std::ops::Range;
+
+const RLE_VECTOR_VERSION: u8 = 0;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct RleVectorHeader {
+  version: u8,
+  padding: [u8; 3],
+  values: u32,
+  ends: u32,
+}
+
+pub struct RleVector<'a, T> {
+  db: &'a mut dyn PageProvider,
+  header: u32,
+  values: u32,
+  ends: u32,
+  _dummy: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: Debug + Copy + PartialEq> RleVector<'a, T> {
+  pub fn new(db: &'a mut dyn PageProvider) -> RleVector<'a, T> {
+    let values = paged_vector::bulk_load::<T>(&[], db);
+    let ends = paged_vector::bulk_load::<u64>(&[], db);
+    let header = db.alloc(1)[0];
+    let mut v = RleVector { db, header, values, ends, _dummy: std::marker::PhantomData };
+    v.write_header();
+    v
+  }
+
+  // Vector previously created whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> RleVector<'a, T> {
+    let h = unsafe { *(db.page_bytes(header).as_ptr() as *const RleVectorHeader) };
+    assert!(h.version == RLE_VECTOR_VERSION, "Unknown run length vector version {} on page {}", h.version, header);
+    RleVector { db, header, values: h.values, ends: h.ends, _dummy: std::marker::PhantomData }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  // The roots move as they grow
+  fn write_header(&mut self) {
+    let h = RleVectorHeader { version: RLE_VECTOR_VERSION, padding: [0; 3], values: self.values, ends: self.ends };
+    unsafe { *(self.db.mut_page_bytes(self.header).as_mut_ptr() as *mut RleVectorHeader) = h };
+  }
+
+  pub fn push(&mut self, v: T) {
+    self.push_run(v, 1)
+  }
+
+  // Adds count copies of v, extending the last run if it has the same value
+  pub fn push_run(&mut self, v: T, count: usize) {
+    if count == 0 {
+      return;
+    }
+    let runs = self.runs();
+    let end = self.len() as u64 + count as u64;
+    if runs > 0 && *paged_vector::get::<T>(self.values, runs - 1, self.db).unwrap() == v {
+      paged_vector::set::<u64>(self.ends, runs - 1, end, self.db);
+    } else {
+      self.values = paged_vector::append_slice(self.values, &[v], self.db);
+      self.ends = paged_vector::append_slice(self.ends, &[end], self.db);
+      self.write_header();
+    }
+  }
+
+  pub fn append(&mut self, vs: &[T]) {
+    let mut i = 0;
+    while i < vs.len() {
+      let run = vs[i..].iter().take_while(|&&v| v == vs[i]).count();
+      self.push_run(vs[i], run);
+      i += run;
+    }
+  }
+
+  pub fn get(&self, i: usize) -> T {
+    match self.try_get(i) {
+      Some(v) => v,
+      None => panic!("Index {} out of bounds for RleVector of length {}", i, self.len()),
+    }
+  }
+
+  pub fn try_get(&self, i: usize) -> Option<T> {
+    if i >= self.len() {
+      return None;
+    }
+    paged_vector::get::<T>(self.values, self.run_of(i), self.db).cloned()
+  }
+
+  pub fn len(&self) -> usize {
+    match self.runs() {
+      0 => 0,
+      n => self.end(n - 1),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Number of runs, the stored size of the column
+  pub fn runs(&self) -> usize {
+    paged_vector::len::<u64>(self.ends, self.db)
+  }
+
+  fn end(&self, run: usize) -> usize {
+    *paged_vector::get::<u64>(self.ends, run, self.db).unwrap() as usize
+  }
+
+  // First run ending after row i
+  fn run_of(&self, i: usize) -> usize {
+    let (mut lo, mut hi) = (0, self.runs());
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      if self.end(mid) <= i { lo = mid + 1 } else { hi = mid }
+    }
+    lo
+  }
+
+  // (value, length) of each run starting with the one holding row i
+  pub fn iter_runs_from(&self, i: usize) -> RleRunIterator<'_, T> {
+    let i = std::cmp::min(i, self.len());
+    let run = self.run_of(i);
+    RleRunIterator {
+      values: paged_vector::iter_range::<T>(self.values, run..usize::MAX, self.db),
+      ends: paged_vector::iter_range::<u64>(self.ends, run..usize::MAX, self.db),
+      start: i,
+    }
+  }
+
+  pub fn iter_runs(&self) -> RleRunIterator<'_, T> {
+    self.iter_runs_from(0)
+  }
+
+  pub fn iter_from(&self, i: usize) -> RleVectorIterator<'_, T> {
+    let i = std::cmp::min(i, self.len());
+    RleVectorIterator {
+      runs: self.iter_runs_from(i),
+      value: None,
+      remaining: 0,
+      total: self.len() - i,
+    }
+  }
+
+  pub fn iter(&self) -> RleVectorIterator<'_, T> {
+    self.iter_from(0)
+  }
+
+  // Rows matching f, tested once per run
+  pub fn count_where<F: Fn(&T) -> bool>(&self, f: F) -> usize {
+    self.iter_runs().filter(|(v, _)| f(v)).map(|(_, n)| n).sum()
+  }
+
+  pub fn count(&self, v: &T) -> usize {
+    self.count_where(|x| x == v)
+  }
+
+  // Sum of rows r, each run adds value * length
+  pub fn sum_range(&self, r: Range<usize>) -> i128 where T: Into<i128> {
+    let mut remaining = std::cmp::min(r.end, self.len()).saturating_sub(r.start);
+    let mut sum = 0;
+    for (v, n) in self.iter_runs_from(r.start) {
+      if remaining == 0 {
+        break;
+      }
+      let n = std::cmp::min(n, remaining);
+      sum += v.into() * n as i128;
+      remaining -= n;
+    }
+    sum
+  }
+
+  pub fn sum(&self) -> i128 where T: Into<i128> {
+    self.sum_range(0..self.len())
+  }
+}
+
+// start is the first row still to return, so the first run can be partial
+pub struct RleRunIterator<'a, T> {
+  values: PagedVectorIterator<'a, T>,
+  ends: PagedVectorIterator<'a, u64>,
+  start: usize,
+}
+
+impl<'a, T: Copy> Iterator for RleRunIterator<'a, T> {
+  type Item = (T, usize);
+  fn next(&mut self) -> Option<(T, usize)> {
+    let v = self.values.next()?;
+    let end = self.ends.next()? as usize;
+    let n = end - self.start;
+    self.start = end;
+    Some((v, n))
+  }
+}
+
+pub struct RleVectorIterator<'a, T> {
+  runs: RleRunIterator<'a, T>,
+  value: Option<T>,
+  remaining: usize,
+  total: usize,
+}
+
+impl<'a, T: Copy> Iterator for RleVectorIterator<'a, T> {
+  type Item = T;
+  fn next(&mut self) -> Option<T> {
+    while self.remaining == 0 {
+      let (v, n) = self.runs.next()?;
+      self.value = Some(v);
+      self.remaining = n;
+    }
+    self.remaining -= 1;
+    self.total -= 1;
+    self.value
+  }
+
+  fn size_hint(&self) -> (usize, Option<usize>) {
+    (self.total, Some(self.total))
+  }
+}
+
+impl<'a, T: Copy> ExactSizeIterator for RleVectorIterator<'a, T> {}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  #[test]
+  pub fn push_get() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = RleVector::<u32>::new(&mut pp);
+    assert!(v.is_empty());
+    assert!(v.try_get(0).is_none());
+    assert!(v.iter().next().is_none());
+
+    v.append(&[1, 1, 1, 2, 2, 1]);
+    v.push(1);
+    v.push_run(3, 4);
+    v.push_run(4, 0);
+    assert!(v.len() == 11);
+    assert!(v.runs() == 4);
+    let expected = [1, 1, 1, 2, 2, 1, 1, 3, 3, 3, 3];
+    for (i, &x) in expected.iter().enumerate() {
+      assert!(v.get(i) == x);
+    }
+    assert!(v.try_get(11).is_none());
+    assert!(v.iter().eq(expected.iter().cloned()));
+    assert!(v.iter_from(4).eq(expected[4..].iter().cloned()));
+    assert!(v.iter_runs_from(4).eq(vec![(2, 1), (1, 2), (3, 4)]));
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let header = {
+      let mut v = RleVector::<u32>::new(&mut pp);
+      (0..3000).for_each(|r| v.push_run(r % 3, 4));
+      v.header_page()
+    };
+    let mut v = RleVector::<u32>::open(&mut pp, header);
+    assert!(v.runs() == 3000 && v.len() == 12000);
+    v.push_run(2, 6);
+    v.push_run(7, 1);
+    let v = RleVector::<u32>::open(&mut pp, header);
+    assert!(v.runs() == 3001 && v.len() == 12007);
+    assert!(v.iter().eq((0..3000u32).flat_map(|r| std::iter::repeat_n(r % 3, 4)).chain([2, 2, 2, 2, 2, 2, 7].iter().cloned())));
+  }
+
+  #[test]
+  pub fn many_runs() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = RleVector::<i64>::new(&mut pp);
+    // Runs of 1 to 50 rows, enough runs to fill several leaves
+    let mut expected = Vec::new();
+    for r in 0..5000i64 {
+      let n = (r * 7) % 50 + 1;
+      let value = (r % 5) - 2;
+      v.push_run(value, n as usize);
+      expected.extend((0..n).map(|_| value));
+    }
+    assert!(v.runs() == 5000);
+    assert!(v.len() == expected.len());
+    for i in (0..expected.len()).step_by(7) {
+      assert!(v.get(i) == expected[i], "index {}", i);
+    }
+    assert!(v.iter().len() == expected.len());
+    assert!(v.iter().eq(expected.iter().cloned()));
+    for &start in [1, 1000, expected.len() - 1, expected.len()].iter() {
+      assert!(v.iter_from(start).eq(expected[start..].iter().cloned()));
+    }
+
+    assert!(v.count(&0) == expected.iter().filter(|&&x| x == 0).count());
+    assert!(v.count_where(|&x| x < 0) == expected.iter().filter(|&&x| x < 0).count());
+    assert!(v.sum() == expected.iter().map(|&x| x as i128).sum::<i128>());
+    assert!(v.sum_range(100..20000) == expected[100..20000].iter().map(|&x| x as i128).sum::<i128>());
+    assert!(v.sum_range(20000..usize::MAX) == expected[20000..].iter().map(|&x| x as i128).sum::<i128>());
+  }
+}
@@ -0,0 +1,567 @@
+// Columnar scan of a table, reading the columns a query needs in lockstep a batch at a time
+// Predicates are pushed down to the columns before any row is read
+//  Int columns compare values, Ids and String columns compare dictionary IDs: a predicate on
+//  values is translated once into the set of IDs it matches, using the sorted order if the
+//  dictionary keeps one, so no row is decoded to test it
+//  Int and Ids columns have zones, only row ranges whose zones could match are read
+// NULL is NULL_INT or NULL_ID, it never matches a comparison, only IsNull
+// Matching rows come back as batches of their positions and the requested columns' values
+
+#![allow(dead_code)]
+
+use crate::bitmap::{Bitmap};
+use crate::catalog::{Catalog, CatalogError, ColumnId, ColumnType, NULL_ID, NULL_INT};
+use crate::database::{PageProvider};
+use crate::dict_column::{self};
+use crate::paged_vector::{self};
+use std::ops::{Bound, Range};
+
+// Rows read from each column at a time
+pub const BATCH_ROWS: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+  Null,
+  Int(i64),
+  Str(String),
+  Float(f64), // Only produced by aggregates, never compared
+}
+
+// Columns are named within the scanned table, Str values compare as bytes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+  Eq(String, Value),
+  Lt(String, Value),
+  Le(String, Value),
+  Gt(String, Value),
+  Ge(String, Value),
+  Between(String, Value, Value), // Both ends included
+  In(String, Vec<Value>),
+  IsNull(String),
+  And(Vec<Predicate>), // Every row if empty
+  Or(Vec<Predicate>),
+}
+
+impl Predicate {
+  pub fn all() -> Predicate {
+    Predicate::And(Vec::new())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BatchColumn {
+  Int(Vec<i64>), // NULL_INT for NULL
+  Ids(Vec<u32>), // IDs in the column's dictionary for Ids and String columns, NULL_ID for NULL
+}
+
+impl BatchColumn {
+  pub fn len(&self) -> usize {
+    match self {
+      BatchColumn::Int(vs) => vs.len(),
+      BatchColumn::Ids(ids) => ids.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Values of the rows set in selected
+  fn select(&self, selected: &Bitmap) -> BatchColumn {
+    match self {
+      BatchColumn::Int(vs) => BatchColumn::Int(selected.iter().map(|i| vs[i]).collect()),
+      BatchColumn::Ids(ids) => BatchColumn::Ids(selected.iter().map(|i| ids[i]).collect()),
+    }
+  }
+}
+
+// rows are positions in the table, columns are in the order requested
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+  pub rows: Vec<usize>,
+  pub columns: Vec<BatchColumn>,
+}
+
+// A predicate on one column reduced to what the column stores
+#[derive(Clone, Debug)]
+enum Test {
+  Never,
+  Null,
+  Ints(i64, i64),  // Between, both ends included
+  IntIn(Vec<i64>), // Sorted
+  Ids(Bitmap),     // Matching IDs
+}
+
+#[derive(Clone, Debug)]
+enum Filter {
+  Test(usize, Test), // Index into the scan's sources
+  And(Vec<Filter>),
+  Or(Vec<Filter>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Source {
+  column: ColumnId,
+  column_type: ColumnType,
+  data: u32,
+}
+
+pub struct Scan<'b> {
+  db: &'b dyn PageProvider,
+  sources: Vec<Source>,
+  outputs: Vec<usize>, // Sources of the requested columns
+  filter: Filter,
+  ranges: Vec<Range<usize>>,
+  range: usize,
+  row: usize,
+}
+
+fn not_found(table: &str, name: &str) -> CatalogError {
+  CatalogError::NotFound(format!("{}.{}", table, name))
+}
+
+fn wrong_type(table: &str, name: &str) -> CatalogError {
+  CatalogError::WrongType(format!("{}.{}", table, name))
+}
+
+// Rows in both, ranges are sorted and don't overlap
+fn intersect(a: &[Range<usize>], b: &[Range<usize>]) -> Vec<Range<usize>> {
+  let (mut i, mut j, mut out) = (0, 0, Vec::new());
+  while i < a.len() && j < b.len() {
+    let r = std::cmp::max(a[i].start, b[j].start)..std::cmp::min(a[i].end, b[j].end);
+    if !r.is_empty() {
+      out.push(r);
+    }
+    if a[i].end < b[j].end { i += 1 } else { j += 1 }
+  }
+  out
+}
+
+fn union(a: &[Range<usize>], b: &[Range<usize>]) -> Vec<Range<usize>> {
+  let mut all : Vec<Range<usize>> = a.iter().chain(b.iter()).cloned().collect();
+  all.sort_by_key(|r| r.start);
+  let mut out : Vec<Range<usize>> = Vec::new();
+  for r in all {
+    match out.last_mut() {
+      Some(last) if r.start <= last.end => last.end = std::cmp::max(last.end, r.end),
+      _ => out.push(r),
+    }
+  }
+  out
+}
+
+fn in_bounds<T: PartialOrd + ?Sized>(v: &T, lo: Bound<&T>, hi: Bound<&T>) -> bool {
+  let above = match lo {
+    Bound::Included(x) => v >= x,
+    Bound::Excluded(x) => v > x,
+    Bound::Unbounded => true,
+  };
+  let below = match hi {
+    Bound::Included(x) => v <= x,
+    Bound::Excluded(x) => v < x,
+    Bound::Unbounded => true,
+  };
+  above && below
+}
+
+// Builds the sources and filter, translating values with the dictionaries
+struct Planner<'c, 'a> {
+  catalog: &'c mut Catalog<'a>,
+  table: String,
+  sources: Vec<Source>,
+}
+
+impl<'c, 'a> Planner<'c, 'a> {
+  fn source(&mut self, name: &str) -> Result<usize, CatalogError> {
+    let column = self.catalog.find_column(&self.table, name).ok_or_else(|| not_found(&self.table, name))?;
+    if let Some(i) = self.sources.iter().position(|s| s.column == column) {
+      return Ok(i);
+    }
+    let column_type = self.catalog.column(column)?.column_type;
+    let data = self.catalog.data_page(column)?;
+    self.sources.push(Source { column, column_type, data });
+    Ok(self.sources.len() - 1)
+  }
+
+  fn compile(&mut self, p: &Predicate) -> Result<Filter, CatalogError> {
+    use Bound::*;
+    let (name, lo, hi) = match p {
+      Predicate::And(ps) => return Ok(Filter::And(ps.iter().map(|p| self.compile(p)).collect::<Result<_, _>>()?)),
+      Predicate::Or(ps) => return Ok(Filter::Or(ps.iter().map(|p| self.compile(p)).collect::<Result<_, _>>()?)),
+      Predicate::In(name, vs) => return self.compile_in(name, vs),
+      Predicate::Eq(name, v) => return self.compile_in(name, std::slice::from_ref(v)),
+      Predicate::IsNull(name) => return Ok(Filter::Test(self.source(name)?, Test::Null)),
+      Predicate::Lt(name, v) => (name, Unbounded, Excluded(v)),
+      Predicate::Le(name, v) => (name, Unbounded, Included(v)),
+      Predicate::Gt(name, v) => (name, Excluded(v), Unbounded),
+      Predicate::Ge(name, v) => (name, Included(v), Unbounded),
+      Predicate::Between(name, a, b) => (name, Included(a), Included(b)),
+    };
+    let slot = self.source(name)?;
+    if matches!(lo, Included(Value::Null) | Excluded(Value::Null)) || matches!(hi, Included(Value::Null) | Excluded(Value::Null)) {
+      return Ok(Filter::Test(slot, Test::Never));
+    }
+    let test = match self.sources[slot].column_type {
+      ColumnType::Int => {
+        let int = |v: &Value| match v {
+          Value::Int(x) => Ok(*x),
+          _ => Err(wrong_type(&self.table, name)),
+        };
+        // NULL_INT is below every value, so comparisons start above it
+        let lo = match lo {
+          Included(v) => Some(std::cmp::max(int(v)?, NULL_INT + 1)),
+          Excluded(v) => int(v)?.checked_add(1),
+          Unbounded => Some(NULL_INT + 1),
+        };
+        let hi = match hi {
+          Included(v) => Some(int(v)?),
+          Excluded(v) => int(v)?.checked_sub(1),
+          Unbounded => Some(i64::MAX),
+        };
+        match (lo, hi) {
+          (Some(lo), Some(hi)) if lo <= hi => Test::Ints(lo, hi),
+          _ => Test::Never,
+        }
+      }
+      _ => {
+        let bytes = |v: &Value| match v {
+          Value::Str(s) => Ok(s.clone().into_bytes()),
+          _ => Err(wrong_type(&self.table, name)),
+        };
+        let lo = match lo { Included(v) => Included(bytes(v)?), Excluded(v) => Excluded(bytes(v)?), Unbounded => Unbounded };
+        let hi = match hi { Included(v) => Included(bytes(v)?), Excluded(v) => Excluded(bytes(v)?), Unbounded => Unbounded };
+        let (lo, hi) = (lo.as_ref().map(|v| &v[..]), hi.as_ref().map(|v| &v[..]));
+        let column = self.sources[slot].column;
+        let dictionary = self.catalog.column(column)?.dictionary.unwrap();
+        let d = self.catalog.dictionary(dictionary)?;
+        let ids = match d.sorted() {
+          Some(sorted) => sorted.range(lo, hi),
+          None => {
+            let mut ids = Bitmap::new(d.len());
+            (0..d.len()).filter(|&id| in_bounds(&d.get(id as u32)[..], lo, hi)).for_each(|id| ids.set(id));
+            ids
+          }
+        };
+        Test::Ids(ids)
+      }
+    };
+    Ok(Filter::Test(slot, test))
+  }
+
+  fn compile_in(&mut self, name: &str, vs: &[Value]) -> Result<Filter, CatalogError> {
+    let slot = self.source(name)?;
+    let vs : Vec<&Value> = vs.iter().filter(|&v| *v != Value::Null).collect();
+    let test = match self.sources[slot].column_type {
+      ColumnType::Int => {
+        let mut ints = vs.iter().map(|v| match v {
+          Value::Int(x) if *x != NULL_INT => Ok(Some(*x)),
+          Value::Int(_) => Ok(None),
+          _ => Err(wrong_type(&self.table, name)),
+        }).filter_map(|x| x.transpose()).collect::<Result<Vec<i64>, _>>()?;
+        ints.sort_unstable();
+        ints.dedup();
+        match ints.len() {
+          0 => Test::Never,
+          1 => Test::Ints(ints[0], ints[0]),
+          _ => Test::IntIn(ints),
+        }
+      }
+      _ => {
+        let column = self.sources[slot].column;
+        let dictionary = self.catalog.column(column)?.dictionary.unwrap();
+        let d = self.catalog.dictionary(dictionary)?;
+        let mut ids = Bitmap::new(d.len());
+        for v in vs {
+          match v {
+            Value::Str(s) => if let Some(id) = d.lookup(s.as_bytes()) { ids.set(id as usize) },
+            _ => return Err(wrong_type(&self.table, name)),
+          }
+        }
+        Test::Ids(ids)
+      }
+    };
+    Ok(Filter::Test(slot, test))
+  }
+}
+
+impl Filter {
+  // Row ranges that could match, from the zones of the columns that have them
+  fn candidates(&self, sources: &[Source], rows: usize, db: &dyn PageProvider) -> Vec<Range<usize>> {
+    let all = || std::iter::once(0..rows).filter(|r| !r.is_empty()).collect::<Vec<_>>();
+    match self {
+      Filter::And(fs) => fs.iter().fold(all(), |r, f| intersect(&r, &f.candidates(sources, rows, db))),
+      Filter::Or(fs) => fs.iter().fold(Vec::new(), |r, f| union(&r, &f.candidates(sources, rows, db))),
+      Filter::Test(slot, test) => {
+        let s = sources[*slot];
+        let ranges = match (s.column_type, test) {
+          (_, Test::Never) => return Vec::new(),
+          (ColumnType::String, _) => return all(),
+          (ColumnType::Int, Test::Null) => paged_vector::null_ranges::<i64>(s.data, db),
+          (_, Test::Null) => paged_vector::null_ranges::<u32>(s.data, db),
+          (_, Test::Ints(lo, hi)) => paged_vector::zone_ranges::<i64>(s.data, *lo, *hi, db),
+          (_, Test::IntIn(vs)) => paged_vector::zone_ranges::<i64>(s.data, vs[0], vs[vs.len() - 1], db),
+          (_, Test::Ids(ids)) => match (ids.iter().next(), ids.iter().last()) {
+            (Some(lo), Some(hi)) => paged_vector::zone_ranges::<u32>(s.data, lo as i64, hi as i64, db),
+            _ => return Vec::new(),
+          },
+        };
+        intersect(&ranges, &all())
+      }
+    }
+  }
+
+  // Rows of a batch that match, columns are the batch's values for each source
+  fn eval(&self, columns: &[BatchColumn], n: usize) -> Bitmap {
+    match self {
+      Filter::And(fs) => fs.iter().fold(Bitmap::full(n), |mut b, f| { b.and(&f.eval(columns, n)); b }),
+      Filter::Or(fs) => fs.iter().fold(Bitmap::new(n), |mut b, f| { b.or(&f.eval(columns, n)); b }),
+      Filter::Test(slot, test) => {
+        let mut b = Bitmap::new(n);
+        match (test, &columns[*slot]) {
+          (Test::Ints(lo, hi), BatchColumn::Int(vs)) => {
+            vs.iter().enumerate().filter(|(_, v)| lo <= v && *v <= hi).for_each(|(i, _)| b.set(i));
+          }
+          (Test::IntIn(set), BatchColumn::Int(vs)) => {
+            vs.iter().enumerate().filter(|(_, v)| set.binary_search(v).is_ok()).for_each(|(i, _)| b.set(i));
+          }
+          (Test::Ids(set), BatchColumn::Ids(ids)) => {
+            ids.iter().enumerate().filter(|(_, &id)| (id as usize) < set.len() && set.get(id as usize)).for_each(|(i, _)| b.set(i));
+          }
+          (Test::Null, BatchColumn::Int(vs)) => {
+            vs.iter().enumerate().filter(|(_, &v)| v == NULL_INT).for_each(|(i, _)| b.set(i));
+          }
+          (Test::Null, BatchColumn::Ids(ids)) => {
+            ids.iter().enumerate().filter(|(_, &id)| id == NULL_ID).for_each(|(i, _)| b.set(i));
+          }
+          _ => {}
+        }
+        b
+      }
+    }
+  }
+}
+
+// Rows of table matching predicate with the values of columns, see Scan
+pub fn scan<'b, 'a>(catalog: &'b mut Catalog<'a>, table: &str, columns: &[&str], predicate: &Predicate) -> Result<Scan<'b>, CatalogError> {
+  let table_columns = catalog.columns(table);
+  if table_columns.is_empty() {
+    return Err(CatalogError::NotFound(table.to_string()));
+  }
+  let mut planner = Planner { catalog, table: table.to_string(), sources: Vec::new() };
+  let outputs = columns.iter().map(|c| planner.source(c)).collect::<Result<Vec<usize>, _>>()?;
+  let filter = planner.compile(predicate)?;
+  let sources = planner.sources;
+  let catalog : &'b Catalog<'a> = planner.catalog;
+
+  // Columns are appended to separately, a row needs all of them
+  let rows = table_columns.iter().map(|c| catalog.rows(c.id)).collect::<Result<Vec<usize>, _>>()?.into_iter().min().unwrap();
+  let ranges = filter.candidates(&sources, rows, catalog.db());
+  let start = ranges.first().map(|r| r.start).unwrap_or(0);
+  Ok(Scan { db: catalog.db(), sources, outputs, filter, ranges, range: 0, row: start })
+}
+
+// Values of column at rows, which needn't be sorted, runs of consecutive rows are read together
+pub fn fetch(catalog: &Catalog, table: &str, column: &str, rows: &[usize]) -> Result<BatchColumn, CatalogError> {
+  let id = catalog.find_column(table, column).ok_or_else(|| not_found(table, column))?;
+  let (column_type, data, db) = (catalog.column(id)?.column_type, catalog.data_page(id)?, catalog.db());
+  let mut out = match column_type {
+    ColumnType::Int => BatchColumn::Int(Vec::with_capacity(rows.len())),
+    _ => BatchColumn::Ids(Vec::with_capacity(rows.len())),
+  };
+  let mut i = 0;
+  while i < rows.len() {
+    let mut j = i + 1;
+    while j < rows.len() && rows[j] == rows[j - 1] + 1 {
+      j += 1;
+    }
+    let run = rows[i]..rows[j - 1] + 1;
+    let before = out.len();
+    match (&mut out, column_type) {
+      (BatchColumn::Int(vs), _) => vs.extend(paged_vector::iter_range::<i64>(data, run, db)),
+      (BatchColumn::Ids(ids), ColumnType::Ids) => ids.extend(paged_vector::iter_range::<u32>(data, run, db)),
+      (BatchColumn::Ids(ids), _) => ids.extend(dict_column::ids_from(data, run.start, db).take(run.len())),
+    }
+    if out.len() - before != j - i {
+      return Err(CatalogError::NotFound(format!("{}.{} row {}", table, column, rows[j - 1])));
+    }
+    i = j;
+  }
+  Ok(out)
+}
+
+impl<'b> Scan<'b> {
+  // Rows the zones couldn't rule out, the most the scan will read
+  pub fn candidate_rows(&self) -> usize {
+    self.ranges.iter().map(|r| r.len()).sum()
+  }
+
+  fn read(&self, s: &Source, rows: Range<usize>) -> BatchColumn {
+    match s.column_type {
+      ColumnType::Int => BatchColumn::Int(paged_vector::iter_range::<i64>(s.data, rows, self.db).collect()),
+      ColumnType::Ids => BatchColumn::Ids(paged_vector::iter_range::<u32>(s.data, rows, self.db).collect()),
+      ColumnType::String => BatchColumn::Ids(dict_column::ids_from(s.data, rows.start, self.db).take(rows.len()).collect()),
+    }
+  }
+}
+
+impl<'b> Iterator for Scan<'b> {
+  type Item = Batch;
+
+  // Batches with no matching rows are skipped, so every batch has at least one row
+  fn next(&mut self) -> Option<Batch> {
+    while self.range < self.ranges.len() {
+      let range = self.ranges[self.range].clone();
+      let rows = self.row..std::cmp::min(self.row + BATCH_ROWS, range.end);
+      self.row = rows.end;
+      if self.row == range.end {
+        self.range += 1;
+        self.row = self.ranges.get(self.range).map(|r| r.start).unwrap_or(0);
+      }
+
+      let columns : Vec<BatchColumn> = self.sources.iter().map(|s| self.read(s, rows.clone())).collect();
+      let selected = self.filter.eval(&columns, rows.len());
+      if selected.count() == 0 {
+        continue;
+      }
+      return Some(Batch {
+        rows: selected.iter().map(|i| rows.start + i).collect(),
+        columns: self.outputs.iter().map(|&o| columns[o].select(&selected)).collect(),
+      });
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  // Events shared with the aggregate and sort tests
+  // kind is a String column with a sorted dictionary and user an Ids column with an unsorted one,
+  // whose 12 names aren't in ID order as user10 and user11 sort before user2
+  pub(crate) struct Event {
+    pub(crate) ts: i64,
+    pub(crate) kind: Option<String>,
+    pub(crate) user: u32,
+  }
+
+  pub(crate) fn user_name(user: u32) -> String {
+    format!("user{}", user)
+  }
+
+  pub(crate) fn events() -> Vec<Event> {
+    (0..20000i64).map(|i| Event {
+      ts: if i % 97 == 5 { NULL_INT } else { 1_000_000 + i * 60 + (i * 7919) % 50 },
+      kind: if i % 13 == 0 { None } else { Some(format!("kind{}", (i * 31) % 40)) },
+      user: (i % 12) as u32,
+    }).collect()
+  }
+
+  pub(crate) fn setup(pp: &mut MemoryPageProvider, events: &[Event]) -> u32 {
+    let mut c = Catalog::new(pp);
+    let kinds = c.create_dictionary("kinds", true).unwrap();
+    let users = c.create_dictionary("users", false).unwrap();
+    let ts = c.add_column("events", "ts", ColumnType::Int, None).unwrap();
+    let kind = c.add_column("events", "kind", ColumnType::String, Some(kinds)).unwrap();
+    let user = c.add_column("events", "user", ColumnType::Ids, Some(users)).unwrap();
+    {
+      let mut d = c.dictionary(users).unwrap();
+      (0..12).for_each(|u| { d.add(user_name(u).as_bytes()); });
+    }
+    c.append_ints(ts, &events.iter().map(|e| e.ts).collect::<Vec<i64>>()).unwrap();
+    c.append_ids(user, &events.iter().map(|e| e.user).collect::<Vec<u32>>()).unwrap();
+    let mut column = c.strings(kind).unwrap();
+    for e in events.iter() {
+      match &e.kind {
+        Some(k) => column.push(k),
+        None => column.push_null(),
+      }
+    }
+    c.header_page()
+  }
+
+  fn rows(c: &mut Catalog, p: &Predicate) -> Vec<usize> {
+    let batches : Vec<Batch> = scan(c, "events", &["ts"], p).unwrap().collect();
+    assert!(batches.iter().all(|b| !b.rows.is_empty() && b.rows.len() == b.columns[0].len()));
+    batches.into_iter().flat_map(|b| b.rows).collect()
+  }
+
+  fn expected(events: &[Event], f: impl Fn(&Event) -> bool) -> Vec<usize> {
+    (0..events.len()).filter(|&i| f(&events[i])).collect()
+  }
+
+  fn s(v: &str) -> Value {
+    Value::Str(v.to_string())
+  }
+
+  #[test]
+  pub fn predicates() {
+    let mut pp = MemoryPageProvider::new();
+    let events = events();
+    let header = setup(&mut pp, &events);
+    let mut c = Catalog::open(&mut pp, header);
+    let ts = |e: &Event| if e.ts == NULL_INT { None } else { Some(e.ts) };
+    let kind = |e: &Event| e.kind.clone();
+
+    assert!(rows(&mut c, &Predicate::all()) == expected(&events, |_| true));
+    assert!(rows(&mut c, &Predicate::Eq("ts".into(), Value::Int(1_000_060 + 7919 % 50))) == vec![1]);
+    assert!(rows(&mut c, &Predicate::Lt("ts".into(), Value::Int(1_003_000))) == expected(&events, |e| ts(e).is_some_and(|t| t < 1_003_000)));
+    assert!(rows(&mut c, &Predicate::Between("ts".into(), Value::Int(1_500_000), Value::Int(1_500_500))) == expected(&events, |e| ts(e).is_some_and(|t| (1_500_000..=1_500_500).contains(&t))));
+    assert!(rows(&mut c, &Predicate::IsNull("ts".into())) == expected(&events, |e| e.ts == NULL_INT));
+    assert!(rows(&mut c, &Predicate::IsNull("kind".into())) == expected(&events, |e| e.kind.is_none()));
+    assert!(rows(&mut c, &Predicate::Eq("kind".into(), s("kind7"))) == expected(&events, |e| kind(e).as_deref() == Some("kind7")));
+    assert!(rows(&mut c, &Predicate::Eq("kind".into(), s("missing"))).is_empty());
+    assert!(rows(&mut c, &Predicate::Ge("kind".into(), s("kind35"))) == expected(&events, |e| kind(e).is_some_and(|k| k.as_str() >= "kind35")));
+    assert!(rows(&mut c, &Predicate::In("user".into(), vec![s("user1"), s("user5"), s("nobody"), Value::Null])) == expected(&events, |e| e.user == 1 || e.user == 5));
+    assert!(rows(&mut c, &Predicate::Lt("user".into(), s("user3"))) == expected(&events, |e| user_name(e.user).as_str() < "user3"));
+    assert!(rows(&mut c, &Predicate::Eq("ts".into(), Value::Null)).is_empty());
+
+    let p = Predicate::And(vec![
+      Predicate::Gt("ts".into(), Value::Int(1_200_000)),
+      Predicate::Or(vec![Predicate::In("ts".into(), vec![Value::Int(1_000_000), Value::Int(1_000_120 + 2 * 7919 % 50)]), Predicate::Eq("user".into(), s("user2"))]),
+      Predicate::IsNull("kind".into()),
+    ]);
+    assert!(rows(&mut c, &p) == expected(&events, |e| ts(e).is_some_and(|t| t > 1_200_000) && e.user == 2 && e.kind.is_none()));
+  }
+
+  #[test]
+  pub fn batches() {
+    let mut pp = MemoryPageProvider::new();
+    let events = events();
+    let header = setup(&mut pp, &events);
+    let mut c = Catalog::open(&mut pp, header);
+
+    // Zones rule out most of the table for a narrow range of timestamps
+    let p = Predicate::Between("ts".into(), Value::Int(1_600_000), Value::Int(1_601_000));
+    let scan = scan(&mut c, "events", &["kind", "ts", "user"], &p).unwrap();
+    assert!(scan.candidate_rows() <= 2 * 511);
+    let batches : Vec<Batch> = scan.collect();
+    let rows : Vec<usize> = batches.iter().flat_map(|b| b.rows.iter().cloned()).collect();
+    assert!(rows == expected(&events, |e| e.ts >= 1_600_000 && e.ts <= 1_601_000));
+    for b in batches.iter() {
+      assert!(b.columns.len() == 3 && b.rows.len() <= BATCH_ROWS);
+      match (&b.columns[1], &b.columns[2]) {
+        (BatchColumn::Int(ts), BatchColumn::Ids(users)) => {
+          assert!(b.rows.iter().zip(ts).all(|(&r, &t)| events[r].ts == t));
+          assert!(b.rows.iter().zip(users).all(|(&r, &u)| events[r].user == u));
+        }
+        _ => panic!("Wrong column types"),
+      }
+    }
+
+    // Zones know no user is NULL
+    assert!(super::scan(&mut c, "events", &[], &Predicate::IsNull("user".into())).unwrap().candidate_rows() == 0);
+
+    // Every batch is full when every row matches
+    let batches : Vec<Batch> = super::scan(&mut c, "events", &["user"], &Predicate::all()).unwrap().collect();
+    assert!(batches.len() == events.len().div_ceil(BATCH_ROWS));
+    assert!(batches[0].rows.len() == BATCH_ROWS);
+
+    assert!(matches!(super::scan(&mut c, "events", &["nope"], &Predicate::all()), Err(CatalogError::NotFound(_))));
+    assert!(matches!(super::scan(&mut c, "nope", &[], &Predicate::all()), Err(CatalogError::NotFound(_))));
+    assert!(matches!(super::scan(&mut c, "events", &[], &Predicate::Eq("ts".into(), s("x"))), Err(CatalogError::WrongType(_))));
+  }
+}
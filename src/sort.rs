@@ -0,0 +1,136 @@
+// Row positions of a table's matching rows ordered by one or more columns
+// Every row's key is a list of i64, one per sort column, compared in order and then by row:
+//  Int columns: the value, NULL_INT sorts before every other value
+//  Ids and String columns: the ID's rank in value order, with NULL first.
+//   A sorted dictionary already has its IDs in value order, others are sorted once per sort
+// Descending columns flip the bits of the key, which reverses the order and can't overflow
+// top_n keeps the first n rows in a heap as the scan goes, rather than sorting every row
+
+#![allow(dead_code)]
+
+use crate::catalog::{Catalog, CatalogError, ColumnType, NULL_ID};
+use crate::scan::{self, BatchColumn, Predicate};
+use std::collections::BinaryHeap;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SortKey {
+  pub column: String,
+  pub descending: bool,
+}
+
+impl SortKey {
+  pub fn asc(column: &str) -> SortKey {
+    SortKey { column: column.to_string(), descending: false }
+  }
+
+  pub fn desc(column: &str) -> SortKey {
+    SortKey { column: column.to_string(), descending: true }
+  }
+}
+
+// Rank of each ID in value order, None for Int columns
+fn ranks(catalog: &mut Catalog, table: &str, key: &SortKey) -> Result<Option<Vec<u32>>, CatalogError> {
+  let id = catalog.find_column(table, &key.column).ok_or_else(|| CatalogError::NotFound(format!("{}.{}", table, key.column)))?;
+  let column = catalog.column(id)?;
+  if column.column_type == ColumnType::Int {
+    return Ok(None);
+  }
+  let d = catalog.dictionary(column.dictionary.unwrap())?;
+  let order = match d.sorted() {
+    Some(sorted) => sorted.sorted_ids(),
+    None => {
+      let mut ids : Vec<u32> = (0..d.len() as u32).collect();
+      ids.sort_by(|&a, &b| d.get(a).cmp(&d.get(b)));
+      ids
+    }
+  };
+  let mut ranks = vec![0; d.len()];
+  order.iter().enumerate().for_each(|(rank, &id)| ranks[id as usize] = rank as u32);
+  Ok(Some(ranks))
+}
+
+// Calls f with each matching row's key and position, in row order
+fn keys(catalog: &mut Catalog, table: &str, keys: &[SortKey], predicate: &Predicate, mut f: impl FnMut(Vec<i64>, usize)) -> Result<(), CatalogError> {
+  let ranks = keys.iter().map(|k| ranks(catalog, table, k)).collect::<Result<Vec<_>, _>>()?;
+  let columns : Vec<&str> = keys.iter().map(|k| &k.column[..]).collect();
+  for batch in scan::scan(catalog, table, &columns, predicate)? {
+    for (i, &row) in batch.rows.iter().enumerate() {
+      let key = batch.columns.iter().zip(keys.iter().zip(ranks.iter())).map(|(c, (k, ranks))| {
+        let v = match (c, ranks) {
+          (BatchColumn::Int(vs), _) => vs[i],
+          (BatchColumn::Ids(ids), _) if ids[i] == NULL_ID => -1,
+          (BatchColumn::Ids(ids), Some(ranks)) => ranks[ids[i] as usize] as i64,
+          (BatchColumn::Ids(ids), None) => ids[i] as i64,
+        };
+        if k.descending { !v } else { v }
+      }).collect();
+      f(key, row);
+    }
+  }
+  Ok(())
+}
+
+// Every matching row, ordered by keys
+pub fn sort(catalog: &mut Catalog, table: &str, by: &[SortKey], predicate: &Predicate) -> Result<Vec<usize>, CatalogError> {
+  let mut rows : Vec<(Vec<i64>, usize)> = Vec::new();
+  keys(catalog, table, by, predicate, |key, row| rows.push((key, row)))?;
+  rows.sort_unstable();
+  Ok(rows.into_iter().map(|(_, row)| row).collect())
+}
+
+// The first n rows sort would return
+pub fn top_n(catalog: &mut Catalog, table: &str, by: &[SortKey], predicate: &Predicate, n: usize) -> Result<Vec<usize>, CatalogError> {
+  // Max heap of the n first rows so far, the top is the one to drop next
+  let mut heap : BinaryHeap<(Vec<i64>, usize)> = BinaryHeap::with_capacity(n + 1);
+  keys(catalog, table, by, predicate, |key, row| {
+    if heap.len() < n {
+      heap.push((key, row));
+    } else if heap.peek().is_some_and(|top| (&key, row) < (&top.0, top.1)) {
+      heap.pop();
+      heap.push((key, row));
+    }
+  })?;
+  Ok(heap.into_sorted_vec().into_iter().map(|(_, row)| row).collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+  #[allow(unused_imports)]
+  use crate::scan::Value;
+  #[allow(unused_imports)]
+  use crate::scan::tests::{events, setup, user_name};
+
+  #[test]
+  pub fn sorts() {
+    let mut pp = MemoryPageProvider::new();
+    let events = events();
+    let header = setup(&mut pp, &events);
+    let mut c = Catalog::open(&mut pp, header);
+    let all = Predicate::all();
+
+    // Kind ascending with NULL first, then ts descending with NULL last, then user by name
+    let by = [SortKey::asc("kind"), SortKey::desc("ts"), SortKey::asc("user")];
+    let mut expected : Vec<usize> = (0..events.len()).collect();
+    expected.sort_by(|&a, &b| {
+      let (a, b) = (&events[a], &events[b]);
+      a.kind.cmp(&b.kind).then(b.ts.cmp(&a.ts)).then(user_name(a.user).cmp(&user_name(b.user)))
+    });
+    assert!(sort(&mut c, "events", &by, &all).unwrap() == expected);
+    for &n in [0, 1, 10, 19999, 20000, 25000].iter() {
+      assert!(top_n(&mut c, "events", &by, &all, n).unwrap()[..] == expected[..std::cmp::min(n, expected.len())]);
+    }
+
+    // Ties are in row order, and only matching rows are sorted
+    let late = Predicate::Ge("ts".into(), Value::Int(1_600_000));
+    let mut expected : Vec<usize> = (0..events.len()).filter(|&i| events[i].ts >= 1_600_000).collect();
+    expected.sort_by(|&a, &b| user_name(events[b].user).cmp(&user_name(events[a].user)));
+    assert!(sort(&mut c, "events", &[SortKey::desc("user")], &late).unwrap() == expected);
+    assert!(top_n(&mut c, "events", &[SortKey::desc("user")], &late, 25).unwrap()[..] == expected[..25]);
+
+    assert!(matches!(sort(&mut c, "events", &[SortKey::asc("nope")], &all), Err(CatalogError::NotFound(_))));
+  }
+}
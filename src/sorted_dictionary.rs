@@ -0,0 +1,310 @@
+// Dictionary that also keeps its IDs in value order
+// IDs come from an ArrayDictionary so they stay stable, the order lives in a separate index
+//  directory: PagedVector<u32> of leaf pages in order
+//  leaf pages: up to LEAF_CAPACITY IDs sorted by value, split in half when full
+// so adding a value only shifts IDs within one leaf, rather than rewriting a sorted array
+// Range and prefix predicates become a Bitmap of matching IDs to test a column against
+
+#![allow(dead_code)]
+
+use crate::bitmap::{Bitmap};
+use crate::database::{PageProvider};
+use crate::dictionary::{ArrayDictionary, Dictionary};
+use crate::paged_vector::{self};
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::hash::{Hash};
+use std::ops::Bound;
+
+const SORTED_DICTIONARY_VERSION: u8 = 0;
+const PAGE_SIZE_SHIFT: u8 = 12;
+const PAGE_SIZE: usize = 1 << PAGE_SIZE_SHIFT;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct LeafHeader {
+  count: u32,
+  padding: u32,
+}
+
+const LEAF_CAPACITY: usize = (PAGE_SIZE - std::mem::size_of::<LeafHeader>()) / 4;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct SortedDictionaryHeader {
+  version: u8,
+  padding: [u8; 3],
+  dictionary: u32, // Header page of the ArrayDictionary
+  directory: u32,  // Entry page of the leaf directory
+}
+
+pub struct SortedDictionary<'a, T> {
+  dictionary: ArrayDictionary<'a, T>,
+  header: u32,
+  directory: u32,
+}
+
+fn leaf(db: &dyn PageProvider, page: u32) -> &[u32] {
+  let bytes = db.page_bytes(page);
+  let h = unsafe { *(bytes.as_ptr() as *const LeafHeader) };
+  let ids = &bytes[std::mem::size_of::<LeafHeader>()..];
+  unsafe { std::slice::from_raw_parts(ids.as_ptr() as *const u32, h.count as usize) }
+}
+
+fn write_leaf(db: &mut dyn PageProvider, page: u32, ids: &[u32]) {
+  let bytes = db.mut_page_bytes(page);
+  let h = LeafHeader { count: ids.len() as u32, padding: 0 };
+  unsafe {
+    *(bytes.as_mut_ptr() as *mut LeafHeader) = h;
+    let out = bytes[std::mem::size_of::<LeafHeader>()..].as_mut_ptr() as *mut u32;
+    std::ptr::copy_nonoverlapping(ids.as_ptr(), out, ids.len());
+  }
+}
+
+// Position in the index, offset can be one past the end of a leaf
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Position {
+  leaf: usize,
+  offset: usize,
+}
+
+impl<'a, T: Debug + Copy + Ord + Hash> SortedDictionary<'a, T> {
+  pub fn new(db: &'a mut dyn PageProvider) -> SortedDictionary<'a, T> {
+    let header = db.alloc(1)[0];
+    let first_leaf = db.alloc(1)[0];
+    write_leaf(db, first_leaf, &[]);
+    let directory = paged_vector::bulk_load::<u32>(&[first_leaf], db);
+    let mut d = SortedDictionary { dictionary: ArrayDictionary::new(db), header, directory };
+    d.write_header();
+    d
+  }
+
+  // Dictionary previously created with new whose header is on page header
+  pub fn open(db: &'a mut dyn PageProvider, header: u32) -> SortedDictionary<'a, T> {
+    let h = unsafe { *(db.page_bytes(header).as_ptr() as *const SortedDictionaryHeader) };
+    assert!(h.version == SORTED_DICTIONARY_VERSION, "Unknown sorted dictionary version {} on page {}", h.version, header);
+    SortedDictionary { dictionary: ArrayDictionary::open(db, h.dictionary), header, directory: h.directory }
+  }
+
+  // Page to pass to open
+  pub fn header_page(&self) -> u32 {
+    self.header
+  }
+
+  // Header page of the underlying ArrayDictionary, for reads through dictionary::get
+  pub fn values_page(&self) -> u32 {
+    self.dictionary.header_page()
+  }
+
+  fn write_header(&mut self) {
+    let h = SortedDictionaryHeader {
+      version: SORTED_DICTIONARY_VERSION,
+      padding: [0; 3],
+      dictionary: self.dictionary.header_page(),
+      directory: self.directory,
+    };
+    let header = self.header;
+    unsafe { *(self.dictionary.db_mut().mut_page_bytes(header).as_mut_ptr() as *mut SortedDictionaryHeader) = h };
+  }
+
+  pub fn get(&self, id: u32) -> Cow<'_, [T]> {
+    self.dictionary.get(id)
+  }
+
+  pub fn try_get(&self, id: u32) -> Option<Cow<'_, [T]>> {
+    self.dictionary.try_get(id)
+  }
+
+  fn leaves(&self) -> usize {
+    paged_vector::len::<u32>(self.directory, self.dictionary.db())
+  }
+
+  fn leaf_page(&self, l: usize) -> u32 {
+    *paged_vector::get::<u32>(self.directory, l, self.dictionary.db()).unwrap()
+  }
+
+  fn leaf(&self, l: usize) -> &[u32] {
+    leaf(self.dictionary.db(), self.leaf_page(l))
+  }
+
+  // First position whose value isn't before, values must be ordered so before is true then false
+  fn lower_bound<F: Fn(&[T]) -> bool>(&self, before: F) -> Position {
+    // First leaf starting with a value that isn't before, the answer is in the leaf ahead of it
+    let (mut lo, mut hi) = (0, self.leaves());
+    while lo < hi {
+      let mid = (lo + hi) / 2;
+      let ids = self.leaf(mid);
+      if !ids.is_empty() && before(&self.get(ids[0])) { lo = mid + 1 } else { hi = mid }
+    }
+    let l = lo.saturating_sub(1);
+    let ids = self.leaf(l);
+    let offset = ids.partition_point(|&id| before(&self.get(id)));
+    Position { leaf: l, offset }
+  }
+
+  fn bound(&self, b: Bound<&[T]>, upper: bool) -> Position {
+    match (b, upper) {
+      (Bound::Unbounded, false) => Position { leaf: 0, offset: 0 },
+      (Bound::Unbounded, true) => Position { leaf: self.leaves(), offset: 0 },
+      (Bound::Included(v), false) | (Bound::Excluded(v), true) => self.lower_bound(|x| x < v),
+      (Bound::Excluded(v), false) | (Bound::Included(v), true) => self.lower_bound(|x| x <= v),
+    }
+  }
+
+  // IDs in value order from start up to end
+  fn ids_in(&self, start: Position, end: Position, mut f: impl FnMut(u32)) {
+    let mut p = start;
+    while p.leaf < end.leaf || (p.leaf == end.leaf && p.offset < end.offset) {
+      let ids = self.leaf(p.leaf);
+      let stop = if p.leaf == end.leaf { end.offset } else { ids.len() };
+      ids[std::cmp::min(p.offset, stop)..stop].iter().for_each(|&id| f(id));
+      p = Position { leaf: p.leaf + 1, offset: 0 };
+    }
+  }
+
+  // All IDs in value order
+  pub fn sorted_ids(&self) -> Vec<u32> {
+    self.range_ids(Bound::Unbounded, Bound::Unbounded)
+  }
+
+  // IDs of values within the bounds, in value order
+  pub fn range_ids(&self, lo: Bound<&[T]>, hi: Bound<&[T]>) -> Vec<u32> {
+    let mut out = Vec::new();
+    self.ids_in(self.bound(lo, false), self.bound(hi, true), |id| out.push(id));
+    out
+  }
+
+  // Selects the IDs of values within the bounds, a row matches if its ID is set
+  pub fn range(&self, lo: Bound<&[T]>, hi: Bound<&[T]>) -> Bitmap {
+    let mut b = Bitmap::new(self.len());
+    self.ids_in(self.bound(lo, false), self.bound(hi, true), |id| b.set(id as usize));
+    b
+  }
+
+  // Selects the IDs of values starting with prefix, they're contiguous in value order
+  pub fn prefix(&self, prefix: &[T]) -> Bitmap {
+    let start = self.bound(Bound::Included(prefix), false);
+    let end = self.lower_bound(|x| x < prefix || x.starts_with(prefix));
+    let mut b = Bitmap::new(self.len());
+    self.ids_in(start, end, |id| b.set(id as usize));
+    b
+  }
+
+  fn insert_sorted(&mut self, id: u32) {
+    let value = self.get(id).into_owned();
+    let p = self.lower_bound(|x| *x < value[..]);
+    let page = self.leaf_page(p.leaf);
+    let mut ids = self.leaf(p.leaf).to_vec();
+    ids.insert(p.offset, id);
+    let db = self.dictionary.db_mut();
+    if ids.len() <= LEAF_CAPACITY {
+      write_leaf(db, page, &ids);
+      return;
+    }
+
+    // Full, the upper half moves to a new leaf after this one
+    let new_page = db.alloc(1)[0];
+    let half = ids.len() / 2;
+    write_leaf(db, page, &ids[..half]);
+    write_leaf(db, new_page, &ids[half..]);
+    let mut after : Vec<u32> = paged_vector::iter_range::<u32>(self.directory, p.leaf + 1..usize::MAX, db).collect();
+    after.insert(0, new_page);
+    let last = after.pop().unwrap();
+    for (i, &leaf) in after.iter().enumerate() {
+      paged_vector::set::<u32>(self.directory, p.leaf + 1 + i, leaf, db);
+    }
+    self.directory = paged_vector::append_slice(self.directory, &[last], db);
+    self.write_header();
+  }
+}
+
+impl<'a, 'b, T: Debug + Copy + Ord + Hash> Dictionary<&'b [T]> for SortedDictionary<'a, T> {
+  fn add(&mut self, v: &'b [T]) -> u32 {
+    let before = self.dictionary.len();
+    let id = self.dictionary.add(v);
+    if id as usize == before {
+      self.insert_sorted(id);
+    }
+    id
+  }
+
+  fn lookup(&self, v: &'b [T]) -> Option<u32> {
+    self.dictionary.lookup(v)
+  }
+
+  fn len(&self) -> usize {
+    self.dictionary.len()
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  fn word(i: usize) -> Vec<u8> {
+    // Scattered so values arrive out of order, with shared prefixes
+    let n = (i * 7919) % 10007;
+    format!("{}{}", ["apple", "apricot", "banana", "cherry", "mango"][n % 5], n).into_bytes()
+  }
+
+  fn expected(values: &[Vec<u8>], f: impl Fn(&[u8]) -> bool) -> Vec<usize> {
+    (0..values.len()).filter(|&i| f(&values[i])).collect()
+  }
+
+  #[test]
+  pub fn sorted() {
+    let mut pp = MemoryPageProvider::new();
+    let mut d = SortedDictionary::<u8>::new(&mut pp);
+    assert!(d.sorted_ids().is_empty());
+    assert!(d.range(Bound::Unbounded, Bound::Unbounded).count() == 0);
+
+    let values : Vec<Vec<u8>> = (0..5000).map(word).collect();
+    for (i, v) in values.iter().enumerate() {
+      assert!(d.add(v) == i as u32);
+    }
+    // Duplicates keep their ID and aren't indexed twice
+    assert!(d.add(&values[10]) == 10);
+    assert!(d.sorted_ids().len() == values.len());
+    assert!(d.leaves() > 1);
+
+    let mut order : Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].cmp(&values[b]));
+    assert!(d.sorted_ids().iter().map(|&id| id as usize).eq(order.iter().cloned()));
+
+    let lo : &[u8] = b"apricot5";
+    let hi : &[u8] = b"cherry";
+    assert!(d.range(Bound::Included(lo), Bound::Excluded(hi)).iter().eq(expected(&values, |v| v >= lo && v < hi)));
+    assert!(d.range(Bound::Excluded(lo), Bound::Included(hi)).iter().eq(expected(&values, |v| v > lo && v <= hi)));
+    assert!(d.range(Bound::Unbounded, Bound::Excluded(b"b")).iter().eq(expected(&values, |v| v < &b"b"[..])));
+    assert!(d.range(Bound::Included(b"m"), Bound::Unbounded).iter().eq(expected(&values, |v| v >= &b"m"[..])));
+    assert!(d.range(Bound::Included(b"z"), Bound::Unbounded).count() == 0);
+    assert!(d.range(Bound::Included(hi), Bound::Excluded(lo)).count() == 0);
+
+    for &p in [&b"ap"[..], b"apricot1", b"banana", b"c", b"", b"zebra"].iter() {
+      assert!(d.prefix(p).iter().eq(expected(&values, |v| v.starts_with(p))), "prefix {:?}", p);
+    }
+  }
+
+  #[test]
+  pub fn reopen() {
+    let mut pp = MemoryPageProvider::new();
+    let values : Vec<Vec<u8>> = (0..3000).map(word).collect();
+    let header = {
+      let mut d = SortedDictionary::<u8>::new(&mut pp);
+      values[..2000].iter().for_each(|v| { d.add(v); });
+      d.header_page()
+    };
+    let mut d = SortedDictionary::<u8>::open(&mut pp, header);
+    assert!(d.len() == 2000);
+    values[2000..].iter().for_each(|v| { d.add(v); });
+
+    let d = SortedDictionary::<u8>::open(&mut pp, header);
+    assert!(d.lookup(&values[2500]) == Some(2500));
+    let ids = d.sorted_ids();
+    assert!(ids.len() == 3000);
+    assert!(ids.windows(2).all(|w| d.get(w[0]) < d.get(w[1])));
+  }
+}
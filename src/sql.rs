@@ -0,0 +1,1112 @@
+// A small SQL dialect over the catalog's tables
+//  CREATE TABLE t (c INT, s STRING [SORTED] [DICTIONARY d], ...)
+//  INSERT INTO t [(c, ...)] VALUES (v, ...), ...
+//  SELECT * | c | t.c | COUNT(*) | COUNT(c) | SUM(c) | MIN(c) | MAX(c) | AVG(c) [AS a], ...
+//    FROM t [JOIN u ON t.c = u.c] [WHERE p] [GROUP BY c, ...] [ORDER BY c [ASC | DESC], ...] [LIMIT n]
+//  DELETE FROM t [WHERE p]
+//  UPDATE t SET c = v, ... [WHERE p]
+// p compares columns with literals (=, <>, <, <=, >, >=, BETWEEN, IN, IS NULL), joined by AND,
+// OR and parentheses. Literals are integers, 'strings' with '' for a quote, and NULL
+// A STRING column gets a dictionary named t.c unless it names one, columns naming the same
+// dictionary share IDs, so joining them needs no translation
+// Keywords are case insensitive, names aren't
+// SELECT is planned onto scan, aggregate, sort and join, ORDER BY sorts in memory only after
+// GROUP BY or JOIN. Results come back as typed batches of up to BATCH_ROWS rows
+
+#![allow(dead_code)]
+
+use crate::aggregate::{self, Aggregate};
+use crate::catalog::{Catalog, CatalogError, ColumnDef, ColumnType, NULL_ID, NULL_INT};
+use crate::join::{self, JoinSide};
+use crate::scan::{self, BatchColumn, Predicate, Value, BATCH_ROWS};
+use crate::sort::{self, SortKey};
+use std::cmp::Ordering;
+
+#[derive(Debug)]
+pub enum SqlError {
+  Parse(String),      // What was expected and what was found instead
+  Plan(String),       // Valid SQL this dialect can't run
+  Catalog(CatalogError),
+}
+
+impl From<CatalogError> for SqlError {
+  fn from(e: CatalogError) -> SqlError {
+    SqlError::Catalog(e)
+  }
+}
+
+// None for NULL
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResultColumn {
+  Int(Vec<Option<i64>>),
+  Float(Vec<Option<f64>>),
+  Str(Vec<Option<String>>),
+}
+
+impl ResultColumn {
+  pub fn len(&self) -> usize {
+    match self {
+      ResultColumn::Int(vs) => vs.len(),
+      ResultColumn::Float(vs) => vs.len(),
+      ResultColumn::Str(vs) => vs.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn value(&self, i: usize) -> Value {
+    match self {
+      ResultColumn::Int(vs) => vs[i].map(Value::Int).unwrap_or(Value::Null),
+      ResultColumn::Float(vs) => vs[i].map(Value::Float).unwrap_or(Value::Null),
+      ResultColumn::Str(vs) => vs[i].clone().map(Value::Str).unwrap_or(Value::Null),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultBatch {
+  pub columns: Vec<ResultColumn>,
+}
+
+impl ResultBatch {
+  pub fn rows(&self) -> usize {
+    self.columns.first().map(|c| c.len()).unwrap_or(0)
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Output {
+  Done,           // CREATE TABLE
+  Changed(usize), // Rows inserted, deleted or updated
+  Rows { names: Vec<String>, batches: Vec<ResultBatch> },
+}
+
+// Parses and runs one statement, a trailing ; is optional
+pub fn execute(catalog: &mut Catalog, sql: &str) -> Result<Output, SqlError> {
+  let mut parser = Parser { tokens: tokenize(sql)?, pos: 0 };
+  let statement = parser.statement()?;
+  parser.symbol(";");
+  if parser.peek() != &Token::End {
+    return Err(parser.error("end of statement"));
+  }
+  match statement {
+    Statement::CreateTable { table, columns } => create_table(catalog, &table, &columns),
+    Statement::Insert { table, columns, rows } => insert(catalog, &table, columns, &rows),
+    Statement::Select(select) => query(catalog, &select),
+    Statement::Delete { table, predicate } => delete(catalog, &table, &predicate),
+    Statement::Update { table, sets, predicate } => update(catalog, &table, &sets, &predicate),
+  }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+  Word(String),
+  Int(i64),
+  Str(String),
+  Symbol(&'static str),
+  End,
+}
+
+// Longest first
+const SYMBOLS: [&str; 14] = ["<=", ">=", "<>", "!=", "(", ")", ",", ";", "*", "=", "<", ">", ".", "-"];
+
+const RESERVED: [&str; 26] = [
+  "SELECT", "FROM", "WHERE", "GROUP", "ORDER", "BY", "LIMIT", "AND", "OR", "NOT", "IN", "IS", "NULL", "BETWEEN",
+  "AS", "JOIN", "ON", "ASC", "DESC", "INSERT", "INTO", "VALUES", "CREATE", "TABLE", "DELETE", "UPDATE",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, SqlError> {
+  let chars : Vec<char> = sql.chars().collect();
+  let mut tokens = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let start = i;
+    if c.is_whitespace() {
+      i += 1;
+    } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+      // Comment to the end of the line
+      while i < chars.len() && chars[i] != '\n' {
+        i += 1;
+      }
+    } else if c.is_ascii_alphabetic() || c == '_' {
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+        i += 1;
+      }
+      tokens.push(Token::Word(chars[start..i].iter().collect()));
+    } else if c.is_ascii_digit() {
+      while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+      }
+      let digits : String = chars[start..i].iter().collect();
+      tokens.push(Token::Int(digits.parse().map_err(|_| SqlError::Parse(format!("integer {} is too large", digits)))?));
+    } else if c == '\'' {
+      let mut s = String::new();
+      i += 1;
+      loop {
+        match (chars.get(i), chars.get(i + 1)) {
+          (Some('\''), Some('\'')) => { s.push('\''); i += 2; }
+          (Some('\''), _) => { i += 1; break; }
+          (Some(&c), _) => { s.push(c); i += 1; }
+          (None, _) => return Err(SqlError::Parse("unterminated string".to_string())),
+        }
+      }
+      tokens.push(Token::Str(s));
+    } else {
+      let symbol = SYMBOLS.iter().find(|s| s.chars().zip(chars[i..].iter()).filter(|(a, b)| a == *b).count() == s.len());
+      match symbol {
+        Some(s) => { tokens.push(Token::Symbol(s)); i += s.len(); }
+        None => return Err(SqlError::Parse(format!("unexpected character {}", c))),
+      }
+    }
+  }
+  tokens.push(Token::End);
+  Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct ColumnRef {
+  table: Option<String>,
+  name: String,
+}
+
+impl ColumnRef {
+  // As written, which is also how predicates name it until it's bound to a table
+  fn text(&self) -> String {
+    match &self.table {
+      Some(t) => format!("{}.{}", t, self.name),
+      None => self.name.clone(),
+    }
+  }
+
+  fn parse(text: &str) -> ColumnRef {
+    match text.find('.') {
+      Some(i) => ColumnRef { table: Some(text[..i].to_string()), name: text[i + 1..].to_string() },
+      None => ColumnRef { table: None, name: text.to_string() },
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+  Count,
+  Sum,
+  Min,
+  Max,
+  Avg,
+}
+
+const FUNCTIONS: [(&str, Function); 5] = [
+  ("COUNT", Function::Count), ("SUM", Function::Sum), ("MIN", Function::Min), ("MAX", Function::Max), ("AVG", Function::Avg),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Item {
+  Star,
+  Column(ColumnRef),
+  Aggregate(Function, Option<ColumnRef>), // None for COUNT(*)
+}
+
+#[derive(Clone, Debug)]
+struct ColumnSpec {
+  name: String,
+  column_type: ColumnType,
+  sorted: bool,
+  dictionary: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Select {
+  items: Vec<(Item, Option<String>)>, // With their aliases
+  from: String,
+  join: Option<(String, ColumnRef, ColumnRef)>,
+  predicate: Predicate,
+  group_by: Vec<ColumnRef>,
+  order_by: Vec<(Item, bool)>, // Descending if true
+  limit: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+enum Statement {
+  CreateTable { table: String, columns: Vec<ColumnSpec> },
+  Insert { table: String, columns: Option<Vec<String>>, rows: Vec<Vec<Value>> },
+  Select(Select),
+  Delete { table: String, predicate: Predicate },
+  Update { table: String, sets: Vec<(String, Value)>, predicate: Predicate },
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  pos: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> &Token {
+    &self.tokens[self.pos]
+  }
+
+  fn error(&self, expected: &str) -> SqlError {
+    let found = match self.peek() {
+      Token::Word(w) => w.clone(),
+      Token::Int(v) => v.to_string(),
+      Token::Str(s) => format!("'{}'", s),
+      Token::Symbol(s) => s.to_string(),
+      Token::End => "end of statement".to_string(),
+    };
+    SqlError::Parse(format!("expected {} but found {}", expected, found))
+  }
+
+  fn is_keyword(&self, k: &str) -> bool {
+    matches!(self.peek(), Token::Word(w) if w.eq_ignore_ascii_case(k))
+  }
+
+  fn keyword(&mut self, k: &str) -> bool {
+    let found = self.is_keyword(k);
+    if found {
+      self.pos += 1;
+    }
+    found
+  }
+
+  fn expect_keyword(&mut self, k: &str) -> Result<(), SqlError> {
+    if self.keyword(k) { Ok(()) } else { Err(self.error(k)) }
+  }
+
+  fn symbol(&mut self, s: &str) -> bool {
+    let found = matches!(self.peek(), Token::Symbol(x) if *x == s);
+    if found {
+      self.pos += 1;
+    }
+    found
+  }
+
+  fn expect_symbol(&mut self, s: &str) -> Result<(), SqlError> {
+    if self.symbol(s) { Ok(()) } else { Err(self.error(s)) }
+  }
+
+  fn name(&mut self) -> Result<String, SqlError> {
+    match self.peek().clone() {
+      Token::Word(w) if !RESERVED.iter().any(|r| w.eq_ignore_ascii_case(r)) => {
+        self.pos += 1;
+        Ok(w)
+      }
+      _ => Err(self.error("a name")),
+    }
+  }
+
+  fn column(&mut self) -> Result<ColumnRef, SqlError> {
+    let name = self.name()?;
+    if self.symbol(".") {
+      return Ok(ColumnRef { table: Some(name), name: self.name()? });
+    }
+    Ok(ColumnRef { table: None, name })
+  }
+
+  // Comma separated, at least one
+  fn list<T>(&mut self, f: impl Fn(&mut Parser) -> Result<T, SqlError>) -> Result<Vec<T>, SqlError> {
+    let mut items = vec![f(self)?];
+    while self.symbol(",") {
+      items.push(f(self)?);
+    }
+    Ok(items)
+  }
+
+  fn literal(&mut self) -> Result<Value, SqlError> {
+    let negative = self.symbol("-");
+    let v = match self.peek().clone() {
+      Token::Int(v) => Value::Int(if negative { -v } else { v }),
+      Token::Str(s) if !negative => Value::Str(s),
+      Token::Word(w) if !negative && w.eq_ignore_ascii_case("NULL") => Value::Null,
+      _ => return Err(self.error("a value")),
+    };
+    self.pos += 1;
+    Ok(v)
+  }
+
+  fn statement(&mut self) -> Result<Statement, SqlError> {
+    if self.keyword("CREATE") {
+      self.expect_keyword("TABLE")?;
+      let table = self.name()?;
+      self.expect_symbol("(")?;
+      let columns = self.list(Parser::column_spec)?;
+      self.expect_symbol(")")?;
+      Ok(Statement::CreateTable { table, columns })
+    } else if self.keyword("INSERT") {
+      self.expect_keyword("INTO")?;
+      let table = self.name()?;
+      let columns = if self.symbol("(") {
+        let columns = self.list(Parser::name)?;
+        self.expect_symbol(")")?;
+        Some(columns)
+      } else {
+        None
+      };
+      self.expect_keyword("VALUES")?;
+      let rows = self.list(|p| {
+        p.expect_symbol("(")?;
+        let row = p.list(Parser::literal)?;
+        p.expect_symbol(")")?;
+        Ok(row)
+      })?;
+      Ok(Statement::Insert { table, columns, rows })
+    } else if self.keyword("SELECT") {
+      Ok(Statement::Select(self.select()?))
+    } else if self.keyword("DELETE") {
+      self.expect_keyword("FROM")?;
+      let table = self.name()?;
+      Ok(Statement::Delete { table, predicate: self.predicate()? })
+    } else if self.keyword("UPDATE") {
+      let table = self.name()?;
+      self.expect_keyword("SET")?;
+      let sets = self.list(|p| {
+        let column = p.name()?;
+        p.expect_symbol("=")?;
+        Ok((column, p.literal()?))
+      })?;
+      Ok(Statement::Update { table, sets, predicate: self.predicate()? })
+    } else {
+      Err(self.error("CREATE, INSERT, SELECT, DELETE or UPDATE"))
+    }
+  }
+
+  fn column_spec(&mut self) -> Result<ColumnSpec, SqlError> {
+    let name = self.name()?;
+    let column_type = match self.peek() {
+      Token::Word(w) if ["INT", "INTEGER", "BIGINT"].iter().any(|t| w.eq_ignore_ascii_case(t)) => ColumnType::Int,
+      Token::Word(w) if ["STRING", "TEXT", "VARCHAR"].iter().any(|t| w.eq_ignore_ascii_case(t)) => ColumnType::String,
+      _ => return Err(self.error("INT or STRING")),
+    };
+    self.pos += 1;
+    let mut spec = ColumnSpec { name, column_type, sorted: false, dictionary: None };
+    if column_type == ColumnType::String {
+      spec.sorted = self.keyword("SORTED");
+      if self.keyword("DICTIONARY") {
+        spec.dictionary = Some(self.name()?);
+      }
+    }
+    Ok(spec)
+  }
+
+  fn item(&mut self) -> Result<Item, SqlError> {
+    if self.symbol("*") {
+      return Ok(Item::Star);
+    }
+    let function = FUNCTIONS.iter().find(|(f, _)| self.is_keyword(f) && self.tokens[self.pos + 1] == Token::Symbol("(")).map(|&(_, f)| f);
+    match function {
+      Some(function) => {
+        self.pos += 2;
+        let column = if function == Function::Count && self.symbol("*") { None } else { Some(self.column()?) };
+        self.expect_symbol(")")?;
+        Ok(Item::Aggregate(function, column))
+      }
+      None => Ok(Item::Column(self.column()?)),
+    }
+  }
+
+  fn select(&mut self) -> Result<Select, SqlError> {
+    let items = self.list(|p| {
+      let item = p.item()?;
+      let alias = if p.keyword("AS") { Some(p.name()?) } else { None };
+      Ok((item, alias))
+    })?;
+    self.expect_keyword("FROM")?;
+    let from = self.name()?;
+    let join = if self.keyword("JOIN") {
+      let table = self.name()?;
+      self.expect_keyword("ON")?;
+      let left = self.column()?;
+      self.expect_symbol("=")?;
+      Some((table, left, self.column()?))
+    } else {
+      None
+    };
+    let predicate = self.predicate()?;
+    let group_by = if self.keyword("GROUP") {
+      self.expect_keyword("BY")?;
+      self.list(Parser::column)?
+    } else {
+      Vec::new()
+    };
+    let order_by = if self.keyword("ORDER") {
+      self.expect_keyword("BY")?;
+      self.list(|p| {
+        let item = p.item()?;
+        let descending = p.keyword("DESC");
+        if !descending {
+          p.keyword("ASC");
+        }
+        Ok((item, descending))
+      })?
+    } else {
+      Vec::new()
+    };
+    let limit = if self.keyword("LIMIT") {
+      match self.peek().clone() {
+        Token::Int(n) => { self.pos += 1; Some(n as usize) }
+        _ => return Err(self.error("a row count")),
+      }
+    } else {
+      None
+    };
+    Ok(Select { items, from, join, predicate, group_by, order_by, limit })
+  }
+
+  // An optional WHERE clause, every row without one
+  fn predicate(&mut self) -> Result<Predicate, SqlError> {
+    if self.keyword("WHERE") { self.or() } else { Ok(Predicate::all()) }
+  }
+
+  fn or(&mut self) -> Result<Predicate, SqlError> {
+    let mut ps = vec![self.and()?];
+    while self.keyword("OR") {
+      ps.push(self.and()?);
+    }
+    Ok(if ps.len() == 1 { ps.pop().unwrap() } else { Predicate::Or(ps) })
+  }
+
+  fn and(&mut self) -> Result<Predicate, SqlError> {
+    let mut ps = vec![self.comparison()?];
+    while self.keyword("AND") {
+      ps.push(self.comparison()?);
+    }
+    Ok(if ps.len() == 1 { ps.pop().unwrap() } else { Predicate::And(ps) })
+  }
+
+  fn comparison(&mut self) -> Result<Predicate, SqlError> {
+    if self.symbol("(") {
+      let p = self.or()?;
+      self.expect_symbol(")")?;
+      return Ok(p);
+    }
+    let c = self.column()?.text();
+    if self.keyword("IS") {
+      if self.keyword("NOT") {
+        return Err(SqlError::Plan("IS NOT NULL isn't supported".to_string()));
+      }
+      self.expect_keyword("NULL")?;
+      return Ok(Predicate::IsNull(c));
+    }
+    if self.keyword("BETWEEN") {
+      let lo = self.literal()?;
+      self.expect_keyword("AND")?;
+      return Ok(Predicate::Between(c, lo, self.literal()?));
+    }
+    if self.keyword("IN") {
+      self.expect_symbol("(")?;
+      let vs = self.list(Parser::literal)?;
+      self.expect_symbol(")")?;
+      return Ok(Predicate::In(c, vs));
+    }
+    let op = match self.peek() {
+      Token::Symbol(s) if ["=", "<>", "!=", "<", "<=", ">", ">="].contains(s) => *s,
+      _ => return Err(self.error("a comparison")),
+    };
+    self.pos += 1;
+    let v = self.literal()?;
+    Ok(match op {
+      "=" => Predicate::Eq(c, v),
+      "<" => Predicate::Lt(c, v),
+      "<=" => Predicate::Le(c, v),
+      ">" => Predicate::Gt(c, v),
+      ">=" => Predicate::Ge(c, v),
+      _ => Predicate::Or(vec![Predicate::Lt(c.clone(), v.clone()), Predicate::Gt(c, v)]),
+    })
+  }
+}
+
+// The same predicate with f applied to each column name
+fn map_columns(p: &Predicate, f: &mut dyn FnMut(&str) -> Result<String, SqlError>) -> Result<Predicate, SqlError> {
+  Ok(match p {
+    Predicate::Eq(c, v) => Predicate::Eq(f(c)?, v.clone()),
+    Predicate::Lt(c, v) => Predicate::Lt(f(c)?, v.clone()),
+    Predicate::Le(c, v) => Predicate::Le(f(c)?, v.clone()),
+    Predicate::Gt(c, v) => Predicate::Gt(f(c)?, v.clone()),
+    Predicate::Ge(c, v) => Predicate::Ge(f(c)?, v.clone()),
+    Predicate::Between(c, a, b) => Predicate::Between(f(c)?, a.clone(), b.clone()),
+    Predicate::In(c, vs) => Predicate::In(f(c)?, vs.clone()),
+    Predicate::IsNull(c) => Predicate::IsNull(f(c)?),
+    Predicate::And(ps) => Predicate::And(ps.iter().map(|p| map_columns(p, f)).collect::<Result<_, _>>()?),
+    Predicate::Or(ps) => Predicate::Or(ps.iter().map(|p| map_columns(p, f)).collect::<Result<_, _>>()?),
+  })
+}
+
+fn table_columns(catalog: &Catalog, table: &str) -> Result<Vec<ColumnDef>, SqlError> {
+  let columns = catalog.columns(table);
+  if columns.is_empty() {
+    return Err(CatalogError::NotFound(table.to_string()).into());
+  }
+  Ok(columns)
+}
+
+fn find_column(catalog: &Catalog, table: &str, name: &str) -> Result<ColumnDef, SqlError> {
+  let id = catalog.find_column(table, name).ok_or_else(|| CatalogError::NotFound(format!("{}.{}", table, name)))?;
+  Ok(catalog.column(id)?)
+}
+
+// The column's name within table, which a qualified column must name
+fn bind(catalog: &Catalog, table: &str, c: &ColumnRef) -> Result<String, SqlError> {
+  if c.table.as_ref().is_some_and(|t| t != table) {
+    return Err(CatalogError::NotFound(c.text()).into());
+  }
+  find_column(catalog, table, &c.name)?;
+  Ok(c.name.clone())
+}
+
+// NULL first, as sort orders columns
+fn compare(a: &Value, b: &Value) -> Ordering {
+  match (a, b) {
+    (Value::Null, Value::Null) => Ordering::Equal,
+    (Value::Null, _) => Ordering::Less,
+    (_, Value::Null) => Ordering::Greater,
+    (Value::Int(a), Value::Int(b)) => a.cmp(b),
+    (Value::Float(a), Value::Float(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+    (Value::Str(a), Value::Str(b)) => a.cmp(b),
+    _ => Ordering::Equal,
+  }
+}
+
+fn create_table(catalog: &mut Catalog, table: &str, columns: &[ColumnSpec]) -> Result<Output, SqlError> {
+  if !catalog.columns(table).is_empty() {
+    return Err(CatalogError::Exists(table.to_string()).into());
+  }
+  if let Some(c) = columns.iter().find(|c| columns.iter().filter(|d| d.name == c.name).count() > 1) {
+    return Err(CatalogError::Exists(format!("{}.{}", table, c.name)).into());
+  }
+  for c in columns.iter() {
+    let dictionary = match c.column_type {
+      ColumnType::Int => None,
+      _ => {
+        let name = c.dictionary.clone().unwrap_or_else(|| format!("{}.{}", table, c.name));
+        match catalog.find_dictionary(&name) {
+          Some(d) => Some(d),
+          None => Some(catalog.create_dictionary(&name, c.sorted)?),
+        }
+      }
+    };
+    catalog.add_column(table, &c.name, c.column_type, dictionary)?;
+  }
+  Ok(Output::Done)
+}
+
+// Checks a value can go in the column, a NULL can go in any
+fn check_type(c: &ColumnDef, v: &Value) -> Result<(), SqlError> {
+  match (c.column_type, v) {
+    (_, Value::Null) | (ColumnType::Int, Value::Int(_)) => Ok(()),
+    (ColumnType::Ids, Value::Str(_)) | (ColumnType::String, Value::Str(_)) => Ok(()),
+    _ => Err(CatalogError::WrongType(format!("{}.{}", c.table, c.name)).into()),
+  }
+}
+
+// The value's ID in an Ids or String column's dictionary, added if it's new
+fn value_id(catalog: &mut Catalog, c: &ColumnDef, v: &Value) -> Result<u32, SqlError> {
+  match v {
+    Value::Str(s) => Ok(catalog.dictionary(c.dictionary.unwrap())?.add(s.as_bytes())),
+    _ => Ok(NULL_ID),
+  }
+}
+
+fn insert(catalog: &mut Catalog, table: &str, names: Option<Vec<String>>, rows: &[Vec<Value>]) -> Result<Output, SqlError> {
+  let columns = table_columns(catalog, table)?;
+  let targets = match names {
+    Some(names) => names.iter().map(|n| find_column(catalog, table, n)).collect::<Result<Vec<_>, _>>()?,
+    None => columns.clone(),
+  };
+  if let Some(row) = rows.iter().find(|r| r.len() != targets.len()) {
+    return Err(SqlError::Plan(format!("{} values for {} columns", row.len(), targets.len())));
+  }
+  // Every value is checked before any column is written, columns left out are NULL
+  let mut values : Vec<Vec<Value>> = vec![vec![Value::Null; rows.len()]; columns.len()];
+  for (t, target) in targets.iter().enumerate() {
+    let c = columns.iter().position(|c| c.id == target.id).unwrap();
+    for (r, row) in rows.iter().enumerate() {
+      check_type(target, &row[t])?;
+      values[c][r] = row[t].clone();
+    }
+  }
+  for (c, vs) in columns.iter().zip(values.iter()) {
+    match c.column_type {
+      ColumnType::Int => {
+        let ints : Vec<i64> = vs.iter().map(|v| if let Value::Int(v) = v { *v } else { NULL_INT }).collect();
+        catalog.append_ints(c.id, &ints)?;
+      }
+      ColumnType::Ids => {
+        let ids = vs.iter().map(|v| value_id(catalog, c, v)).collect::<Result<Vec<u32>, _>>()?;
+        catalog.append_ids(c.id, &ids)?;
+      }
+      ColumnType::String => {
+        let mut column = catalog.strings(c.id)?;
+        for v in vs.iter() {
+          match v {
+            Value::Str(s) => column.push(s),
+            _ => column.push_null(),
+          }
+        }
+      }
+    }
+  }
+  Ok(Output::Changed(rows.len()))
+}
+
+// Positions of the rows matching predicate, in row order
+fn matching_rows(catalog: &mut Catalog, table: &str, predicate: &Predicate, limit: Option<usize>) -> Result<Vec<usize>, SqlError> {
+  let mut rows = Vec::new();
+  let limit = limit.unwrap_or(usize::MAX);
+  for batch in scan::scan(catalog, table, &[], predicate)? {
+    rows.extend(batch.rows.into_iter().take(limit - rows.len()));
+    if rows.len() == limit {
+      break;
+    }
+  }
+  Ok(rows)
+}
+
+fn bound_predicate(catalog: &Catalog, table: &str, predicate: &Predicate) -> Result<Predicate, SqlError> {
+  map_columns(predicate, &mut |c| bind(catalog, table, &ColumnRef::parse(c)))
+}
+
+fn delete(catalog: &mut Catalog, table: &str, predicate: &Predicate) -> Result<Output, SqlError> {
+  let columns = table_columns(catalog, table)?;
+  let predicate = bound_predicate(catalog, table, predicate)?;
+  let deleted = matching_rows(catalog, table, &predicate, None)?;
+  if !deleted.is_empty() {
+    let rows = columns.iter().map(|c| catalog.rows(c.id)).collect::<Result<Vec<usize>, _>>()?.into_iter().min().unwrap();
+    let mut kept = Vec::with_capacity(rows - deleted.len());
+    let mut deleted_rows = deleted.iter().peekable();
+    for row in 0..rows {
+      if deleted_rows.peek() == Some(&&row) {
+        deleted_rows.next();
+      } else {
+        kept.push(row);
+      }
+    }
+    catalog.retain_rows(table, &kept)?;
+  }
+  Ok(Output::Changed(deleted.len()))
+}
+
+fn update(catalog: &mut Catalog, table: &str, sets: &[(String, Value)], predicate: &Predicate) -> Result<Output, SqlError> {
+  table_columns(catalog, table)?;
+  let predicate = bound_predicate(catalog, table, predicate)?;
+  let columns = sets.iter().map(|(name, v)| {
+    let c = find_column(catalog, table, name)?;
+    check_type(&c, v)?;
+    Ok(c)
+  }).collect::<Result<Vec<_>, SqlError>>()?;
+  let rows = matching_rows(catalog, table, &predicate, None)?;
+  if !rows.is_empty() {
+    for (c, (_, v)) in columns.iter().zip(sets.iter()) {
+      match c.column_type {
+        ColumnType::Int => catalog.update_ints(c.id, &rows, if let Value::Int(v) = v { *v } else { NULL_INT })?,
+        _ => {
+          let id = value_id(catalog, c, v)?;
+          catalog.update_ids(c.id, &rows, id)?;
+        }
+      }
+    }
+  }
+  Ok(Output::Changed(rows.len()))
+}
+
+// A column's values at rows, with IDs decoded through its dictionary
+fn materialise(catalog: &mut Catalog, c: &ColumnDef, rows: &[usize]) -> Result<ResultColumn, SqlError> {
+  match scan::fetch(catalog, &c.table, &c.name, rows)? {
+    BatchColumn::Int(vs) => Ok(ResultColumn::Int(vs.into_iter().map(|v| if v == NULL_INT { None } else { Some(v) }).collect())),
+    BatchColumn::Ids(ids) => {
+      let d = catalog.dictionary(c.dictionary.unwrap())?;
+      Ok(ResultColumn::Str(ids.into_iter().map(|id| {
+        if id == NULL_ID { None } else { Some(String::from_utf8_lossy(&d.get(id)).into_owned()) }
+      }).collect()))
+    }
+  }
+}
+
+// Batches of the columns at the rows of each, which pair up
+fn column_batches(catalog: &mut Catalog, columns: &[(ColumnDef, &[usize])]) -> Result<Vec<ResultBatch>, SqlError> {
+  let rows = columns.first().map(|(_, rows)| rows.len()).unwrap_or(0);
+  let mut batches = Vec::new();
+  for start in (0..rows).step_by(BATCH_ROWS) {
+    let end = std::cmp::min(start + BATCH_ROWS, rows);
+    let columns = columns.iter().map(|(c, rows)| materialise(catalog, c, &rows[start..end])).collect::<Result<Vec<_>, _>>()?;
+    batches.push(ResultBatch { columns });
+  }
+  Ok(batches)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ResultType {
+  Int,
+  Float,
+  Str,
+}
+
+// Batches of rows of values already computed
+fn value_batches(types: &[ResultType], rows: &[Vec<Value>]) -> Result<Vec<ResultBatch>, SqlError> {
+  let mut batches = Vec::new();
+  for chunk in rows.chunks(BATCH_ROWS) {
+    let columns = types.iter().enumerate().map(|(i, t)| {
+      let values = chunk.iter().map(|r| &r[i]);
+      match t {
+        ResultType::Int => values.map(|v| match v {
+          Value::Int(v) => Ok(Some(*v)),
+          Value::Null => Ok(None),
+          _ => Err(SqlError::Plan("integer overflow".to_string())),
+        }).collect::<Result<_, _>>().map(ResultColumn::Int),
+        ResultType::Float => Ok(ResultColumn::Float(values.map(|v| if let Value::Float(v) = v { Some(*v) } else { None }).collect())),
+        ResultType::Str => Ok(ResultColumn::Str(values.map(|v| if let Value::Str(v) = v { Some(v.clone()) } else { None }).collect())),
+      }
+    }).collect::<Result<Vec<_>, _>>()?;
+    batches.push(ResultBatch { columns });
+  }
+  Ok(batches)
+}
+
+fn function_name(f: Function) -> &'static str {
+  FUNCTIONS.iter().find(|(_, g)| *g == f).unwrap().0
+}
+
+fn item_name(item: &Item, alias: &Option<String>) -> String {
+  match (item, alias) {
+    (_, Some(a)) => a.clone(),
+    (Item::Column(c), None) => c.text(),
+    (Item::Aggregate(f, None), None) => format!("{}(*)", function_name(*f).to_lowercase()),
+    (Item::Aggregate(f, Some(c)), None) => format!("{}({})", function_name(*f).to_lowercase(), c.text()),
+    (Item::Star, None) => "*".to_string(),
+  }
+}
+
+// Which selected item an ORDER BY item is, by alias or by being the same
+fn order_position(select: &Select, item: &Item) -> Option<usize> {
+  select.items.iter().position(|(i, alias)| match (item, alias) {
+    (Item::Column(ColumnRef { table: None, name }), Some(a)) if name == a => true,
+    _ => i == item,
+  })
+}
+
+fn query(catalog: &mut Catalog, select: &Select) -> Result<Output, SqlError> {
+  let grouped = !select.group_by.is_empty() || select.items.iter().any(|(i, _)| matches!(i, Item::Aggregate(_, _)));
+  match (&select.join, grouped) {
+    (Some(_), true) => Err(SqlError::Plan("GROUP BY and aggregates over a JOIN aren't supported".to_string())),
+    (Some(_), false) => join_query(catalog, select),
+    (None, true) => group_query(catalog, select),
+    (None, false) => table_query(catalog, select),
+  }
+}
+
+fn table_query(catalog: &mut Catalog, select: &Select) -> Result<Output, SqlError> {
+  let table = &select.from;
+  let mut columns = Vec::new();
+  let mut names = Vec::new();
+  for (item, alias) in select.items.iter() {
+    match item {
+      Item::Star => for c in table_columns(catalog, table)? {
+        names.push(c.name.clone());
+        columns.push(c);
+      },
+      Item::Column(c) => {
+        names.push(item_name(item, alias));
+        columns.push(find_column(catalog, table, &bind(catalog, table, c)?)?);
+      }
+      Item::Aggregate(_, _) => unreachable!(),
+    }
+  }
+  let predicate = bound_predicate(catalog, table, &select.predicate)?;
+
+  let rows = if select.order_by.is_empty() {
+    matching_rows(catalog, table, &predicate, select.limit)?
+  } else {
+    let keys = select.order_by.iter().map(|(item, descending)| {
+      let column = match (order_position(select, item).map(|i| &select.items[i].0), item) {
+        (Some(Item::Column(c)), _) | (None, Item::Column(c)) => bind(catalog, table, c)?,
+        _ => return Err(SqlError::Plan(format!("can't order by {}", item_name(item, &None)))),
+      };
+      Ok(SortKey { column, descending: *descending })
+    }).collect::<Result<Vec<_>, SqlError>>()?;
+    match select.limit {
+      Some(n) => sort::top_n(catalog, table, &keys, &predicate, n)?,
+      None => sort::sort(catalog, table, &keys, &predicate)?,
+    }
+  };
+  let columns : Vec<(ColumnDef, &[usize])> = columns.into_iter().map(|c| (c, &rows[..])).collect();
+  Ok(Output::Rows { names, batches: column_batches(catalog, &columns)? })
+}
+
+// Where a selected item's values come from in each group, by index
+enum GroupItem {
+  Key(usize),
+  Aggregate(usize),
+}
+
+fn group_query(catalog: &mut Catalog, select: &Select) -> Result<Output, SqlError> {
+  let table = &select.from;
+  let group_by = select.group_by.iter().map(|c| bind(catalog, table, c)).collect::<Result<Vec<_>, _>>()?;
+  let mut aggregates = Vec::new();
+  let mut types = Vec::new();
+  let mut sources = Vec::new();
+  for (item, _) in select.items.iter() {
+    match item {
+      Item::Column(c) => {
+        let name = bind(catalog, table, c)?;
+        let key = group_by.iter().position(|g| *g == name).ok_or_else(|| SqlError::Plan(format!("{} isn't in GROUP BY", c.text())))?;
+        let c = find_column(catalog, table, &name)?;
+        types.push(if c.column_type == ColumnType::Int { ResultType::Int } else { ResultType::Str });
+        sources.push(GroupItem::Key(key));
+      }
+      Item::Aggregate(f, c) => {
+        let c = c.as_ref().map(|c| bind(catalog, table, c)).transpose()?;
+        aggregates.push(match (f, c) {
+          (Function::Count, None) => Aggregate::Count,
+          (Function::Count, Some(c)) => Aggregate::CountOf(c),
+          (Function::Sum, Some(c)) => Aggregate::Sum(c),
+          (Function::Min, Some(c)) => Aggregate::Min(c),
+          (Function::Max, Some(c)) => Aggregate::Max(c),
+          (Function::Avg, Some(c)) => Aggregate::Avg(c),
+          (_, None) => unreachable!(),
+        });
+        types.push(if *f == Function::Avg { ResultType::Float } else { ResultType::Int });
+        sources.push(GroupItem::Aggregate(aggregates.len() - 1));
+      }
+      Item::Star => return Err(SqlError::Plan("* can't be selected with GROUP BY or aggregates".to_string())),
+    }
+  }
+  let order = select.order_by.iter().map(|(item, descending)| {
+    let i = order_position(select, item).ok_or_else(|| SqlError::Plan(format!("ORDER BY {} isn't selected", item_name(item, &None))))?;
+    Ok((i, *descending))
+  }).collect::<Result<Vec<_>, SqlError>>()?;
+
+  let predicate = bound_predicate(catalog, table, &select.predicate)?;
+  let group_names : Vec<&str> = group_by.iter().map(|g| &g[..]).collect();
+  let groups = aggregate::aggregate(catalog, table, &group_names, &aggregates, &predicate)?;
+  let mut rows : Vec<Vec<Value>> = groups.into_iter().map(|g| {
+    sources.iter().map(|s| match s {
+      GroupItem::Key(k) => g.key[*k].clone(),
+      GroupItem::Aggregate(a) => g.values[*a].clone(),
+    }).collect()
+  }).collect();
+  rows.sort_by(|a, b| order.iter().fold(Ordering::Equal, |o, &(i, descending)| {
+    o.then_with(|| if descending { compare(&b[i], &a[i]) } else { compare(&a[i], &b[i]) })
+  }));
+  rows.truncate(select.limit.unwrap_or(usize::MAX));
+
+  let names = select.items.iter().map(|(item, alias)| item_name(item, alias)).collect();
+  Ok(Output::Rows { names, batches: value_batches(&types, &rows)? })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+  Left,
+  Right,
+}
+
+fn join_query(catalog: &mut Catalog, select: &Select) -> Result<Output, SqlError> {
+  let (right_table, on_left, on_right) = select.join.clone().unwrap();
+  let left_table = select.from.clone();
+  if left_table == right_table {
+    return Err(SqlError::Plan("a table can't be joined with itself".to_string()));
+  }
+  let tables = [left_table.clone(), right_table.clone()];
+  // Side and column of a column named by either table
+  let resolve = |catalog: &Catalog, c: &ColumnRef| -> Result<(Side, ColumnDef), SqlError> {
+    let found : Vec<Side> = [Side::Left, Side::Right].iter().cloned().filter(|&s| {
+      let table = &tables[s as usize];
+      c.table.as_ref().is_none_or(|t| t == table) && catalog.find_column(table, &c.name).is_some()
+    }).collect();
+    match found[..] {
+      [side] => Ok((side, find_column(catalog, &tables[side as usize], &c.name)?)),
+      [] => Err(CatalogError::NotFound(c.text()).into()),
+      _ => Err(SqlError::Plan(format!("{} is in both tables", c.text()))),
+    }
+  };
+
+  let (mut left_on, mut right_on) = (resolve(catalog, &on_left)?, resolve(catalog, &on_right)?);
+  if left_on.0 == Side::Right {
+    std::mem::swap(&mut left_on, &mut right_on);
+  }
+  if left_on.0 == right_on.0 {
+    return Err(SqlError::Plan("ON must compare a column of each table".to_string()));
+  }
+
+  // Conditions ANDed together go to the side whose columns they test
+  let conditions = match &select.predicate {
+    Predicate::And(ps) => ps.clone(),
+    p => vec![p.clone()],
+  };
+  let mut predicates = [Vec::new(), Vec::new()];
+  for p in conditions.iter() {
+    let mut sides = Vec::new();
+    let bound = map_columns(p, &mut |c| {
+      let (side, c) = resolve(catalog, &ColumnRef::parse(c))?;
+      sides.push(side);
+      Ok(c.name)
+    })?;
+    if sides.iter().any(|&s| s != sides[0]) {
+      return Err(SqlError::Plan("conditions on both tables must be joined by AND".to_string()));
+    }
+    predicates[sides[0] as usize].push(bound);
+  }
+  let [left_predicate, right_predicate] = predicates;
+  let (left_predicate, right_predicate) = (Predicate::And(left_predicate), Predicate::And(right_predicate));
+
+  let mut columns = Vec::new();
+  let mut names = Vec::new();
+  for (item, alias) in select.items.iter() {
+    match item {
+      Item::Star => for table in tables.iter() {
+        for c in table_columns(catalog, table)? {
+          names.push(format!("{}.{}", c.table, c.name));
+          columns.push((if *table == left_table { Side::Left } else { Side::Right }, c));
+        }
+      },
+      Item::Column(c) => {
+        names.push(item_name(item, alias));
+        columns.push(resolve(catalog, c)?);
+      }
+      Item::Aggregate(_, _) => unreachable!(),
+    }
+  }
+  let keys = select.order_by.iter().map(|(item, descending)| {
+    match (order_position(select, item).map(|i| &select.items[i].0), item) {
+      (Some(Item::Column(c)), _) | (None, Item::Column(c)) => Ok((resolve(catalog, c)?, *descending)),
+      _ => Err(SqlError::Plan(format!("can't order by {}", item_name(item, &None)))),
+    }
+  }).collect::<Result<Vec<_>, SqlError>>()?;
+
+  let joined = join::join(
+    catalog,
+    &JoinSide { table: &left_table, column: &left_on.1.name, predicate: &left_predicate },
+    &JoinSide { table: &right_table, column: &right_on.1.name, predicate: &right_predicate },
+  )?;
+  let sides = [&joined.left, &joined.right];
+
+  // Pairs are sorted in memory on their keys' values
+  let mut order : Vec<usize> = (0..joined.len()).collect();
+  if !keys.is_empty() {
+    let mut values = Vec::new();
+    for ((side, c), _) in keys.iter() {
+      let column = materialise(catalog, c, sides[*side as usize])?;
+      values.push((0..column.len()).map(|i| column.value(i)).collect::<Vec<Value>>());
+    }
+    order.sort_by(|&a, &b| keys.iter().zip(values.iter()).fold(Ordering::Equal, |o, ((_, descending), vs)| {
+      o.then_with(|| if *descending { compare(&vs[b], &vs[a]) } else { compare(&vs[a], &vs[b]) })
+    }));
+  }
+  order.truncate(select.limit.unwrap_or(usize::MAX));
+
+  let rows : Vec<Vec<usize>> = sides.iter().map(|s| order.iter().map(|&i| s[i]).collect()).collect();
+  let columns : Vec<(ColumnDef, &[usize])> = columns.into_iter().map(|(side, c)| (c, &rows[side as usize][..])).collect();
+  Ok(Output::Rows { names, batches: column_batches(catalog, &columns)? })
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  fn rows(output: Output) -> Vec<Vec<Value>> {
+    match output {
+      Output::Rows { batches, .. } => batches.iter().flat_map(|b| (0..b.rows()).map(move |i| b.columns.iter().map(|c| c.value(i)).collect::<Vec<_>>())).collect(),
+      o => panic!("Expected rows, got {:?}", o),
+    }
+  }
+
+  fn i(v: i64) -> Value {
+    Value::Int(v)
+  }
+
+  fn s(v: &str) -> Value {
+    Value::Str(v.to_string())
+  }
+
+  fn setup(c: &mut Catalog) {
+    let statements = [
+      "CREATE TABLE cities (name STRING SORTED DICTIONARY city, country STRING, population INT)",
+      "INSERT INTO cities VALUES ('leeds', 'uk', 800), ('york', 'uk', 200), ('paris', 'france', 2100), ('lyon', 'france', 500), ('atlantis', NULL, NULL)",
+      "create table people (name string, home string dictionary city, age int);",
+      "INSERT INTO people (name, home, age) VALUES ('ann', 'leeds', 34), ('bob', 'paris', 51), ('cat', 'york', -2), ('dan', 'leeds', 27)",
+      "INSERT INTO people (name, age) VALUES ('eve', 40), ('it''s', NULL)",
+    ];
+    for sql in statements.iter() {
+      assert!(execute(c, sql).is_ok(), "{}", sql);
+    }
+  }
+
+  #[test]
+  pub fn select() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    setup(&mut c);
+
+    let output = execute(&mut c, "SELECT name, age AS years FROM people WHERE age >= 30 OR home IS NULL").unwrap();
+    assert!(matches!(&output, Output::Rows { names, batches } if *names == vec!["name", "years"] && batches.len() == 1
+      && batches[0].columns[1] == ResultColumn::Int(vec![Some(34), Some(51), Some(40), None])));
+    assert!(rows(output)[3] == vec![s("it's"), Value::Null]);
+
+    assert!(rows(execute(&mut c, "SELECT * FROM cities WHERE country = 'france' AND population BETWEEN 400 AND 1000").unwrap()) == vec![vec![s("lyon"), s("france"), i(500)]]);
+    assert!(rows(execute(&mut c, "SELECT name FROM people WHERE home IN ('york', 'paris') AND age <> 51").unwrap()) == vec![vec![s("cat")]]);
+    assert!(rows(execute(&mut c, "SELECT name FROM people LIMIT 2").unwrap()) == vec![vec![s("ann")], vec![s("bob")]]);
+
+    // ORDER BY with and without LIMIT, NULL first
+    let all = rows(execute(&mut c, "SELECT name, age FROM people ORDER BY age DESC, name").unwrap());
+    assert!(all.iter().map(|r| r[0].clone()).collect::<Vec<_>>() == vec![s("bob"), s("eve"), s("ann"), s("dan"), s("cat"), s("it's")]);
+    assert!(rows(execute(&mut c, "SELECT name FROM people ORDER BY name DESC LIMIT 2").unwrap()) == vec![vec![s("it's")], vec![s("eve")]]);
+    assert!(rows(execute(&mut c, "SELECT name, age AS a FROM people WHERE age > 0 ORDER BY a LIMIT 1").unwrap()) == vec![vec![s("dan"), i(27)]]);
+
+    // Aggregates and GROUP BY
+    let totals = rows(execute(&mut c, "SELECT COUNT(*), COUNT(age), SUM(age), MIN(age), MAX(age), AVG(age) FROM people").unwrap());
+    assert!(totals == vec![vec![i(6), i(5), i(150), i(-2), i(51), Value::Float(30.0)]]);
+    let by_country = rows(execute(&mut c, "SELECT country, SUM(population) AS total, count(*) FROM cities GROUP BY country ORDER BY total DESC").unwrap());
+    assert!(by_country == vec![vec![s("france"), i(2600), i(2)], vec![s("uk"), i(1000), i(2)], vec![Value::Null, Value::Null, i(1)]]);
+    let by_home = rows(execute(&mut c, "SELECT home, COUNT(*) FROM people WHERE age > 0 GROUP BY home ORDER BY home LIMIT 2").unwrap());
+    assert!(by_home == vec![vec![Value::Null, i(1)], vec![s("leeds"), i(2)]]);
+
+    // Joins, by ID through the shared dictionary
+    let joined = rows(execute(&mut c, "SELECT people.name, cities.name, population FROM people JOIN cities ON home = cities.name WHERE age > 30 ORDER BY population DESC").unwrap());
+    assert!(joined == vec![vec![s("bob"), s("paris"), i(2100)], vec![s("ann"), s("leeds"), i(800)]]);
+    let joined = rows(execute(&mut c, "SELECT * FROM cities JOIN people ON cities.name = people.home WHERE country = 'uk' AND age < 30 LIMIT 5").unwrap());
+    assert!(joined == vec![vec![s("leeds"), s("uk"), i(800), s("dan"), s("leeds"), i(27)], vec![s("york"), s("uk"), i(200), s("cat"), s("york"), i(-2)]]);
+  }
+
+  #[test]
+  pub fn modify() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    setup(&mut c);
+
+    assert!(execute(&mut c, "UPDATE people SET age = 35, home = 'lyon' WHERE name = 'ann'").unwrap() == Output::Changed(1));
+    assert!(execute(&mut c, "UPDATE people SET home = NULL WHERE age < 30").unwrap() == Output::Changed(2));
+    assert!(rows(execute(&mut c, "SELECT name, home, age FROM people WHERE age BETWEEN -5 AND 35").unwrap())
+      == vec![vec![s("ann"), s("lyon"), i(35)], vec![s("cat"), Value::Null, i(-2)], vec![s("dan"), Value::Null, i(27)]]);
+
+    assert!(execute(&mut c, "DELETE FROM people WHERE home IS NULL").unwrap() == Output::Changed(4));
+    assert!(execute(&mut c, "DELETE FROM people WHERE age > 100").unwrap() == Output::Changed(0));
+    assert!(rows(execute(&mut c, "SELECT name, home FROM people").unwrap()) == vec![vec![s("ann"), s("lyon")], vec![s("bob"), s("paris")]]);
+    assert!(execute(&mut c, "INSERT INTO people VALUES ('fay', 'york', 9)").unwrap() == Output::Changed(1));
+    assert!(rows(execute(&mut c, "SELECT COUNT(*) FROM people").unwrap()) == vec![vec![i(3)]]);
+    assert!(execute(&mut c, "DELETE FROM people").unwrap() == Output::Changed(3));
+    assert!(rows(execute(&mut c, "SELECT name FROM people").unwrap()).is_empty());
+  }
+
+  #[test]
+  pub fn errors() {
+    let mut pp = MemoryPageProvider::new();
+    let mut c = Catalog::new(&mut pp);
+    setup(&mut c);
+
+    assert!(matches!(execute(&mut c, "SELECT name FROM"), Err(SqlError::Parse(_))));
+    assert!(matches!(execute(&mut c, "SELECT name FROM people WHERE name = 'ann"), Err(SqlError::Parse(_))));
+    assert!(matches!(execute(&mut c, "SELECT name FROM people extra"), Err(SqlError::Parse(_))));
+    assert!(matches!(execute(&mut c, "SELECT nope FROM people"), Err(SqlError::Catalog(CatalogError::NotFound(_)))));
+    assert!(matches!(execute(&mut c, "SELECT name FROM nowhere"), Err(SqlError::Catalog(CatalogError::NotFound(_)))));
+    assert!(matches!(execute(&mut c, "SELECT name, COUNT(*) FROM people"), Err(SqlError::Plan(_))));
+    assert!(matches!(execute(&mut c, "SELECT name FROM people JOIN cities ON home = cities.name"), Err(SqlError::Plan(_))));
+    assert!(matches!(execute(&mut c, "SELECT age FROM people WHERE age = 'old'"), Err(SqlError::Catalog(CatalogError::WrongType(_)))));
+    assert!(matches!(execute(&mut c, "INSERT INTO people VALUES ('gus', 3)"), Err(SqlError::Plan(_))));
+    assert!(matches!(execute(&mut c, "INSERT INTO people VALUES ('gus', 'york', 'old')"), Err(SqlError::Catalog(CatalogError::WrongType(_)))));
+    assert!(matches!(execute(&mut c, "CREATE TABLE people (a INT)"), Err(SqlError::Catalog(CatalogError::Exists(_)))));
+    // Nothing was written by the failed statements
+    assert!(rows(execute(&mut c, "SELECT COUNT(*) FROM people").unwrap()) == vec![vec![i(6)]]);
+  }
+}
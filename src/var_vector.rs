@@ -0,0 +1,143 @@
+// Variable length values over a set of pages
+// Two PagedVectors sharing one PageProvider
+//  offsets: end offset of each value in the heap, value i is heap[offsets[i-1]..offsets[i]]
+//  heap: the value bytes back to back, a value can span leaf pages
+// Values within a single leaf are returned without copying
+
+#![allow(dead_code)]
+
+use crate::database::{PageProvider};
+use crate::paged_vector::{self};
+use std::borrow::Cow;
+
+pub struct VarVector<'a> {
+  db: &'a mut dyn PageProvider,
+  offsets: u32,
+  heap: u32,
+}
+
+impl<'a> VarVector<'a> {
+  pub fn new(db: &'a mut dyn PageProvider) -> VarVector<'a> {
+    let offsets = paged_vector::bulk_load::<u64>(&[], db);
+    let heap = paged_vector::bulk_load::<u8>(&[], db);
+    VarVector { db, offsets, heap }
+  }
+
+  pub fn push(&mut self, v: &[u8]) {
+    let end = self.heap_len() + v.len() as u64;
+    self.heap = paged_vector::append_slice(self.heap, v, self.db);
+    self.offsets = paged_vector::append_slice(self.offsets, &[end], self.db);
+  }
+
+  pub fn push_str(&mut self, v: &str) {
+    self.push(v.as_bytes())
+  }
+
+  pub fn get(&self, i: usize) -> Cow<'_, [u8]> {
+    match self.try_get(i) {
+      Some(v) => v,
+      None => panic!("Index {} out of bounds for VarVector of length {}", i, self.len()),
+    }
+  }
+
+  pub fn try_get(&self, i: usize) -> Option<Cow<'_, [u8]>> {
+    let end = *paged_vector::get::<u64>(self.offsets, i, self.db)?;
+    let start = if i == 0 { 0 } else { *paged_vector::get::<u64>(self.offsets, i - 1, self.db)? };
+    paged_vector::read_range::<u8>(self.heap, start as usize..end as usize, self.db)
+  }
+
+  pub fn len(&self) -> usize {
+    paged_vector::len::<u64>(self.offsets, self.db)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn iter(&self) -> VarVectorIterator<'a, '_> {
+    VarVectorIterator {
+      vector: self,
+      index: 0,
+      end: self.len(),
+    }
+  }
+
+  fn heap_len(&self) -> u64 {
+    match self.len() {
+      0 => 0,
+      n => *paged_vector::get::<u64>(self.offsets, n - 1, self.db).unwrap(),
+    }
+  }
+}
+
+pub struct VarVectorIterator<'a, 'b> {
+  vector: &'b VarVector<'a>,
+  index: usize,
+  end: usize,
+}
+
+impl<'a, 'b> Iterator for VarVectorIterator<'a, 'b> {
+  type Item = Cow<'b, [u8]>;
+  fn next(&mut self) -> Option<Cow<'b, [u8]>> {
+    if self.index >= self.end {
+      return None;
+    }
+    let v = self.vector.try_get(self.index);
+    self.index += 1;
+    v
+  }
+}
+
+#[cfg(test)]
+pub mod tests {
+  #[allow(unused_imports)]
+  use super::*;
+  #[allow(unused_imports)]
+  use crate::database::{MemoryPageProvider};
+
+  #[test]
+  pub fn push_get() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = VarVector::new(&mut pp);
+
+    assert!(v.is_empty());
+    assert!(v.try_get(0).is_none());
+
+    v.push_str("Hello");
+    v.push_str("");
+    v.push(&[0, 1, 2]);
+
+    assert!(v.len() == 3);
+    assert!(&*v.get(0) == b"Hello");
+    assert!(v.get(1).is_empty());
+    assert!(*v.get(2) == [0, 1, 2]);
+    assert!(v.try_get(3).is_none());
+  }
+
+  #[test]
+  pub fn spans_pages() {
+    let mut pp = MemoryPageProvider::new();
+    let mut v = VarVector::new(&mut pp);
+
+    // Lengths chosen so values straddle leaves, including values bigger than a page
+    let values : Vec<Vec<u8>> = (0..2000usize)
+      .map(|i| (0..(i * 37) % 9000).map(|b| (b + i) as u8).collect())
+      .collect();
+    for value in values.iter() {
+      v.push(value);
+    }
+
+    assert!(v.len() == values.len());
+    let mut borrowed = 0;
+    for (i, value) in values.iter().enumerate() {
+      let got = v.get(i);
+      if let Cow::Borrowed(_) = got {
+        borrowed += 1;
+      }
+      assert!(*got == value[..]);
+    }
+    // Most small values live inside a single leaf
+    assert!(borrowed > 0);
+    assert!(v.iter().zip(values.iter()).all(|(a, b)| *a == b[..]));
+  }
+}